@@ -32,6 +32,15 @@ impl Colour {
         alpha: 255,
     };
 
+    /// Returns `self` with its alpha scaled by `opacity`, which should be between 0 and 1
+    #[must_use]
+    pub fn with_opacity(self, opacity: f64) -> Self {
+        Self {
+            alpha: (self.alpha as f64 * opacity.clamp(0.0, 1.0)).round() as u8,
+            ..self
+        }
+    }
+
     /// Serialises `self` as an u32 where each byte represents a component of `Colour`.
     #[must_use]
     pub const fn serialise_to_u32(self) -> u32 {
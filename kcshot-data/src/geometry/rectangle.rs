@@ -1,6 +1,6 @@
 use super::Point;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Rectangle {
     pub x: f64,
     pub y: f64,
@@ -41,4 +41,21 @@ impl Rectangle {
     pub fn area(&self) -> f64 {
         self.w * self.h
     }
+
+    /// Returns the smallest rectangle that contains both `self` and `other`
+    #[must_use]
+    pub fn union(&self, other: &Self) -> Self {
+        let a = self.normalised();
+        let b = other.normalised();
+
+        let x = a.x.min(b.x);
+        let y = a.y.min(b.y);
+
+        Self {
+            x,
+            y,
+            w: (a.x + a.w).max(b.x + b.w) - x,
+            h: (a.y + a.h).max(b.y + b.h) - y,
+        }
+    }
 }
@@ -7,7 +7,10 @@ pub mod settings;
 
 #[derive(Debug)]
 pub struct Text {
+    /// The text as Pango markup, which is what gets drawn
     pub string: String,
+    /// What the user typed, which `string` was made from, kept so that the text can be edited again
+    pub markdown: String,
     pub font_description: FontDescription,
     pub colour: Colour,
 }
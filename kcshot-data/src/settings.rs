@@ -1,6 +1,92 @@
-use gtk4::{gio, glib};
+use std::{borrow::Cow, collections::BTreeMap};
 
-use crate::colour::Colour;
+use gtk4::{
+    gio,
+    glib::{self, prelude::*},
+};
+
+use crate::{colour::Colour, geometry::Rectangle};
+
+/// Regions that can be captured without going through the editor, by name
+pub type RegionPresets = BTreeMap<String, Rectangle>;
+
+/// What an editor tool draws with, tools that don't make use of some of these ignore them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToolProperties {
+    pub line_width: f64,
+    /// Whether shapes get filled in with the primary colour
+    pub fill: bool,
+    /// Between 0 and 1, applied on top of the colours' own alpha
+    pub opacity: f64,
+    /// A tool-specific parameter, e.g. the radius of blurs or the size of pixelation blocks
+    pub strength: f64,
+    pub stroke_style: StrokeStyle,
+    pub arrow_head: ArrowHead,
+    /// Whether arrows have a head at their start too
+    pub double_headed: bool,
+}
+
+/// How the outlines of shapes are drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StrokeStyle {
+    #[default]
+    Solid,
+    Dashed,
+    Dotted,
+    /// Jittered, as if it was drawn by hand
+    Sketchy,
+}
+
+impl StrokeStyle {
+    pub const ALL: [Self; 4] = [Self::Solid, Self::Dashed, Self::Dotted, Self::Sketchy];
+
+    /// The name this style is stored under in settings
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Solid => "solid",
+            Self::Dashed => "dashed",
+            Self::Dotted => "dotted",
+            Self::Sketchy => "sketchy",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|style| style.name() == name)
+    }
+}
+
+/// What the ends of arrows look like
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ArrowHead {
+    /// Two lines meeting at the tip
+    #[default]
+    Open,
+    /// A filled in triangle
+    Filled,
+    None,
+    Dot,
+}
+
+impl ArrowHead {
+    pub const ALL: [Self; 4] = [Self::Open, Self::Filled, Self::None, Self::Dot];
+
+    /// The name this head is stored under in settings
+    const fn name(self) -> &'static str {
+        match self {
+            Self::Open => "open",
+            Self::Filled => "filled",
+            Self::None => "none",
+            Self::Dot => "dot",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|head| head.name() == name)
+    }
+}
+
+/// The properties each editor tool was last used with, by the tool's name
+pub type ToolPropertiesMap = BTreeMap<String, ToolProperties>;
 
 #[gsettings_macro::gen_settings(file = "./resources/kc.kcshot.gschema.xml", id = "kc.kcshot")]
 #[gen_settings_define(
@@ -13,6 +99,21 @@ use crate::colour::Colour;
     arg_type = "Colour",
     ret_type = "Colour"
 )]
+#[gen_settings_define(
+    key_name = "last-region",
+    arg_type = "Rectangle",
+    ret_type = "Rectangle"
+)]
+#[gen_settings_define(
+    key_name = "region-presets",
+    arg_type = "RegionPresets",
+    ret_type = "RegionPresets"
+)]
+#[gen_settings_define(
+    key_name = "tool-properties",
+    arg_type = "ToolPropertiesMap",
+    ret_type = "ToolPropertiesMap"
+)]
 pub struct Settings;
 
 impl Settings {
@@ -20,3 +121,74 @@ impl Settings {
         Self::default()
     }
 }
+
+// Regions are stored as their x, y, width and height
+impl StaticVariantType for Rectangle {
+    fn static_variant_type() -> Cow<'static, glib::VariantTy> {
+        <(f64, f64, f64, f64)>::static_variant_type()
+    }
+}
+
+impl FromVariant for Rectangle {
+    fn from_variant(variant: &glib::Variant) -> Option<Self> {
+        let (x, y, w, h) = variant.get()?;
+        Some(Self { x, y, w, h })
+    }
+}
+
+impl ToVariant for Rectangle {
+    fn to_variant(&self) -> glib::Variant {
+        (self.x, self.y, self.w, self.h).to_variant()
+    }
+}
+
+impl From<Rectangle> for glib::Variant {
+    fn from(value: Rectangle) -> Self {
+        value.to_variant()
+    }
+}
+
+// Tool properties are stored as their line width, fill, opacity, strength, the name of their
+// stroke style, the name of their arrow head and whether arrows are double-headed
+impl StaticVariantType for ToolProperties {
+    fn static_variant_type() -> Cow<'static, glib::VariantTy> {
+        <(f64, bool, f64, f64, String, String, bool)>::static_variant_type()
+    }
+}
+
+impl FromVariant for ToolProperties {
+    fn from_variant(variant: &glib::Variant) -> Option<Self> {
+        let (line_width, fill, opacity, strength, stroke_style, arrow_head, double_headed) =
+            variant.get::<(f64, bool, f64, f64, String, String, bool)>()?;
+        Some(Self {
+            line_width,
+            fill,
+            opacity,
+            strength,
+            stroke_style: StrokeStyle::from_name(&stroke_style).unwrap_or_default(),
+            arrow_head: ArrowHead::from_name(&arrow_head).unwrap_or_default(),
+            double_headed,
+        })
+    }
+}
+
+impl ToVariant for ToolProperties {
+    fn to_variant(&self) -> glib::Variant {
+        (
+            self.line_width,
+            self.fill,
+            self.opacity,
+            self.strength,
+            self.stroke_style.name().to_owned(),
+            self.arrow_head.name().to_owned(),
+            self.double_headed,
+        )
+            .to_variant()
+    }
+}
+
+impl From<ToolProperties> for glib::Variant {
+    fn from(value: ToolProperties) -> Self {
+        value.to_variant()
+    }
+}
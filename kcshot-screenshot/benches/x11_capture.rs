@@ -0,0 +1,65 @@
+//! Compares capturing the screen through MIT-SHM with capturing it through `GetImage`.
+//!
+//! This needs an X server and kcshot's GSettings schema, e.g. for a 4K screen:
+//! ```sh
+//! meson devenv -C builddir xvfb-run -s "-screen 0 3840x2160x24" \
+//!     cargo bench --manifest-path=../Cargo.toml -p kcshot-screenshot
+//! ```
+
+use std::time::{Duration, Instant};
+
+use kcshot_screenshot::{CaptureTarget, Recorder};
+
+const WARMUP_ITERATIONS: u32 = 3;
+const ITERATIONS: u32 = 30;
+
+fn main() {
+    let bounds = kcshot_screenshot::capture_bounds(&CaptureTarget::AllMonitors)
+        .expect("Failed to get the bounds of the screen, is an X server running?");
+    println!("Capturing {}x{} pixels", bounds.w, bounds.h);
+
+    for shm in [false, true] {
+        // SAFETY: The benchmark doesn't spawn any threads, so nothing else reads the environment
+        unsafe {
+            if shm {
+                std::env::remove_var("KCSHOT_DISABLE_SHM");
+            } else {
+                std::env::set_var("KCSHOT_DISABLE_SHM", "1");
+            }
+        }
+        let method = if shm { "MIT-SHM" } else { "GetImage" };
+
+        measure(&format!("take_screenshot ({method})"), || {
+            kcshot_screenshot::take_screenshot(None, &CaptureTarget::AllMonitors)
+                .expect("Failed to take screenshot");
+        });
+
+        let mut recorder = Recorder::new(bounds).expect("Failed to create recorder");
+        measure(&format!("Recorder::capture_frame ({method})"), || {
+            recorder.capture_frame().expect("Failed to capture frame");
+        });
+    }
+}
+
+fn measure(name: &str, mut f: impl FnMut()) {
+    for _ in 0..WARMUP_ITERATIONS {
+        f();
+    }
+
+    let mut times = Vec::with_capacity(ITERATIONS as usize);
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        f();
+        times.push(start.elapsed());
+    }
+
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / ITERATIONS;
+    let median = times[times.len() / 2];
+
+    println!(
+        "{name:<40} mean {mean:>10.2?}  median {median:>10.2?}  min {:>10.2?}  max {:>10.2?}",
+        times[0],
+        times[times.len() - 1],
+    );
+}
@@ -0,0 +1,151 @@
+//! The ways we know of getting screenshots (and the things that go with them) out of the system.
+//!
+//! The public functions of this crate forward to the [`Backend`] returned by [`get`], which is
+//! picked once, the first time it's needed.
+
+use std::{env, sync::OnceLock};
+
+use cairo::ImageSurface;
+use kcshot_data::geometry::Rectangle;
+
+use crate::{
+    CaptureTarget, Error, Monitor, Recorder, Result, Screenshot, Window, WmFeatures, replay,
+    wayland, xorg,
+};
+
+/// Setting this environment variable to a directory makes kcshot use the [`replay`] backend, which
+/// never talks to the display server.
+const REPLAY_DIR_VAR: &str = "KCSHOT_REPLAY_DIR";
+
+pub(crate) trait Backend: Send + Sync {
+    fn take_screenshot(
+        &self,
+        tokio: Option<&tokio::runtime::Handle>,
+        target: &CaptureTarget,
+    ) -> Result<Screenshot>;
+
+    fn get_windows(&self, target: &CaptureTarget) -> Result<Vec<Window>>;
+
+    fn monitors(&self) -> Result<Vec<Monitor>>;
+
+    fn capture_window(&self, _window_id: u32, _with_decorations: bool) -> Result<ImageSurface> {
+        Err(Error::WindowCaptureUnsupported)
+    }
+
+    fn capture_bounds(&self, _target: &CaptureTarget) -> Result<Rectangle> {
+        Err(Error::RecordingUnsupported)
+    }
+
+    fn recorder(&self, _region: Rectangle) -> Result<Recorder> {
+        Err(Error::RecordingUnsupported)
+    }
+
+    fn span_window_across_all_monitors(&self, _window_id: u32) -> Result<()> {
+        Ok(())
+    }
+
+    fn will_make_use_of_desktop_portals(&self) -> bool {
+        false
+    }
+}
+
+/// Returns the backend that should be used for this session
+pub(crate) fn get() -> Result<&'static dyn Backend> {
+    static BACKEND: OnceLock<Box<dyn Backend>> = OnceLock::new();
+
+    Ok(if let Some(backend) = BACKEND.get() {
+        backend.as_ref()
+    } else {
+        BACKEND
+            .set(select()?)
+            .unwrap_or_else(|_| panic!("BACKEND cannot be initialised at this point"));
+        BACKEND.get().unwrap().as_ref()
+    })
+}
+
+fn select() -> Result<Box<dyn Backend>> {
+    if let Some(dir) = env::var_os(REPLAY_DIR_VAR) {
+        tracing::info!("Replaying captures from {}", dir.to_string_lossy());
+        return Ok(Box::new(replay::ReplayBackend::new(dir.into())));
+    }
+
+    Ok(if WmFeatures::get()?.is_wayland() {
+        Box::new(WaylandBackend)
+    } else {
+        Box::new(XorgBackend)
+    })
+}
+
+struct XorgBackend;
+
+impl Backend for XorgBackend {
+    fn take_screenshot(
+        &self,
+        _tokio: Option<&tokio::runtime::Handle>,
+        target: &CaptureTarget,
+    ) -> Result<Screenshot> {
+        xorg::take_screenshot(target)
+    }
+
+    fn get_windows(&self, target: &CaptureTarget) -> Result<Vec<Window>> {
+        xorg::get_windows(target)
+    }
+
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        xorg::monitors()
+    }
+
+    fn capture_window(&self, window_id: u32, with_decorations: bool) -> Result<ImageSurface> {
+        xorg::capture_window(window_id, with_decorations)
+    }
+
+    fn capture_bounds(&self, target: &CaptureTarget) -> Result<Rectangle> {
+        xorg::capture_bounds(target)
+    }
+
+    fn recorder(&self, region: Rectangle) -> Result<Recorder> {
+        Ok(Recorder {
+            inner: xorg::Recorder::new(region)?,
+        })
+    }
+
+    fn span_window_across_all_monitors(&self, window_id: u32) -> Result<()> {
+        xorg::span_window_across_all_monitors(window_id)
+    }
+
+    fn will_make_use_of_desktop_portals(&self) -> bool {
+        WmFeatures::get().is_ok_and(|wm_features| wm_features.should_use_portals)
+    }
+}
+
+struct WaylandBackend;
+
+impl Backend for WaylandBackend {
+    fn take_screenshot(
+        &self,
+        tokio: Option<&tokio::runtime::Handle>,
+        target: &CaptureTarget,
+    ) -> Result<Screenshot> {
+        wayland::take_screenshot(tokio, target)
+    }
+
+    fn get_windows(&self, target: &CaptureTarget) -> Result<Vec<Window>> {
+        wayland::get_windows(target)
+    }
+
+    /// There is no way to pick which monitor gets captured without wlr-screencopy, so the list is
+    /// empty then
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        if WmFeatures::get()?.should_use_screencopy() {
+            wayland::monitors()
+        } else {
+            Ok(vec![])
+        }
+    }
+
+    /// Even when wlr-screencopy is available, capturing through it can still fail (e.g. because of
+    /// a buffer format we don't support), in which case we fall back to portals
+    fn will_make_use_of_desktop_portals(&self) -> bool {
+        true
+    }
+}
@@ -1,9 +1,16 @@
-use std::{env, io, sync::OnceLock};
+use std::{convert::Infallible, env, fmt, io, str::FromStr, sync::OnceLock, time::SystemTime};
 
-use cairo::{self, Error as CairoError, ImageSurface};
-use kcshot_data::geometry::Rectangle;
+use cairo::{self, Context, Error as CairoError, ImageSurface};
+use kcshot_data::{
+    geometry::{Point, Rectangle},
+    settings::Settings,
+};
 use tracing::error;
 
+use self::backend::Backend;
+
+mod backend;
+mod replay;
 mod wayland;
 mod xorg;
 
@@ -19,10 +26,24 @@ pub enum Error {
     FailedToTakeScreenshot,
     #[error("Failed to get windows")]
     FailedToGetWindows,
+    #[error("Capturing a single window is not supported on this display server")]
+    WindowCaptureUnsupported,
+    #[error("There is no monitor matching '{0}'")]
+    NoSuchMonitor(String),
+    #[error("No window has the input focus")]
+    NoActiveWindow,
+    #[error("The window that has the input focus is not on the screen")]
+    ActiveWindowOffScreen,
+    #[error("The region to capture is not on the screen")]
+    RegionOffScreen,
+    #[error("Recording the screen is not supported on this display server")]
+    RecordingUnsupported,
     #[error("Encountered an error interacting with the X server: {0}")]
     Xorg(#[from] xorg::Error),
     #[error("Encountered an error interacting with the Wayland stack: {0}")]
     Wayland(#[from] wayland::Error),
+    #[error("Encountered an error replaying a capture: {0}")]
+    Replay(#[from] replay::Error),
 }
 
 impl From<cairo::IoError> for Error {
@@ -34,25 +55,222 @@ impl From<cairo::IoError> for Error {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Window {
     /// This fields contains the rect of the window that also encompasses window decorations
     pub outer_rect: Rectangle,
     /// This fields contains the rect of the window that **only** encompasses the content
     pub content_rect: Rectangle,
+    /// The ID of the window, this can be passed to [`capture_window`]. This is only available on X11
+    pub id: Option<u32>,
+    /// The title of the window, as shown in its title bar
+    pub title: Option<String>,
+    /// Identifies the application the window belongs to, this is the class from `WM_CLASS` on X11
+    /// and the app ID on Wayland
+    pub class: Option<String>,
+    /// The ID of the process that owns the window
+    pub pid: Option<u32>,
+    /// The rects of the windows inside of this one, e.g. the panes of some older applications, in
+    /// stacking order. This is only available on X11
+    pub children: Vec<Rectangle>,
+}
+
+/// A screenshot, along with the things that were captured separately from it
+#[derive(Debug)]
+pub struct Screenshot {
+    pub image: ImageSurface,
+    /// The mouse cursor, if the user asked for it to be captured and we were able to get it on its
+    /// own. Otherwise it may already be part of [`Self::image`].
+    pub cursor: Option<Cursor>,
+    pub metadata: CaptureMetadata,
+}
+
+/// Describes where and when a screenshot was taken
+#[derive(Clone, Debug)]
+pub struct CaptureMetadata {
+    pub time: SystemTime,
+    /// The position and size of the screenshot inside the virtual screen
+    pub bounds: Rectangle,
+    /// The monitors that are part of the screenshot, this is empty if we don't know which monitors
+    /// were captured (e.g. when going through desktop portals)
+    pub monitors: Vec<Monitor>,
+    /// How many physical pixels make up a logical one, this is 1 when the display server doesn't
+    /// scale its contents or when we can't tell
+    pub scale: f64,
+    /// The window that had the input focus, in the same coordinate space as [`get_windows`]
+    pub focused_window: Option<Window>,
+    /// Where the cursor pointed, relative to the top left corner of the screenshot. This is `None`
+    /// if the cursor was outside of the screenshot or if its position is unknown.
+    pub cursor_position: Option<Point>,
+    /// The window the screenshot shows on its own, i.e. the one it was cropped to
+    pub window: Option<Window>,
+}
+
+impl CaptureMetadata {
+    /// Creates metadata for a screenshot of `bounds` that was just taken, about which nothing else is
+    /// known
+    pub fn new(bounds: Rectangle) -> Self {
+        Self {
+            time: SystemTime::now(),
+            bounds,
+            monitors: vec![],
+            scale: 1.0,
+            focused_window: None,
+            cursor_position: None,
+            window: None,
+        }
+    }
+
+    /// Returns the metadata for the part of the screenshot at `crop`, which is relative to the
+    /// screenshot
+    #[must_use]
+    pub fn cropped(self, crop: Rectangle) -> Self {
+        let bounds = Rectangle {
+            x: self.bounds.x + crop.x,
+            y: self.bounds.y + crop.y,
+            ..crop
+        };
+        let cursor_position = self
+            .cursor_position
+            .filter(|&position| crop.contains(position))
+            .map(|position| Point {
+                x: position.x - crop.x,
+                y: position.y - crop.y,
+            });
+
+        Self {
+            bounds,
+            monitors: monitors_within(self.monitors, bounds),
+            cursor_position,
+            ..self
+        }
+    }
+
+    /// Returns the names of the captured monitors separated by commas, or `None` if they're unknown
+    pub fn monitor_names(&self) -> Option<String> {
+        if self.monitors.is_empty() {
+            return None;
+        }
+
+        let names = self
+            .monitors
+            .iter()
+            .map(|monitor| monitor.name.as_str())
+            .collect::<Vec<_>>();
+
+        Some(names.join(","))
+    }
+
+    /// Returns the class of the application whose window the screenshot shows, if it shows a single
+    /// window
+    pub fn application(&self) -> Option<&str> {
+        self.window.as_ref()?.class.as_deref()
+    }
+}
+
+/// The image of the mouse cursor at the time a screenshot was taken
+#[derive(Debug)]
+pub struct Cursor {
+    /// The image of the cursor, this is always in [`cairo::Format::ARgb32`]
+    pub image: ImageSurface,
+    /// The position of the top left corner of [`Self::image`], relative to the top left corner of the
+    /// screenshot
+    pub position: Point,
+    /// The point of [`Self::image`] that the cursor points at
+    pub hotspot: Point,
+}
+
+/// Describes which part of the desktop a screenshot should cover
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CaptureTarget {
+    /// Only the monitor the cursor is on
+    #[default]
+    MonitorUnderCursor,
+    /// Every monitor, stitched together into a single image covering the whole virtual screen
+    AllMonitors,
+    /// A specific monitor, regardless of where the cursor is
+    Monitor(MonitorSelector),
+}
+
+impl CaptureTarget {
+    /// Returns the target the user asked for in their settings
+    pub fn from_settings() -> Self {
+        if Settings::open().capture_all_monitors() {
+            Self::AllMonitors
+        } else {
+            Self::MonitorUnderCursor
+        }
+    }
+}
+
+/// Picks a monitor out of the ones returned by [`monitors`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MonitorSelector {
+    /// The name of the monitor, e.g. "DP-2"
+    Name(String),
+    /// The (0-based) index of the monitor in the list returned by [`monitors`]
+    Index(usize),
+}
+
+impl MonitorSelector {
+    pub fn select<'m>(&self, monitors: &'m [Monitor]) -> Option<&'m Monitor> {
+        match self {
+            Self::Name(name) => monitors.iter().find(|monitor| &monitor.name == name),
+            Self::Index(idx) => monitors.get(*idx),
+        }
+    }
+}
+
+impl FromStr for MonitorSelector {
+    type Err = Infallible;
+
+    /// Strings that are valid numbers are treated as indices, anything else is a monitor name
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        Ok(match s.parse() {
+            Ok(idx) => Self::Index(idx),
+            Err(_) => Self::Name(s.to_owned()),
+        })
+    }
+}
+
+impl fmt::Display for MonitorSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Index(idx) => write!(f, "{idx}"),
+        }
+    }
+}
+
+/// A monitor as reported by the display server
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    /// The name of the monitor, this is usually the name of the output it's plugged into, e.g. "DP-2"
+    pub name: String,
+    /// The position and size of the monitor inside the virtual screen
+    pub rect: Rectangle,
+    pub is_primary: bool,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 enum DisplayServerKind {
-    X11 { can_retrieve_windows: bool },
+    /// `supports_client_list` is whether the WM maintains _NET_CLIENT_LIST_STACKING, without it we
+    /// have to find the top-level windows ourselves
+    X11 {
+        supports_client_list: bool,
+    },
     GenericWayland,
     Hyprland,
+    Sway,
 }
 
 #[derive(Clone, Copy, Debug)]
 struct WmFeatures {
     display_server_kind: DisplayServerKind,
     should_use_portals: bool,
+    /// Whether the compositor lets us copy the contents of outputs through the wlr-screencopy
+    /// protocol, in which case we don't need to go through portals to take screenshots
+    can_use_screencopy: bool,
 }
 
 impl WmFeatures {
@@ -90,49 +308,243 @@ impl WmFeatures {
         Ok(wm_features)
     }
 
-    fn can_retrieve_windows(self) -> bool {
-        use DisplayServerKind::*;
-        matches!(
-            self.display_server_kind,
-            X11 {
-                can_retrieve_windows: true,
-            } | Hyprland
-        )
-    }
-
     fn is_wayland(self) -> bool {
         !matches!(self.display_server_kind, DisplayServerKind::X11 { .. })
     }
-}
 
-pub fn take_screenshot(tokio: Option<&tokio::runtime::Handle>) -> Result<ImageSurface> {
-    if WmFeatures::get()?.is_wayland() {
-        wayland::take_screenshot(tokio)
-    } else {
-        xorg::take_screenshot()
+    fn should_use_screencopy(self) -> bool {
+        self.is_wayland() && self.can_use_screencopy && !self.should_use_portals
     }
 }
 
+/// Takes a screenshot of the area described by `target`.
+///
+/// On Wayland `target` is only taken into account if the compositor supports wlr-screencopy, and
+/// even then the cursor position is unknown, so [`CaptureTarget::MonitorUnderCursor`] captures every
+/// monitor. Otherwise the portal decides what gets captured.
+///
+/// The cursor is only returned separately on X11, Wayland compositors draw it onto the screenshot
+/// themselves.
+///
+/// If `$KCSHOT_REPLAY_DIR` is set, the screenshot is read from that directory instead, see the
+/// [`replay`] module.
+pub fn take_screenshot(
+    tokio: Option<&tokio::runtime::Handle>,
+    target: &CaptureTarget,
+) -> Result<Screenshot> {
+    backend::get()?.take_screenshot(tokio, target)
+}
+
 /// Obtains a list of all windows from the display server, the list is in stacking order.
-pub fn get_windows() -> Result<Vec<Window>> {
-    if WmFeatures::get()?.is_wayland() {
-        wayland::get_windows()
+///
+/// The window rects are relative to the top left corner of the area captured for `target`.
+pub fn get_windows(target: &CaptureTarget) -> Result<Vec<Window>> {
+    backend::get()?.get_windows(target)
+}
+
+/// Captures the contents of the window with the given ID, including the parts of it that are covered
+/// by other windows. Window IDs are obtained from [`Window::id`].
+///
+/// The returned image has an alpha channel, so shaped windows and windows with rounded corners keep
+/// their transparency.
+///
+/// This is only supported on X11 servers that have the Composite extension.
+pub fn capture_window(window_id: u32, with_decorations: bool) -> Result<ImageSurface> {
+    backend::get()?.capture_window(window_id, with_decorations)
+}
+
+/// Takes a screenshot of the window that has the input focus, cropped to its outer rect (which
+/// includes its decorations) or to its content rect.
+///
+/// Unlike [`capture_window`], the parts of the window that are covered by other windows or that are
+/// off-screen can't be captured. If the cursor was captured, it's drawn on top of the image.
+pub fn capture_active_window(
+    tokio: Option<&tokio::runtime::Handle>,
+    with_decorations: bool,
+) -> Result<Screenshot> {
+    let screenshot = take_screenshot(tokio, &CaptureTarget::AllMonitors)?;
+
+    let window = screenshot
+        .metadata
+        .focused_window
+        .clone()
+        .ok_or(Error::NoActiveWindow)?;
+    let rect = if with_decorations {
+        window.outer_rect
     } else {
-        xorg::get_windows()
-    }
+        window.content_rect
+    };
+
+    crop_screenshot(screenshot, rect, Some(window))?.ok_or(Error::ActiveWindowOffScreen)
 }
 
-pub fn will_make_use_of_desktop_portals() -> bool {
-    let Ok(wm_features) = WmFeatures::get() else {
-        return false;
+/// Takes a screenshot of `region`, which is given in the coordinate space of the virtual screen
+/// (see [`capture_bounds`]), e.g. of a region the user cropped a screenshot to before.
+///
+/// Like with [`capture_active_window`], only the parts of the region that are on the screen can be
+/// captured, and the cursor is drawn on top of the image if it was captured.
+pub fn capture_region(
+    tokio: Option<&tokio::runtime::Handle>,
+    region: Rectangle,
+) -> Result<Screenshot> {
+    let screenshot = take_screenshot(tokio, &CaptureTarget::AllMonitors)?;
+
+    let bounds = screenshot.metadata.bounds;
+    let rect = Rectangle {
+        x: region.x - bounds.x,
+        y: region.y - bounds.y,
+        ..region
+    };
+
+    crop_screenshot(screenshot, rect, None)?.ok_or(Error::RegionOffScreen)
+}
+
+/// Crops `screenshot` to `rect`, which is relative to it, and draws the cursor on top of it.
+///
+/// Returns `None` if no part of `rect` is inside of the screenshot.
+fn crop_screenshot(
+    Screenshot {
+        image,
+        cursor,
+        metadata,
+    }: Screenshot,
+    rect: Rectangle,
+    window: Option<Window>,
+) -> Result<Option<Screenshot>> {
+    // Regions may be partially off-screen, but we can only capture what's on the screen
+    let left = rect.x.max(0.0).floor();
+    let top = rect.y.max(0.0).floor();
+    let right = (rect.x + rect.w).min(image.width() as f64).ceil();
+    let bottom = (rect.y + rect.h).min(image.height() as f64).ceil();
+    if right <= left || bottom <= top {
+        return Ok(None);
+    }
+
+    let crop = Rectangle {
+        x: left,
+        y: top,
+        w: right - left,
+        h: bottom - top,
     };
 
-    if wm_features.should_use_portals {
-        return true;
+    let cropped = ImageSurface::create(image.format(), crop.w as i32, crop.h as i32)?;
+    let cairo = Context::new(&cropped)?;
+    cairo.set_source_surface(&image, -crop.x, -crop.y)?;
+    cairo.paint()?;
+    if let Some(cursor) = &cursor {
+        cairo.set_source_surface(
+            &cursor.image,
+            cursor.position.x - crop.x,
+            cursor.position.y - crop.y,
+        )?;
+        cairo.paint()?;
     }
+    drop(cairo);
+
+    Ok(Some(Screenshot {
+        image: cropped,
+        cursor: None,
+        metadata: CaptureMetadata {
+            window,
+            ..metadata.cropped(crop)
+        },
+    }))
+}
+
+/// Obtains the list of monitors from the display server.
+///
+/// On Wayland this is only available when the compositor supports wlr-screencopy, as otherwise
+/// there is no way to pick which monitor gets captured anyway, so the list is empty.
+pub fn monitors() -> Result<Vec<Monitor>> {
+    backend::get()?.monitors()
+}
+
+/// Returns the position and size of the area that [`take_screenshot`] would capture for `target`
+/// inside the virtual screen, this can be used to make rects relative to a screenshot absolute.
+///
+/// This is only supported on X11.
+pub fn capture_bounds(target: &CaptureTarget) -> Result<Rectangle> {
+    backend::get()?.capture_bounds(target)
+}
+
+/// A single frame captured by a [`Recorder`]
+#[derive(Clone, Debug)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    /// The pixels of the frame in the layout of [`cairo::Format::Rgb24`], without any padding at
+    /// the end of rows
+    pub data: Vec<u8>,
+}
+
+/// Repeatedly captures the same area of the screen, keeping the connection to the display server
+/// around between frames so that it can keep up with recording the screen.
+pub struct Recorder {
+    inner: xorg::Recorder,
+}
+
+impl Recorder {
+    /// Creates a recorder for `region`, which is given in the coordinate space of the virtual screen
+    /// (see [`capture_bounds`]).
+    ///
+    /// This is only supported on X11.
+    pub fn new(region: Rectangle) -> Result<Self> {
+        backend::get()?.recorder(region)
+    }
+
+    /// Makes the recorder leave the cursor out of the frames it captures, regardless of the
+    /// settings
+    pub fn without_cursor(mut self) -> Self {
+        self.inner.disable_cursor();
+        self
+    }
+
+    /// Captures what is currently shown inside the region, the cursor is drawn on top of it if the
+    /// user asked for it in their settings
+    pub fn capture_frame(&mut self) -> Result<Frame> {
+        self.inner.capture_frame()
+    }
+
+    /// Moves the cursor to the middle of the region and scrolls down by one step, as if the user
+    /// turned their mouse wheel.
+    ///
+    /// This requires the X server to support the XTEST extension.
+    pub fn scroll_down(&self) -> Result<()> {
+        self.inner.scroll_down()
+    }
+}
+
+/// Makes the fullscreen X11 window with the given ID span every monitor, this is needed to show
+/// screenshots taken with [`CaptureTarget::AllMonitors`] in their entirety.
+///
+/// This does nothing on Wayland.
+pub fn span_window_across_all_monitors(window_id: u32) -> Result<()> {
+    backend::get()?.span_window_across_all_monitors(window_id)
+}
+
+pub fn will_make_use_of_desktop_portals() -> bool {
+    backend::get().is_ok_and(Backend::will_make_use_of_desktop_portals)
+}
+
+/// Returns the monitors that are at least partially inside of `bounds`
+fn monitors_within(monitors: Vec<Monitor>, bounds: Rectangle) -> Vec<Monitor> {
+    monitors
+        .into_iter()
+        .filter(|monitor| {
+            let Rectangle { x, y, w, h } = monitor.rect;
+            x < bounds.x + bounds.w
+                && bounds.x < x + w
+                && y < bounds.y + bounds.h
+                && bounds.y < y + h
+        })
+        .collect()
+}
 
-    matches!(
-        wm_features.display_server_kind,
-        DisplayServerKind::GenericWayland | DisplayServerKind::Hyprland,
-    )
+/// Returns the smallest rectangle that contains all the passed in monitors
+fn bounds_of(monitors: &[Rectangle]) -> Rectangle {
+    monitors
+        .iter()
+        .copied()
+        .reduce(|acc, monitor| acc.union(&monitor))
+        .expect("There should be at least one monitor to take a screenshot of")
 }
@@ -0,0 +1,333 @@
+//! A backend which "captures" screenshots that were saved to disk beforehand, so that everything
+//! past taking the screenshot can be exercised without a display server, e.g. in CI.
+//!
+//! The directory it reads from holds:
+//! * `screenshot.png`, which is returned by every capture
+//! * `windows.json` (optional), a list of windows in stacking order, in the following format:
+//! ```json
+//! [
+//!     {
+//!         "outer_rect": { "x": 0, "y": 0, "w": 960, "h": 1080 },
+//!         "content_rect": { "x": 2, "y": 28, "w": 956, "h": 1050 },
+//!         "id": null,
+//!         "title": "kcshot",
+//!         "class": "kcshot",
+//!         "pid": 1234,
+//!         "children": [{ "x": 2, "y": 28, "w": 478, "h": 1050 }]
+//!     }
+//! ]
+//! ```
+//! where `id`, `title`, `class`, `pid` and `children` may be left out.
+
+use std::{fs::File, path::PathBuf};
+
+use cairo::{Context, Format as CairoImageFormat, ImageSurface};
+use kcshot_data::geometry::Rectangle;
+use serde::Deserialize;
+
+use crate::{
+    CaptureMetadata, CaptureTarget, Monitor, Result, Screenshot, Window, backend::Backend,
+};
+
+const SCREENSHOT_FILE: &str = "screenshot.png";
+const WINDOWS_FILE: &str = "windows.json";
+/// The replayed screenshot acts as if it was taken on a single monitor with this name
+const MONITOR_NAME: &str = "REPLAY-1";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to read the replayed screenshot from {path}: {error}")]
+    Screenshot { error: cairo::IoError, path: String },
+    #[error("Failed to read the replayed windows from {path}: {error}")]
+    Windows {
+        error: serde_json::Error,
+        path: String,
+    },
+}
+
+pub(crate) struct ReplayBackend {
+    dir: PathBuf,
+}
+
+impl ReplayBackend {
+    pub(crate) fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+
+    fn load_screenshot(&self) -> Result<ImageSurface> {
+        let path = self.dir.join(SCREENSHOT_FILE);
+        let to_error = |error| Error::Screenshot {
+            error,
+            path: path.display().to_string(),
+        };
+
+        let png = File::open(&path)
+            .map_err(cairo::IoError::Io)
+            .and_then(|mut file| ImageSurface::create_from_png(&mut file))
+            .map_err(to_error)?;
+
+        // Real captures never have an alpha channel, so neither should replayed ones
+        let screenshot = ImageSurface::create(CairoImageFormat::Rgb24, png.width(), png.height())?;
+        let cairo = Context::new(&screenshot)?;
+        cairo.set_source_surface(&png, 0.0, 0.0)?;
+        cairo.paint()?;
+        drop(cairo);
+
+        Ok(screenshot)
+    }
+
+    fn bounds(&self) -> Result<Rectangle> {
+        let screenshot = self.load_screenshot()?;
+
+        Ok(Rectangle {
+            x: 0.0,
+            y: 0.0,
+            w: screenshot.width() as f64,
+            h: screenshot.height() as f64,
+        })
+    }
+}
+
+impl Backend for ReplayBackend {
+    fn take_screenshot(
+        &self,
+        _tokio: Option<&tokio::runtime::Handle>,
+        target: &CaptureTarget,
+    ) -> Result<Screenshot> {
+        if let CaptureTarget::Monitor(selector) = target
+            && selector.select(&self.monitors()?).is_none()
+        {
+            return Err(crate::Error::NoSuchMonitor(selector.to_string()));
+        }
+
+        let image = self.load_screenshot()?;
+        let bounds = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            w: image.width() as f64,
+            h: image.height() as f64,
+        };
+
+        Ok(Screenshot {
+            image,
+            cursor: None,
+            metadata: CaptureMetadata {
+                monitors: self.monitors()?,
+                ..CaptureMetadata::new(bounds)
+            },
+        })
+    }
+
+    fn get_windows(&self, _target: &CaptureTarget) -> Result<Vec<Window>> {
+        let path = self.dir.join(WINDOWS_FILE);
+
+        match std::fs::read(&path) {
+            Ok(windows) => windows_from_json(&windows).map_err(|error| {
+                Error::Windows {
+                    error,
+                    path: path.display().to_string(),
+                }
+                .into()
+            }),
+            Err(why) if why.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+            Err(why) => Err(why.into()),
+        }
+    }
+
+    fn monitors(&self) -> Result<Vec<Monitor>> {
+        Ok(vec![Monitor {
+            name: MONITOR_NAME.into(),
+            rect: self.bounds()?,
+            is_primary: true,
+        }])
+    }
+
+    fn capture_bounds(&self, _target: &CaptureTarget) -> Result<Rectangle> {
+        self.bounds()
+    }
+}
+
+#[derive(Deserialize)]
+struct ReplayWindow {
+    outer_rect: ReplayRect,
+    content_rect: ReplayRect,
+    #[serde(default)]
+    id: Option<u32>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    class: Option<String>,
+    #[serde(default)]
+    pid: Option<u32>,
+    #[serde(default)]
+    children: Vec<ReplayRect>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct ReplayRect {
+    x: f64,
+    y: f64,
+    w: f64,
+    h: f64,
+}
+
+impl From<ReplayRect> for Rectangle {
+    fn from(ReplayRect { x, y, w, h }: ReplayRect) -> Self {
+        Self { x, y, w, h }
+    }
+}
+
+fn windows_from_json(json: &[u8]) -> serde_json::Result<Vec<Window>> {
+    let windows: Vec<ReplayWindow> = serde_json::from_slice(json)?;
+
+    Ok(windows
+        .into_iter()
+        .map(|window| Window {
+            outer_rect: window.outer_rect.into(),
+            content_rect: window.content_rect.into(),
+            id: window.id,
+            title: window.title,
+            class: window.class,
+            pid: window.pid,
+            children: window.children.into_iter().map(Rectangle::from).collect(),
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use cairo::{Context, Format, ImageSurface};
+
+    use super::{ReplayBackend, windows_from_json};
+    use crate::{CaptureTarget, MonitorSelector, backend::Backend};
+
+    /// A replay directory that gets removed once the test using it is over
+    struct ReplayDir(PathBuf);
+
+    impl ReplayDir {
+        fn backend(&self) -> ReplayBackend {
+            ReplayBackend::new(self.0.clone())
+        }
+    }
+
+    impl Drop for ReplayDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Creates a directory holding a 4x2 screenshot that is red on the left and blue on the right
+    fn replay_dir(name: &str, windows: Option<&str>) -> ReplayDir {
+        let dir = std::env::temp_dir().join(format!("kcshot-replay-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let dir = ReplayDir(dir);
+
+        let png = ImageSurface::create(Format::ARgb32, 4, 2).unwrap();
+        let cairo = Context::new(&png).unwrap();
+        cairo.set_source_rgb(1.0, 0.0, 0.0);
+        cairo.rectangle(0.0, 0.0, 2.0, 2.0);
+        cairo.fill().unwrap();
+        cairo.set_source_rgb(0.0, 0.0, 1.0);
+        cairo.rectangle(2.0, 0.0, 2.0, 2.0);
+        cairo.fill().unwrap();
+        drop(cairo);
+
+        let mut file = std::fs::File::create(dir.0.join(super::SCREENSHOT_FILE)).unwrap();
+        png.write_to_png(&mut file).unwrap();
+
+        if let Some(windows) = windows {
+            std::fs::write(dir.0.join(super::WINDOWS_FILE), windows).unwrap();
+        }
+
+        dir
+    }
+
+    #[test]
+    fn screenshot_is_loaded_without_alpha() {
+        let dir = replay_dir("screenshot", None);
+        let backend = dir.backend();
+
+        let screenshot = backend
+            .take_screenshot(None, &CaptureTarget::AllMonitors)
+            .unwrap();
+        let mut image = screenshot.image;
+
+        assert_eq!(image.format(), Format::Rgb24);
+        assert_eq!((image.width(), image.height()), (4, 2));
+        assert!(screenshot.cursor.is_none());
+
+        let stride = image.stride() as usize;
+        let data = image.data().unwrap();
+        // Pixels are stored as native-endian BGRX
+        assert_eq!(&data[..3], &[0, 0, 255]);
+        assert_eq!(&data[stride - 4..stride - 1], &[255, 0, 0]);
+    }
+
+    #[test]
+    fn single_monitor() {
+        let dir = replay_dir("monitors", None);
+        let backend = dir.backend();
+
+        let monitors = backend.monitors().unwrap();
+        assert_eq!(monitors.len(), 1);
+        assert_eq!((monitors[0].rect.w, monitors[0].rect.h), (4.0, 2.0));
+
+        let unknown = CaptureTarget::Monitor(MonitorSelector::Index(1));
+        assert!(backend.take_screenshot(None, &unknown).is_err());
+    }
+
+    #[test]
+    fn missing_windows_file() {
+        let dir = replay_dir("no-windows", None);
+        let backend = dir.backend();
+
+        assert!(
+            backend
+                .get_windows(&CaptureTarget::AllMonitors)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn windows() {
+        let windows = r#"[
+            {
+                "outer_rect": { "x": 0, "y": 0, "w": 3, "h": 2 },
+                "content_rect": { "x": 1, "y": 1, "w": 2, "h": 1 },
+                "id": 4194307,
+                "title": "Terminal",
+                "class": "XTerm",
+                "pid": 1234,
+                "children": [{ "x": 1, "y": 1, "w": 1, "h": 1 }]
+            },
+            {
+                "outer_rect": { "x": 2, "y": 0, "w": 2, "h": 2 },
+                "content_rect": { "x": 2, "y": 0, "w": 2, "h": 2 }
+            }
+        ]"#;
+        let dir = replay_dir("windows", Some(windows));
+        let backend = dir.backend();
+
+        let windows = backend.get_windows(&CaptureTarget::AllMonitors).unwrap();
+
+        assert_eq!(windows.len(), 2);
+        assert_eq!(windows[0].id, Some(0x400003));
+        assert_eq!(windows[0].content_rect.x, 1.0);
+        assert_eq!(windows[0].title.as_deref(), Some("Terminal"));
+        assert_eq!(windows[0].class.as_deref(), Some("XTerm"));
+        assert_eq!(windows[0].pid, Some(1234));
+        assert_eq!(windows[0].children.len(), 1);
+        assert_eq!(windows[1].id, None);
+        assert_eq!(windows[1].title, None);
+        assert!(windows[1].children.is_empty());
+        assert_eq!(windows[1].outer_rect.w, 2.0);
+    }
+
+    #[test]
+    fn malformed_windows() {
+        assert!(windows_from_json(b"[{ \"id\": 1 }]").is_err());
+    }
+}
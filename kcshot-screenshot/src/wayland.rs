@@ -10,9 +10,13 @@ use gtk4::{
 };
 use kcshot_data::geometry::Rectangle;
 
-use super::{Result, Window, WmFeatures};
+use super::{CaptureMetadata, CaptureTarget, Monitor, Result, Screenshot, Window, WmFeatures};
 use crate::DisplayServerKind;
 
+mod hyprland;
+mod screencopy;
+mod sway;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
     #[error("Encountered a desktop portal error: {0}")]
@@ -24,15 +28,36 @@ pub enum Error {
         error: serde_json::Error,
         command: String,
     },
+    #[error("$SWAYSOCK is not set, so sway's IPC socket can't be found")]
+    SwaySocketNotSet,
+    #[error("Received a malformed reply from sway's IPC socket")]
+    MalformedSwayReply,
+    #[error("Failed to connect to the Wayland compositor: {0}")]
+    Connect(#[from] wayland_client::ConnectError),
+    #[error("Failed to retrieve the globals advertised by the compositor: {0}")]
+    Globals(#[from] wayland_client::globals::GlobalError),
+    #[error("Failed to bind a global advertised by the compositor: {0}")]
+    Bind(#[from] wayland_client::globals::BindError),
+    #[error("Failed to dispatch Wayland events: {0}")]
+    Dispatch(#[from] wayland_client::DispatchError),
+    #[error("The compositor failed to copy the contents of output '{0}'")]
+    ScreencopyFailed(String),
+    #[error("The compositor offered a buffer format we can't handle: {0}")]
+    UnsupportedBufferFormat(String),
 }
 
 pub(super) fn get_wm_features() -> Result<WmFeatures> {
     let xdg_current_desktop = std::env::var("XDG_CURRENT_DESKTOP");
+    // Not every sway setup sets $XDG_CURRENT_DESKTOP, but sway always tells its children where its
+    // IPC socket is
+    let is_sway = std::env::var_os("SWAYSOCK").is_some();
 
     let display_server_kind = match xdg_current_desktop {
         Ok(xdg_current_desktop) => {
             if xdg_current_desktop.eq_ignore_ascii_case("hyprland") {
                 DisplayServerKind::Hyprland
+            } else if xdg_current_desktop.eq_ignore_ascii_case("sway") || is_sway {
+                DisplayServerKind::Sway
             } else {
                 tracing::warn!(
                     "Unknown Wayland compositor ('{xdg_current_desktop}'), assuming a generic Wayland setup."
@@ -40,6 +65,7 @@ pub(super) fn get_wm_features() -> Result<WmFeatures> {
                 DisplayServerKind::GenericWayland
             }
         }
+        Err(_) if is_sway => DisplayServerKind::Sway,
         Err(why) => {
             tracing::warn!(
                 "Failed to retrieve $XDG_CURRENT_DESKTOP, assuming a generic Wayland setup: {why}"
@@ -48,15 +74,98 @@ pub(super) fn get_wm_features() -> Result<WmFeatures> {
         }
     };
 
+    let can_use_screencopy = screencopy::is_supported();
+    if !can_use_screencopy {
+        tracing::info!(
+            "Your compositor does not support wlr-screencopy, so screenshots will be taken through desktop portals"
+        );
+    }
+
     let wm_features = WmFeatures {
         display_server_kind,
         should_use_portals: false,
+        can_use_screencopy,
     };
 
     Ok(wm_features)
 }
 
-pub(super) fn take_screenshot(tokio: Option<&tokio::runtime::Handle>) -> Result<ImageSurface> {
+/// The cursor is never returned separately, as compositors draw it onto the screenshot themselves.
+pub(super) fn take_screenshot(
+    tokio: Option<&tokio::runtime::Handle>,
+    target: &CaptureTarget,
+) -> Result<Screenshot> {
+    let mut screenshot = take_screenshot_without_focused_window(tokio, target)?;
+
+    let CaptureMetadata { bounds, scale, .. } = screenshot.metadata;
+    match focused_window() {
+        Ok(focused_window) => {
+            screenshot.metadata.focused_window =
+                focused_window.map(|window| place_in_capture(window, bounds, scale));
+        }
+        Err(why) => tracing::info!("Failed to retrieve the focused window: {why}"),
+    }
+
+    Ok(screenshot)
+}
+
+/// Compositors give us windows in logical pixels of their global space, but like on X11 they have
+/// to be in the pixels of the screenshot of `bounds`, relative to it
+fn place_in_capture(window: Window, bounds: Rectangle, scale: f64) -> Window {
+    let place = |rect: Rectangle| Rectangle {
+        x: rect.x * scale - bounds.x,
+        y: rect.y * scale - bounds.y,
+        w: rect.w * scale,
+        h: rect.h * scale,
+    };
+
+    Window {
+        outer_rect: place(window.outer_rect),
+        content_rect: place(window.content_rect),
+        children: window.children.iter().copied().map(place).collect(),
+        ..window
+    }
+}
+
+fn take_screenshot_without_focused_window(
+    tokio: Option<&tokio::runtime::Handle>,
+    target: &CaptureTarget,
+) -> Result<Screenshot> {
+    if WmFeatures::get()?.should_use_screencopy() {
+        match screencopy::take_screenshot(target) {
+            Ok(screenshot) => return Ok(screenshot),
+            // Portals need a tokio runtime, which callers are expected to provide on Wayland, see
+            // `will_make_use_of_desktop_portals`
+            Err(why) if tokio.is_some() => {
+                tracing::warn!(
+                    "Failed to take screenshot through wlr-screencopy, falling back to portals: {why}"
+                );
+            }
+            Err(why) => return Err(why),
+        }
+    }
+
+    let image = take_screenshot_with_portals(tokio)?;
+    // The portal doesn't tell us what it captured, so we only know how big it was
+    let bounds = Rectangle {
+        x: 0.0,
+        y: 0.0,
+        w: image.width() as f64,
+        h: image.height() as f64,
+    };
+
+    Ok(Screenshot {
+        image,
+        cursor: None,
+        metadata: CaptureMetadata::new(bounds),
+    })
+}
+
+pub(super) fn monitors() -> Result<Vec<Monitor>> {
+    screencopy::monitors()
+}
+
+fn take_screenshot_with_portals(tokio: Option<&tokio::runtime::Handle>) -> Result<ImageSurface> {
     let uri = tokio
         .expect("kcshot is attempting to use portals but there is no tokio runtime running")
         .block_on(async {
@@ -93,88 +202,40 @@ pub(super) fn take_screenshot(tokio: Option<&tokio::runtime::Handle>) -> Result<
     Ok(screenshot?)
 }
 
-pub(super) fn get_windows() -> Result<Vec<Window>> {
+pub(super) fn get_windows(target: &CaptureTarget) -> Result<Vec<Window>> {
     let wm_features = WmFeatures::get()?;
 
-    if wm_features.display_server_kind == DisplayServerKind::Hyprland {
-        get_windows_hyprland()
-    } else {
-        Ok(vec![])
-    }
-}
-
-fn get_windows_hyprland() -> Result<Vec<Window>> {
-    use serde::{Deserialize, de::DeserializeOwned};
-
-    #[derive(Deserialize)]
-    struct HyprWindow {
-        at: [f64; 2],
-        size: [f64; 2],
-        workspace: HyprWorkspace,
-        monitor: i32,
-    }
-
-    #[derive(Deserialize)]
-    struct HyprWorkspace {
-        id: i32,
-    }
-
-    #[derive(Deserialize)]
-    struct HyprBorderSize {
-        int: i32,
-    }
-
-    fn spawn_and_parse_output<O: DeserializeOwned>(command_str: &str) -> Result<O> {
-        let mut argv = command_str.split_ascii_whitespace();
-        let mut command = std::process::Command::new(argv.next().unwrap());
-
-        for arg in argv {
-            command.arg(arg);
-        }
-
-        let output = command.output()?;
+    let windows = match wm_features.display_server_kind {
+        DisplayServerKind::Hyprland => hyprland::get_windows()?,
+        DisplayServerKind::Sway => sway::get_windows()?,
+        _ => return Ok(vec![]),
+    };
 
-        Ok(
-            serde_json::from_slice(&output.stdout).map_err(|error| Error::Deserialize {
-                error,
-                command: command_str.into(),
-            })?,
-        )
-    }
+    // Screenshots taken through portals cover everything, and we can't tell how they're scaled
+    let (bounds, scale) = if wm_features.should_use_screencopy() {
+        screencopy::capture_area(target)?
+    } else {
+        let origin = Rectangle {
+            x: 0.0,
+            y: 0.0,
+            w: 0.0,
+            h: 0.0,
+        };
+        (origin, 1.0)
+    };
 
-    let border_size =
-        spawn_and_parse_output::<HyprBorderSize>("hyprctl -j getoption general:border_size")?.int
-            as f64;
-    let active_window = spawn_and_parse_output::<HyprWindow>("hyprctl -j activewindow")?;
-    let hypr_windows: Vec<_> = spawn_and_parse_output::<Vec<HyprWindow>>("hyprctl -j clients")?
+    Ok(windows
         .into_iter()
-        .filter(|win| {
-            win.workspace.id == active_window.workspace.id && win.monitor == active_window.monitor
-        })
-        .collect();
-
-    let mut windows = Vec::with_capacity(hypr_windows.len());
-
-    for window in hypr_windows {
-        let outer_rect = Rectangle {
-            x: window.at[0] - border_size,
-            y: window.at[1] - border_size,
-            w: window.size[0] + 2.0 * border_size,
-            h: window.size[1] + 2.0 * border_size,
-        };
+        .map(|window| place_in_capture(window, bounds, scale))
+        .collect())
+}
 
-        let content_rect = Rectangle {
-            x: window.at[0],
-            y: window.at[1],
-            w: window.size[0],
-            h: window.size[1],
-        };
+fn focused_window() -> Result<Option<Window>> {
+    let wm_features = WmFeatures::get()?;
 
-        windows.push(Window {
-            outer_rect,
-            content_rect,
-        });
+    match wm_features.display_server_kind {
+        DisplayServerKind::Hyprland => hyprland::focused_window().map(Some),
+        DisplayServerKind::Sway => sway::focused_window(),
+        _ => Ok(None),
     }
-
-    Ok(windows)
 }
@@ -0,0 +1,105 @@
+//! Retrieves the windows shown by Hyprland by parsing the JSON output of `hyprctl`.
+
+use kcshot_data::geometry::Rectangle;
+use serde::{Deserialize, de::DeserializeOwned};
+
+use super::Error;
+use crate::{Result, Window};
+
+#[derive(Deserialize)]
+struct HyprWindow {
+    at: [f64; 2],
+    size: [f64; 2],
+    workspace: HyprWorkspace,
+    monitor: i32,
+    title: String,
+    class: String,
+    pid: i32,
+}
+
+#[derive(Deserialize)]
+struct HyprWorkspace {
+    id: i32,
+}
+
+#[derive(Deserialize)]
+struct HyprBorderSize {
+    int: i32,
+}
+
+impl HyprWindow {
+    fn to_window(&self, border_size: f64) -> Window {
+        let outer_rect = Rectangle {
+            x: self.at[0] - border_size,
+            y: self.at[1] - border_size,
+            w: self.size[0] + 2.0 * border_size,
+            h: self.size[1] + 2.0 * border_size,
+        };
+
+        let content_rect = Rectangle {
+            x: self.at[0],
+            y: self.at[1],
+            w: self.size[0],
+            h: self.size[1],
+        };
+
+        Window {
+            outer_rect,
+            content_rect,
+            id: None,
+            title: (!self.title.is_empty()).then(|| self.title.clone()),
+            class: (!self.class.is_empty()).then(|| self.class.clone()),
+            // hyprctl reports -1 for windows whose process it doesn't know
+            pid: u32::try_from(self.pid).ok(),
+            children: vec![],
+        }
+    }
+}
+
+/// Obtains the windows on the active workspace of the monitor that holds the active window
+pub(super) fn get_windows() -> Result<Vec<Window>> {
+    let border_size = border_size()?;
+    let active_window = spawn_and_parse_output::<HyprWindow>("hyprctl -j activewindow")?;
+
+    let clients = spawn_and_parse_output::<Vec<HyprWindow>>("hyprctl -j clients")?;
+
+    Ok(clients
+        .into_iter()
+        .filter(|win| {
+            win.workspace.id == active_window.workspace.id && win.monitor == active_window.monitor
+        })
+        .map(|window| window.to_window(border_size))
+        .collect())
+}
+
+pub(super) fn focused_window() -> Result<Window> {
+    let border_size = border_size()?;
+    let active_window = spawn_and_parse_output::<HyprWindow>("hyprctl -j activewindow")?;
+
+    Ok(active_window.to_window(border_size))
+}
+
+fn border_size() -> Result<f64> {
+    let border_size =
+        spawn_and_parse_output::<HyprBorderSize>("hyprctl -j getoption general:border_size")?;
+
+    Ok(border_size.int as f64)
+}
+
+fn spawn_and_parse_output<O: DeserializeOwned>(command_str: &str) -> Result<O> {
+    let mut argv = command_str.split_ascii_whitespace();
+    let mut command = std::process::Command::new(argv.next().unwrap());
+
+    for arg in argv {
+        command.arg(arg);
+    }
+
+    let output = command.output()?;
+
+    Ok(
+        serde_json::from_slice(&output.stdout).map_err(|error| Error::Deserialize {
+            error,
+            command: command_str.into(),
+        })?,
+    )
+}
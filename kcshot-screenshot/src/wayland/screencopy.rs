@@ -0,0 +1,604 @@
+//! Takes screenshots by talking to the compositor directly through the [wlr-screencopy][`screencopy`]
+//! protocol, which is a lot faster than going through desktop portals and never shows any dialogs.
+//!
+//! [`screencopy`]: https://wayland.app/protocols/wlr-screencopy-unstable-v1
+
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io,
+    os::{fd::AsFd, unix::fs::FileExt},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use cairo::{Context, Format as CairoImageFormat, ImageSurface, Operator};
+use kcshot_data::{geometry::Rectangle, settings::Settings};
+use wayland_client::{
+    Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum, delegate_noop,
+    globals::{GlobalList, GlobalListContents, registry_queue_init},
+    protocol::{wl_buffer, wl_output, wl_registry, wl_shm, wl_shm_pool},
+};
+use wayland_protocols::xdg::xdg_output::zv1::client::{
+    zxdg_output_manager_v1::ZxdgOutputManagerV1,
+    zxdg_output_v1::{self, ZxdgOutputV1},
+};
+use wayland_protocols_wlr::screencopy::v1::client::{
+    zwlr_screencopy_frame_v1::{self, ZwlrScreencopyFrameV1},
+    zwlr_screencopy_manager_v1::ZwlrScreencopyManagerV1,
+};
+
+use super::Error;
+use crate::{CaptureMetadata, CaptureTarget, Monitor, Result, Screenshot, bounds_of};
+
+const SCREENCOPY_MANAGER: &str = "zwlr_screencopy_manager_v1";
+
+/// Checks whether the compositor advertises the globals we need for taking screenshots
+pub(super) fn is_supported() -> bool {
+    let globals = Connection::connect_to_env()
+        .map_err(Error::from)
+        .and_then(|connection| Ok(registry_queue_init::<State>(&connection)?));
+
+    match globals {
+        Ok((globals, _)) => globals.contents().with_list(|globals| {
+            let has = |interface| globals.iter().any(|global| global.interface == interface);
+            has(SCREENCOPY_MANAGER) && has("wl_shm") && has("wl_output")
+        }),
+        Err(why) => {
+            tracing::info!("Failed to check for wlr-screencopy support: {why}");
+            false
+        }
+    }
+}
+
+/// The metadata of the returned screenshot lacks the focused window, as that is specific to each
+/// compositor.
+pub(super) fn take_screenshot(target: &CaptureTarget) -> Result<Screenshot> {
+    let mut session = Session::new()?;
+    let monitors = session.monitors();
+    let outputs_to_capture = outputs_for(target, &monitors)?;
+
+    let captured_monitors = outputs_to_capture
+        .iter()
+        .map(|&idx| monitors[idx].clone())
+        .collect::<Vec<_>>();
+
+    let overlay_cursor = Settings::open().capture_mouse_cursor();
+    let captures = outputs_to_capture
+        .into_iter()
+        .map(|idx| session.capture_output(idx, overlay_cursor))
+        .collect::<Result<Vec<_>>>()?;
+
+    let rects = captured_monitors
+        .iter()
+        .map(|monitor| monitor.rect)
+        .collect::<Vec<_>>();
+    let screenshot_bounds = bounds_of(&rects);
+
+    let image = ImageSurface::create(
+        CairoImageFormat::Rgb24,
+        screenshot_bounds.w as i32,
+        screenshot_bounds.h as i32,
+    )?;
+    let cairo = Context::new(&image)?;
+    cairo.set_operator(Operator::Source);
+
+    for (capture, rect) in captures.into_iter().zip(rects) {
+        let (width, height) = (capture.width, capture.height);
+        let surface = ImageSurface::create_for_data(
+            capture.data,
+            CairoImageFormat::Rgb24,
+            width as i32,
+            height as i32,
+            CairoImageFormat::Rgb24.stride_for_width(width)?,
+        )?;
+
+        // Outputs with a lower scale than the screenshot's get stretched to cover as many pixels
+        // as they do in the screenshot, for the others this is a plain copy
+        cairo.save()?;
+        cairo.translate(rect.x - screenshot_bounds.x, rect.y - screenshot_bounds.y);
+        cairo.scale(rect.w / width as f64, rect.h / height as f64);
+        cairo.set_source_surface(&surface, 0.0, 0.0)?;
+        cairo.paint()?;
+        cairo.restore()?;
+    }
+    drop(cairo);
+
+    Ok(Screenshot {
+        image,
+        // The compositor draws the cursor onto the outputs itself
+        cursor: None,
+        metadata: CaptureMetadata {
+            monitors: captured_monitors,
+            scale: session.scale(),
+            ..CaptureMetadata::new(screenshot_bounds)
+        },
+    })
+}
+
+pub(super) fn monitors() -> Result<Vec<Monitor>> {
+    Ok(Session::new()?.monitors())
+}
+
+/// Returns the part of the compositor's global space that a screenshot of `target` covers, in the
+/// screenshot's pixels, along with how many of them make up a logical pixel
+pub(super) fn capture_area(target: &CaptureTarget) -> Result<(Rectangle, f64)> {
+    let session = Session::new()?;
+    let monitors = session.monitors();
+    let rects = outputs_for(target, &monitors)?
+        .into_iter()
+        .map(|idx| monitors[idx].rect)
+        .collect::<Vec<_>>();
+
+    Ok((bounds_of(&rects), session.scale()))
+}
+
+/// Returns which of `monitors` get captured for `target`, by index
+fn outputs_for(target: &CaptureTarget, monitors: &[Monitor]) -> Result<Vec<usize>> {
+    Ok(match target {
+        CaptureTarget::Monitor(selector) => {
+            let monitor = selector
+                .select(monitors)
+                .ok_or_else(|| crate::Error::NoSuchMonitor(selector.to_string()))?;
+            let idx = monitors
+                .iter()
+                .position(|m| std::ptr::eq(m, monitor))
+                .expect("The monitor was picked out of this list");
+            vec![idx]
+        }
+        // We have no way of knowing where the cursor is, so we capture everything, just like the
+        // screenshot portal does
+        CaptureTarget::MonitorUnderCursor | CaptureTarget::AllMonitors => {
+            (0..monitors.len()).collect()
+        }
+    })
+}
+
+/// The contents of a single output, converted to the layout of [`CairoImageFormat::Rgb24`] without
+/// any padding at the end of rows
+struct OutputCapture {
+    data: Vec<u8>,
+    /// The size of the output's buffer, in physical pixels
+    width: u32,
+    height: u32,
+}
+
+struct Session {
+    queue: EventQueue<State>,
+    state: State,
+    shm: wl_shm::WlShm,
+    manager: ZwlrScreencopyManagerV1,
+    outputs: Vec<wl_output::WlOutput>,
+}
+
+impl Session {
+    fn new() -> Result<Self> {
+        let connection = Connection::connect_to_env().map_err(Error::from)?;
+        let (globals, mut queue) =
+            registry_queue_init::<State>(&connection).map_err(Error::from)?;
+        let qh = queue.handle();
+
+        let shm = globals.bind(&qh, 1..=1, ()).map_err(Error::from)?;
+        let manager = globals.bind(&qh, 1..=3, ()).map_err(Error::from)?;
+
+        let mut state = State::default();
+        let outputs = bind_outputs(&globals, &qh, &mut state);
+
+        // xdg-output tells us where outputs are in logical pixels, without it we have to guess that
+        // from their integer scale
+        match globals.bind::<ZxdgOutputManagerV1, _, _>(&qh, 1..=3, ()) {
+            Ok(xdg_output_manager) => {
+                for (idx, output) in outputs.iter().enumerate() {
+                    xdg_output_manager.get_xdg_output(output, &qh, idx);
+                }
+            }
+            Err(why) => tracing::info!("xdg-output is not available: {why}"),
+        }
+
+        // This gets the compositor to send us the information about the outputs we just bound
+        queue.roundtrip(&mut state).map_err(Error::from)?;
+
+        Ok(Self {
+            queue,
+            state,
+            shm,
+            manager,
+            outputs,
+        })
+    }
+
+    /// How many pixels of a screenshot make up a logical pixel. This is the biggest scale of all the
+    /// outputs, so that none of them lose any detail.
+    fn scale(&self) -> f64 {
+        self.state
+            .outputs
+            .iter()
+            .map(OutputInfo::scale_factor)
+            .fold(1.0, f64::max)
+    }
+
+    /// Returns the outputs in the order the compositor advertised them in, placed in the pixels of
+    /// screenshots (see [`Self::scale`])
+    fn monitors(&self) -> Vec<Monitor> {
+        let scale = self.scale();
+
+        self.state
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(idx, output)| Monitor {
+                name: output.name(idx),
+                rect: output.rect(scale),
+                // Wayland has no concept of a primary monitor
+                is_primary: false,
+            })
+            .collect()
+    }
+
+    fn capture_output(&mut self, idx: usize, overlay_cursor: bool) -> Result<OutputCapture> {
+        let qh = self.queue.handle();
+        let output_name = self.state.outputs[idx].name(idx);
+
+        self.state.frame = FrameState::default();
+        let frame = self
+            .manager
+            .capture_output(overlay_cursor as i32, &self.outputs[idx], &qh, ());
+
+        // Version 3 of the protocol may advertise multiple buffer types, so we have to wait for all
+        // of them to be sent, older versions only send the (single) shm buffer type
+        let has_buffer_done = self.manager.version() >= 3;
+        while self.state.frame.status.is_none()
+            && (self.state.frame.buffer.is_none()
+                || (has_buffer_done && !self.state.frame.buffer_done))
+        {
+            self.queue
+                .blocking_dispatch(&mut self.state)
+                .map_err(Error::from)?;
+        }
+
+        let (Some(buffer_info), None) = (self.state.frame.buffer, self.state.frame.status) else {
+            frame.destroy();
+            return Err(Error::ScreencopyFailed(output_name).into());
+        };
+
+        let format = match buffer_info.format {
+            WEnum::Value(
+                format @ (wl_shm::Format::Xrgb8888
+                | wl_shm::Format::Argb8888
+                | wl_shm::Format::Xbgr8888
+                | wl_shm::Format::Abgr8888),
+            ) => format,
+            format => {
+                frame.destroy();
+                return Err(Error::UnsupportedBufferFormat(format!("{format:?}")).into());
+            }
+        };
+
+        let size = buffer_info.stride * buffer_info.height;
+        let file = anonymous_file(size as u64)?;
+        let pool = self.shm.create_pool(file.as_fd(), size as i32, &qh, ());
+        let buffer = pool.create_buffer(
+            0,
+            buffer_info.width as i32,
+            buffer_info.height as i32,
+            buffer_info.stride as i32,
+            format,
+            &qh,
+            (),
+        );
+
+        frame.copy(&buffer);
+        while self.state.frame.status.is_none() {
+            self.queue
+                .blocking_dispatch(&mut self.state)
+                .map_err(Error::from)?;
+        }
+
+        frame.destroy();
+        buffer.destroy();
+        pool.destroy();
+
+        if self.state.frame.status == Some(FrameStatus::Failed) {
+            return Err(Error::ScreencopyFailed(output_name).into());
+        }
+
+        let mut raw = vec![0; size as usize];
+        file.read_exact_at(&mut raw, 0)?;
+
+        let row_len = 4 * buffer_info.width as usize;
+        let mut data = Vec::with_capacity(row_len * buffer_info.height as usize);
+        let mut rows = raw
+            .chunks_exact(buffer_info.stride as usize)
+            .map(|row| &row[..row_len])
+            .collect::<Vec<_>>();
+        if self.state.frame.y_invert {
+            rows.reverse();
+        }
+
+        for row in rows {
+            data.extend_from_slice(row);
+        }
+
+        // wl_shm formats are little-endian, so (A|X)RGB8888 is laid out in memory just like cairo's
+        // formats, the (A|X)BGR8888 ones have the red and blue channels swapped though
+        if matches!(format, wl_shm::Format::Xbgr8888 | wl_shm::Format::Abgr8888) {
+            for pixel in data.chunks_exact_mut(4) {
+                pixel.swap(0, 2);
+            }
+        }
+
+        Ok(OutputCapture {
+            data,
+            width: buffer_info.width,
+            height: buffer_info.height,
+        })
+    }
+}
+
+fn bind_outputs(
+    globals: &GlobalList,
+    qh: &QueueHandle<State>,
+    state: &mut State,
+) -> Vec<wl_output::WlOutput> {
+    let outputs = globals.contents().with_list(|globals| {
+        globals
+            .iter()
+            .filter(|global| global.interface == wl_output::WlOutput::interface().name)
+            .map(|global| (global.name, global.version))
+            .collect::<Vec<_>>()
+    });
+
+    outputs
+        .into_iter()
+        .enumerate()
+        .map(|(idx, (name, version))| {
+            state.outputs.push(OutputInfo::default());
+            // Version 4 added the name event
+            globals
+                .registry()
+                .bind::<wl_output::WlOutput, _, _>(name, version.min(4), qh, idx)
+        })
+        .collect()
+}
+
+/// Creates a file that only lives in memory (unless `XDG_RUNTIME_DIR` is not a tmpfs), for sharing
+/// buffers with the compositor
+fn anonymous_file(size: u64) -> io::Result<File> {
+    let dir = env::var_os("XDG_RUNTIME_DIR").map_or_else(env::temp_dir, PathBuf::from);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    let path = dir.join(format!("kcshot-screencopy-{}-{nanos}", std::process::id()));
+
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    // The compositor gets the file through its file descriptor, so it doesn't need a path
+    fs::remove_file(&path)?;
+    file.set_len(size)?;
+
+    Ok(file)
+}
+
+#[derive(Debug, Default)]
+struct State {
+    outputs: Vec<OutputInfo>,
+    frame: FrameState,
+}
+
+#[derive(Debug, Default)]
+struct OutputInfo {
+    name: Option<String>,
+    /// The name sent through xdg-output, which older compositors send instead of the wl_output one
+    xdg_name: Option<String>,
+    make_and_model: Option<String>,
+    x: i32,
+    y: i32,
+    /// The size of the current mode, in physical pixels
+    width: i32,
+    height: i32,
+    scale: i32,
+    logical_position: Option<(i32, i32)>,
+    logical_size: Option<(i32, i32)>,
+}
+
+impl OutputInfo {
+    fn name(&self, idx: usize) -> String {
+        self.name
+            .clone()
+            .or_else(|| self.xdg_name.clone())
+            .or_else(|| self.make_and_model.clone())
+            .unwrap_or_else(|| format!("Output {idx}"))
+    }
+
+    /// Where the output is placed in the compositor's global space, in logical pixels
+    fn logical_rect(&self) -> Rectangle {
+        let scale = self.scale.max(1);
+        let (x, y) = self.logical_position.unwrap_or((self.x, self.y));
+        let (w, h) = self
+            .logical_size
+            .unwrap_or((self.width / scale, self.height / scale));
+
+        Rectangle {
+            x: x as f64,
+            y: y as f64,
+            w: w as f64,
+            h: h as f64,
+        }
+    }
+
+    /// How many physical pixels make up a logical one on this output, which isn't necessarily a
+    /// whole number
+    fn scale_factor(&self) -> f64 {
+        let logical = self.logical_rect();
+        // Modes aren't rotated along with the output, so comparing the longer sides keeps this
+        // working for rotated outputs
+        let logical_len = logical.w.max(logical.h);
+        if logical_len <= 0.0 {
+            return self.scale.max(1) as f64;
+        }
+
+        self.width.max(self.height) as f64 / logical_len
+    }
+
+    /// Where the output is placed in screenshots in which a logical pixel is `scale` pixels big.
+    /// Edges are rounded rather than the size, so that adjacent outputs don't get gaps between them.
+    fn rect(&self, scale: f64) -> Rectangle {
+        let logical = self.logical_rect();
+        let left = (logical.x * scale).round();
+        let top = (logical.y * scale).round();
+        let right = ((logical.x + logical.w) * scale).round();
+        let bottom = ((logical.y + logical.h) * scale).round();
+
+        Rectangle {
+            x: left,
+            y: top,
+            w: right - left,
+            h: bottom - top,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct FrameState {
+    buffer: Option<BufferInfo>,
+    buffer_done: bool,
+    y_invert: bool,
+    status: Option<FrameStatus>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct BufferInfo {
+    format: WEnum<wl_shm::Format>,
+    width: u32,
+    height: u32,
+    stride: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FrameStatus {
+    Ready,
+    Failed,
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        // We only look at the globals once, so changes to them are not interesting
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        &idx: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[idx];
+
+        match event {
+            wl_output::Event::Geometry {
+                x, y, make, model, ..
+            } => {
+                output.x = x;
+                output.y = y;
+                output.make_and_model = Some(format!("{make} {model}"));
+            }
+            wl_output::Event::Mode {
+                flags: WEnum::Value(flags),
+                width,
+                height,
+                ..
+            } if flags.contains(wl_output::Mode::Current) => {
+                output.width = width;
+                output.height = height;
+            }
+            wl_output::Event::Scale { factor } => output.scale = factor,
+            wl_output::Event::Name { name } => output.name = Some(name),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZxdgOutputV1, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZxdgOutputV1,
+        event: zxdg_output_v1::Event,
+        &idx: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let output = &mut state.outputs[idx];
+
+        match event {
+            zxdg_output_v1::Event::LogicalPosition { x, y } => {
+                output.logical_position = Some((x, y));
+            }
+            zxdg_output_v1::Event::LogicalSize { width, height } => {
+                output.logical_size = Some((width, height));
+            }
+            zxdg_output_v1::Event::Name { name } => output.xdg_name = Some(name),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrScreencopyFrameV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &ZwlrScreencopyFrameV1,
+        event: zwlr_screencopy_frame_v1::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let frame = &mut state.frame;
+
+        match event {
+            zwlr_screencopy_frame_v1::Event::Buffer {
+                format,
+                width,
+                height,
+                stride,
+            } => {
+                frame.buffer = Some(BufferInfo {
+                    format,
+                    width,
+                    height,
+                    stride,
+                });
+            }
+            zwlr_screencopy_frame_v1::Event::BufferDone => frame.buffer_done = true,
+            zwlr_screencopy_frame_v1::Event::Flags { flags } => {
+                frame.y_invert = matches!(
+                    flags,
+                    WEnum::Value(flags) if flags.contains(zwlr_screencopy_frame_v1::Flags::YInvert)
+                );
+            }
+            zwlr_screencopy_frame_v1::Event::Ready { .. } => {
+                frame.status = Some(FrameStatus::Ready);
+            }
+            zwlr_screencopy_frame_v1::Event::Failed => frame.status = Some(FrameStatus::Failed),
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(State: ignore wl_shm::WlShm);
+delegate_noop!(State: wl_shm_pool::WlShmPool);
+delegate_noop!(State: ignore wl_buffer::WlBuffer);
+delegate_noop!(State: ZwlrScreencopyManagerV1);
+delegate_noop!(State: ZxdgOutputManagerV1);
@@ -0,0 +1,277 @@
+//! Retrieves the windows shown by sway by asking for its layout tree over the i3-compatible
+//! [IPC socket][`sway_ipc`].
+//!
+//! [`sway_ipc`]: https://man.archlinux.org/man/sway-ipc.7
+
+use std::{
+    env,
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+};
+
+use kcshot_data::geometry::Rectangle;
+use serde::Deserialize;
+
+use super::Error;
+use crate::{Result, Window};
+
+const IPC_MAGIC: &[u8; 6] = b"i3-ipc";
+const GET_TREE: u32 = 4;
+
+/// Obtains the windows that are visible on any output, tiled windows come before floating ones.
+pub(super) fn get_windows() -> Result<Vec<Window>> {
+    let tree = get_tree()?;
+
+    windows_from_tree(&tree)
+}
+
+/// Obtains the window that has the input focus, if any
+pub(super) fn focused_window() -> Result<Option<Window>> {
+    let tree = get_tree()?;
+
+    focused_window_from_tree(&tree)
+}
+
+/// Sends a GET_TREE message and returns the JSON payload of the reply
+fn get_tree() -> Result<Vec<u8>> {
+    let socket = env::var_os("SWAYSOCK").ok_or(Error::SwaySocketNotSet)?;
+    let mut stream = UnixStream::connect(socket)?;
+
+    // Messages are made up of the magic string, the length of the payload and the message type, with
+    // the integers in native byte order. GET_TREE has no payload.
+    let mut message = Vec::with_capacity(IPC_MAGIC.len() + 8);
+    message.extend_from_slice(IPC_MAGIC);
+    message.extend_from_slice(&0u32.to_ne_bytes());
+    message.extend_from_slice(&GET_TREE.to_ne_bytes());
+    stream.write_all(&message)?;
+
+    let mut header = [0; IPC_MAGIC.len() + 8];
+    stream.read_exact(&mut header)?;
+
+    let (magic, rest) = header.split_at(IPC_MAGIC.len());
+    let (length, kind) = rest.split_at(4);
+    let length = u32::from_ne_bytes(length.try_into().unwrap());
+    let kind = u32::from_ne_bytes(kind.try_into().unwrap());
+
+    if magic != IPC_MAGIC || kind != GET_TREE {
+        return Err(Error::MalformedSwayReply.into());
+    }
+
+    let mut payload = vec![0; length as usize];
+    stream.read_exact(&mut payload)?;
+
+    Ok(payload)
+}
+
+#[derive(Deserialize)]
+struct SwayNode {
+    /// The rect of the container, including its borders and title bar
+    rect: SwayRect,
+    /// The rect of the content of the container, relative to [`Self::rect`]
+    window_rect: SwayRect,
+    /// Only set for views (i.e. windows) and workspaces
+    visible: Option<bool>,
+    /// The title of views, or the name of outputs and workspaces
+    name: Option<String>,
+    /// Only set for views
+    pid: Option<i32>,
+    /// Only set for native Wayland views
+    app_id: Option<String>,
+    /// Only set for Xwayland views
+    window_properties: Option<SwayWindowProperties>,
+    #[serde(default)]
+    focused: bool,
+    #[serde(default)]
+    nodes: Vec<SwayNode>,
+    #[serde(default)]
+    floating_nodes: Vec<SwayNode>,
+}
+
+#[derive(Deserialize)]
+struct SwayWindowProperties {
+    class: Option<String>,
+}
+
+#[derive(Clone, Copy, Deserialize)]
+struct SwayRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+impl SwayNode {
+    fn is_view(&self) -> bool {
+        self.pid.is_some() && self.nodes.is_empty() && self.floating_nodes.is_empty()
+    }
+
+    fn to_window(&self) -> Window {
+        let SwayRect {
+            x,
+            y,
+            width,
+            height,
+        } = self.rect;
+        let content = self.window_rect;
+
+        Window {
+            outer_rect: Rectangle {
+                x,
+                y,
+                w: width,
+                h: height,
+            },
+            content_rect: Rectangle {
+                x: x + content.x,
+                y: y + content.y,
+                w: content.width,
+                h: content.height,
+            },
+            id: None,
+            title: self.name.clone(),
+            class: self.app_id.clone().or_else(|| {
+                self.window_properties
+                    .as_ref()
+                    .and_then(|properties| properties.class.clone())
+            }),
+            pid: self.pid.and_then(|pid| u32::try_from(pid).ok()),
+            children: vec![],
+        }
+    }
+
+    fn children(&self) -> impl Iterator<Item = &SwayNode> {
+        // Floating containers are drawn above tiled ones, so they come later in stacking order
+        self.nodes.iter().chain(&self.floating_nodes)
+    }
+}
+
+fn parse_tree(tree: &[u8]) -> Result<SwayNode> {
+    Ok(
+        serde_json::from_slice(tree).map_err(|error| Error::Deserialize {
+            error,
+            command: "swaymsg -t get_tree".into(),
+        })?,
+    )
+}
+
+fn windows_from_tree(tree: &[u8]) -> Result<Vec<Window>> {
+    let root = parse_tree(tree)?;
+
+    let mut windows = vec![];
+    collect_visible_windows(&root, &mut windows);
+
+    Ok(windows)
+}
+
+fn collect_visible_windows(node: &SwayNode, windows: &mut Vec<Window>) {
+    if node.is_view() {
+        if node.visible == Some(true) {
+            windows.push(node.to_window());
+        }
+
+        return;
+    }
+
+    for child in node.children() {
+        collect_visible_windows(child, windows);
+    }
+}
+
+fn focused_window_from_tree(tree: &[u8]) -> Result<Option<Window>> {
+    fn find_focused_view(node: &SwayNode) -> Option<&SwayNode> {
+        if node.is_view() {
+            return node.focused.then_some(node);
+        }
+
+        node.children().find_map(find_focused_view)
+    }
+
+    let root = parse_tree(tree)?;
+
+    Ok(find_focused_view(&root).map(SwayNode::to_window))
+}
+
+#[cfg(test)]
+mod tests {
+    use kcshot_data::geometry::Rectangle;
+
+    use super::{focused_window_from_tree, windows_from_tree};
+
+    /// Output of `swaymsg -t get_tree -r` with two outputs. The second workspace of the first output
+    /// holds a window which is not visible, the second output has a floating dialog on top of a
+    /// window without a title bar, and the scratchpad holds a hidden window.
+    const TREE: &str = include_str!("sway_tree.json");
+
+    fn rect(rect: Rectangle) -> (f64, f64, f64, f64) {
+        (rect.x, rect.y, rect.w, rect.h)
+    }
+
+    #[test]
+    fn only_visible_windows() {
+        let windows = windows_from_tree(TREE.as_bytes()).unwrap();
+
+        assert_eq!(windows.len(), 3);
+        assert!(windows.iter().all(|window| window.id.is_none()));
+    }
+
+    #[test]
+    fn tiled_windows() {
+        let windows = windows_from_tree(TREE.as_bytes()).unwrap();
+
+        // A terminal with a title bar
+        assert_eq!(rect(windows[0].outer_rect), (0.0, 0.0, 960.0, 1080.0));
+        assert_eq!(rect(windows[0].content_rect), (2.0, 28.0, 956.0, 1050.0));
+
+        // An Xwayland window on the second output, without a title bar
+        assert_eq!(rect(windows[1].outer_rect), (1920.0, 0.0, 2560.0, 1440.0));
+        assert_eq!(rect(windows[1].content_rect), (1922.0, 2.0, 2556.0, 1436.0));
+    }
+
+    #[test]
+    fn window_properties() {
+        let windows = windows_from_tree(TREE.as_bytes()).unwrap();
+
+        assert_eq!(windows[0].title.as_deref(), Some("~"));
+        assert_eq!(windows[0].class.as_deref(), Some("foot"));
+        assert_eq!(windows[0].pid, Some(1234));
+
+        // Xwayland windows have no app ID, so the class comes from WM_CLASS
+        assert_eq!(windows[1].class.as_deref(), Some("Gimp"));
+    }
+
+    #[test]
+    fn floating_windows_come_last() {
+        let windows = windows_from_tree(TREE.as_bytes()).unwrap();
+
+        assert_eq!(rect(windows[2].outer_rect), (2400.0, 300.0, 604.0, 430.0));
+        assert_eq!(rect(windows[2].content_rect), (2402.0, 328.0, 600.0, 400.0));
+    }
+
+    #[test]
+    fn focused_window() {
+        let window = focused_window_from_tree(TREE.as_bytes()).unwrap().unwrap();
+
+        assert_eq!(rect(window.outer_rect), (0.0, 0.0, 960.0, 1080.0));
+    }
+
+    #[test]
+    fn empty_tree() {
+        let tree = r#"{
+            "id": 1,
+            "type": "root",
+            "rect": { "x": 0, "y": 0, "width": 0, "height": 0 },
+            "window_rect": { "x": 0, "y": 0, "width": 0, "height": 0 },
+            "nodes": [],
+            "floating_nodes": []
+        }"#;
+
+        let windows = windows_from_tree(tree.as_bytes()).unwrap();
+
+        assert!(windows.is_empty());
+    }
+
+    #[test]
+    fn malformed_tree() {
+        assert!(windows_from_tree(b"{ \"nodes\": [] }").is_err());
+    }
+}
@@ -1,34 +1,49 @@
-use std::sync::OnceLock;
-
 use cairo::{self, Format as CairoImageFormat, ImageSurface};
 use kcshot_data::{
     geometry::{Point, Rectangle},
     settings::Settings,
 };
 use xcb::{
-    shape,
+    BaseEvent, Xid, XidNew, composite, shape,
     x::{
-        self, ATOM_ATOM, ATOM_CARDINAL, ATOM_NONE, ATOM_WINDOW, ImageFormat as XImageFormat,
-        MapState, Window as XWindow,
+        self, ATOM_ATOM, ATOM_CARDINAL, ATOM_NONE, ATOM_STRING, ATOM_WINDOW, ATOM_WM_CLASS,
+        ATOM_WM_NAME, ImageFormat as XImageFormat, MapState, Window as XWindow,
     },
-    xfixes,
+    xfixes, xtest,
 };
 
-use super::{Result, Window, WmFeatures};
+use self::{pixels::PixelFormat, shm::ShmSegment};
+use super::{
+    CaptureMetadata, CaptureTarget, Cursor, Frame, Monitor, MonitorSelector, Result, Screenshot,
+    Window, WmFeatures, bounds_of, monitors_within,
+};
 use crate::DisplayServerKind;
 
+mod pixels;
+mod shm;
+
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
-    #[error("WM does not support _NET_CLIENT_LIST_STACKING")]
-    WmDoesNotSupportWindowList,
-    #[error("WM does not support _NET_FRAME_EXTENTS")]
-    WmDoesNotSupportFrameExtents,
     #[error("Failed to get root window")]
     FailedToGetRootWindow,
     #[error("Failed to establish a connection to the X server: {0:?}")]
     XcbConnection(#[from] xcb::ConnError),
+    #[error("The X server does not support the Composite extension")]
+    CompositeUnsupported,
+    #[error("Visual {visual:#x} with a depth of {depth} bits is not supported")]
+    UnsupportedVisual { depth: u8, visual: x::Visualid },
+    #[error("The X server does not support the XTEST extension")]
+    XTestUnsupported,
     #[error("Encountered an X protocol error: {0:?}")]
     XcbProtocol(xcb::ProtocolError),
+    #[error("The X server does not support the MIT-SHM extension")]
+    ShmUnsupported,
+    #[error("MIT-SHM was disabled through KCSHOT_DISABLE_SHM")]
+    ShmDisabled,
+    #[error("Failed to set up shared memory: {0}")]
+    Shm(std::io::Error),
+    #[error("The shared memory segment holds {available} bytes, but the image needs {needed}")]
+    ShmSegmentTooSmall { needed: usize, available: usize },
 }
 
 impl From<xcb::Error> for Error {
@@ -40,11 +55,15 @@ impl From<xcb::Error> for Error {
     }
 }
 
-pub(super) fn take_screenshot() -> Result<ImageSurface> {
+pub(super) fn take_screenshot(target: &CaptureTarget) -> Result<Screenshot> {
     let (connection, _) = xcb::Connection::connect_with_extensions(
         None,
         &[],
-        &[xcb::Extension::Shape, xcb::Extension::XFixes],
+        &[
+            xcb::Extension::Shape,
+            xcb::Extension::XFixes,
+            xcb::Extension::Shm,
+        ],
     )
     .map_err(Error::from)?;
     let setup = connection.get_setup();
@@ -63,68 +82,234 @@ pub(super) fn take_screenshot() -> Result<ImageSurface> {
             .wait_for_reply(pointer_cookie)
             .map_err(Error::from)?;
         if pointer_reply.same_screen() {
+            let cursor_position = Point {
+                x: pointer_reply.root_x() as _,
+                y: pointer_reply.root_y() as _,
+            };
+
             // Just because the cursor is on the same screen(monitor) as the root window, it doesn't
             // mean the root window spans a single monitor, in fact it can span multiple.
-            // So we get the monitor that's under the cursor here.
-            let screenshot_bounds = retrieve_bounds_of_monitor_under_cursor(
-                &connection,
-                Point {
-                    x: pointer_reply.root_x() as _,
-                    y: pointer_reply.root_y() as _,
-                },
-                window,
-            )?;
+            // So we figure out which monitors we're interested in here.
+            let monitors =
+                retrieve_monitors_to_capture(&connection, target, cursor_position, window)?;
+            let screenshot_bounds = bounds_of(&monitors);
+            let metadata =
+                retrieve_capture_metadata(&connection, window, screenshot_bounds, cursor_position);
+
+            let pixel_format = PixelFormat::of_root(setup, root_screen)?;
+            let format = pixel_format.cairo_format();
+            let stride = format.stride_for_width(screenshot_bounds.w as u32)?;
+            let mut screenshot = vec![0; stride as usize * screenshot_bounds.h as usize];
+
+            // Monitors are captured one at a time, so the segment only has to fit the biggest one
+            let largest_monitor = monitors
+                .iter()
+                .map(|monitor| pixel_format.stride(monitor.w as usize) * monitor.h as usize)
+                .max()
+                .unwrap_or_default();
+            let mut shm_segment = ShmSegment::new(&connection, largest_monitor)
+                .inspect_err(|why| tracing::info!("Not using MIT-SHM: {why}"))
+                .ok();
+
+            for monitor in monitors {
+                let drawable = x::Drawable::Window(window);
+
+                if let Some(segment) = shm_segment.as_mut() {
+                    match segment.get_image(&connection, drawable, monitor) {
+                        Ok(monitor_image) => {
+                            pixel_format.blit_monitor(
+                                monitor_image,
+                                monitor,
+                                &mut screenshot,
+                                stride as usize,
+                                screenshot_bounds,
+                                format,
+                            );
+                            continue;
+                        }
+                        Err(why) => {
+                            tracing::info!("Capturing through MIT-SHM failed, not using it: {why}");
+                            shm_segment = None;
+                        }
+                    }
+                }
 
-            let screenshot_cookie = connection.send_request(&x::GetImage {
-                format: XImageFormat::ZPixmap,
-                drawable: x::Drawable::Window(window),
-                x: screenshot_bounds.x as _,
-                y: screenshot_bounds.y as _,
-                width: screenshot_bounds.w as _,
-                height: screenshot_bounds.h as _,
-                plane_mask: u32::MAX,
-            });
+                let monitor_image = get_image(&connection, drawable, monitor)?;
 
-            let mut screenshot = connection
-                .wait_for_reply(screenshot_cookie)
-                .map_err(Error::from)?
-                .data()
-                .to_vec();
+                pixel_format.blit_monitor(
+                    monitor_image.data(),
+                    monitor,
+                    &mut screenshot,
+                    stride as usize,
+                    screenshot_bounds,
+                    format,
+                );
+            }
 
             let capture_mouse_cursor = Settings::open().capture_mouse_cursor();
 
+            // The cursor is kept apart from the screenshot, so that the editor can move or hide it
+            let mut cursor = None;
             if capture_mouse_cursor {
                 let cursor_cookie = connection.send_request(&xfixes::GetCursorImage {});
-                let cursor = connection.wait_for_reply(cursor_cookie);
-                match cursor {
-                    Ok(cursor) => {
-                        overlay_cursor(cursor, &mut screenshot, screenshot_bounds);
-                    }
+                match connection.wait_for_reply(cursor_cookie) {
+                    Ok(reply) => cursor = cursor_from_reply(&reply, screenshot_bounds)?,
                     Err(why) => tracing::info!("Unable to fetch cursor data: {why:?}"),
                 }
             }
 
-            let stride = CairoImageFormat::Rgb24.stride_for_width(screenshot_bounds.w as u32)?;
-            let screenshot = ImageSurface::create_for_data(
+            let image = ImageSurface::create_for_data(
                 screenshot,
-                CairoImageFormat::Rgb24,
+                format,
                 screenshot_bounds.w as i32,
                 screenshot_bounds.h as i32,
                 stride,
             )?;
 
-            return Ok(screenshot);
+            return Ok(Screenshot {
+                image,
+                cursor,
+                metadata,
+            });
         }
     }
 
     Err(super::Error::FailedToTakeScreenshot)
 }
 
-fn retrieve_bounds_of_monitor_under_cursor(
+/// Gathers the metadata of a screenshot of `bounds`, this is best-effort, so parts that can't be
+/// retrieved are left out instead of failing the whole screenshot.
+fn retrieve_capture_metadata(
     connection: &xcb::Connection,
+    root: XWindow,
+    bounds: Rectangle,
+    cursor_position: Point,
+) -> CaptureMetadata {
+    let mut metadata = CaptureMetadata::new(bounds);
+
+    match retrieve_monitors(connection, root) {
+        Ok(monitors) => metadata.monitors = monitors_within(monitors, bounds),
+        Err(why) => tracing::info!("Failed to retrieve monitors for capture metadata: {why}"),
+    }
+
+    let focused_window = AtomsOfInterest::intern(connection)
+        .and_then(|atoms| retrieve_focused_window(connection, &atoms, root, bounds));
+    match focused_window {
+        Ok(focused_window) => metadata.focused_window = focused_window,
+        Err(why) => tracing::info!("Failed to retrieve the focused window: {why}"),
+    }
+
+    metadata.cursor_position = bounds.contains(cursor_position).then_some(Point {
+        x: cursor_position.x - bounds.x,
+        y: cursor_position.y - bounds.y,
+    });
+
+    metadata
+}
+
+/// Returns the window that _NET_ACTIVE_WINDOW points to, relative to `bounds`
+fn retrieve_focused_window(
+    connection: &xcb::Connection,
+    atoms: &AtomsOfInterest,
+    root: XWindow,
+    bounds: Rectangle,
+) -> Result<Option<Window>> {
+    let &AtomsOfInterest { active_window, .. } = atoms;
+    if active_window == ATOM_NONE {
+        return Ok(None);
+    }
+
+    let active = connection.send_request(&x::GetProperty {
+        delete: false,
+        window: root,
+        property: active_window,
+        r#type: ATOM_WINDOW,
+        long_offset: 0,
+        long_length: 1,
+    });
+    let active = connection.wait_for_reply(active).map_err(Error::from)?;
+
+    match active.value::<XWindow>().first() {
+        Some(&window) if !window.is_none() => {
+            retrieve_window(connection, atoms, root, window, bounds).map(Some)
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Returns the rects of the monitors which have to be part of the screenshot, as determined by
+/// `target`
+fn retrieve_monitors_to_capture(
+    connection: &xcb::Connection,
+    target: &CaptureTarget,
+    cursor_position: Point,
+    window: XWindow,
+) -> Result<Vec<Rectangle>> {
+    match target {
+        CaptureTarget::MonitorUnderCursor => Ok(vec![retrieve_bounds_of_monitor_under_cursor(
+            connection,
+            cursor_position,
+            window,
+        )?]),
+        CaptureTarget::AllMonitors => retrieve_bounds_of_all_monitors(connection, window),
+        CaptureTarget::Monitor(selector) => Ok(vec![retrieve_bounds_of_selected_monitor(
+            connection, selector, window,
+        )?]),
+    }
+}
+
+/// Returns the bounds of the area that [`take_screenshot`] would capture for `target`, this is
+/// what window rects have to be made relative to.
+fn retrieve_capture_bounds(
+    connection: &xcb::Connection,
+    target: &CaptureTarget,
     cursor_position: Point,
     window: XWindow,
 ) -> Result<Rectangle> {
+    retrieve_monitors_to_capture(connection, target, cursor_position, window)
+        .map(|monitors| bounds_of(&monitors))
+}
+
+pub(super) fn capture_bounds(target: &CaptureTarget) -> Result<Rectangle> {
+    let (connection, _) = xcb::Connection::connect(None).map_err(Error::from)?;
+
+    let root = connection
+        .get_setup()
+        .roots()
+        .next()
+        .ok_or(Error::FailedToGetRootWindow)?
+        .root();
+
+    let pointer = connection.send_request(&x::QueryPointer { window: root });
+    let pointer = connection.wait_for_reply(pointer).map_err(Error::from)?;
+
+    retrieve_capture_bounds(
+        &connection,
+        target,
+        Point {
+            x: pointer.root_x() as _,
+            y: pointer.root_y() as _,
+        },
+        root,
+    )
+}
+
+fn retrieve_bounds_of_all_monitors(
+    connection: &xcb::Connection,
+    window: XWindow,
+) -> Result<Vec<Rectangle>> {
+    let monitors = retrieve_monitors(connection, window)?;
+
+    if monitors.is_empty() {
+        tracing::error!("Failed to find any monitors. (How?!)");
+        return Err(super::Error::FailedToTakeScreenshot);
+    }
+
+    Ok(monitors.into_iter().map(|monitor| monitor.rect).collect())
+}
+
+/// Returns the active monitors as reported by RandR, in the order RandR reports them in.
+fn retrieve_monitors(connection: &xcb::Connection, window: XWindow) -> Result<Vec<Monitor>> {
     let get_monitors = connection.send_request(&xcb::randr::GetMonitors {
         window,
         get_active: true,
@@ -133,29 +318,635 @@ fn retrieve_bounds_of_monitor_under_cursor(
         .wait_for_reply(get_monitors)
         .map_err(Error::from)?;
 
-    for monitor in monitors.monitors() {
-        let monitor_rect = Rectangle {
-            x: monitor.x() as _,
-            y: monitor.y() as _,
-            w: monitor.width() as _,
-            h: monitor.height() as _,
+    // We send all the requests first so we don't have to wait for a roundtrip per monitor
+    let names = monitors
+        .monitors()
+        .map(|monitor| {
+            connection.send_request(&x::GetAtomName {
+                atom: monitor.name(),
+            })
+        })
+        .collect::<Vec<_>>();
+
+    monitors
+        .monitors()
+        .zip(names)
+        .map(|(monitor, name)| {
+            let name = connection.wait_for_reply(name).map_err(Error::from)?;
+
+            Ok(Monitor {
+                name: name.name().to_utf8().into_owned(),
+                rect: Rectangle {
+                    x: monitor.x() as _,
+                    y: monitor.y() as _,
+                    w: monitor.width() as _,
+                    h: monitor.height() as _,
+                },
+                is_primary: monitor.primary(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the rect of the monitor picked by `selector`
+fn retrieve_bounds_of_selected_monitor(
+    connection: &xcb::Connection,
+    selector: &MonitorSelector,
+    window: XWindow,
+) -> Result<Rectangle> {
+    let monitors = retrieve_monitors(connection, window)?;
+
+    selector
+        .select(&monitors)
+        .map(|monitor| monitor.rect)
+        .ok_or_else(|| super::Error::NoSuchMonitor(selector.to_string()))
+}
+
+pub(super) fn monitors() -> Result<Vec<Monitor>> {
+    let (connection, _) = xcb::Connection::connect(None).map_err(Error::from)?;
+
+    let root = connection
+        .get_setup()
+        .roots()
+        .next()
+        .ok_or(Error::FailedToGetRootWindow)?
+        .root();
+
+    retrieve_monitors(&connection, root)
+}
+
+fn retrieve_bounds_of_monitor_under_cursor(
+    connection: &xcb::Connection,
+    cursor_position: Point,
+    window: XWindow,
+) -> Result<Rectangle> {
+    let monitors = retrieve_bounds_of_all_monitors(connection, window)?;
+
+    if let Some(monitor) = monitors
+        .into_iter()
+        .find(|monitor| monitor.contains(cursor_position))
+    {
+        return Ok(monitor);
+    }
+
+    tracing::error!("Failed to find the monitor under the cursor. (How?!)");
+    unreachable!()
+}
+
+/// Asks the window manager to make the (fullscreen) window span all monitors, instead of only the
+/// one it is currently on.
+///
+/// This is done through _NET_WM_FULLSCREEN_MONITORS, which takes the indices of the monitors that
+/// define the top, bottom, left and right edges of the fullscreen window.
+///
+/// https://specifications.freedesktop.org/wm-spec/latest/ar01s06.html#id-1.7.6
+pub(super) fn span_window_across_all_monitors(window: u32) -> Result<()> {
+    let (connection, _) = xcb::Connection::connect(None).map_err(Error::from)?;
+
+    let root = connection
+        .get_setup()
+        .roots()
+        .next()
+        .ok_or(Error::FailedToGetRootWindow)?
+        .root();
+
+    let fullscreen_monitors = connection.send_request(&x::InternAtom {
+        only_if_exists: true,
+        name: b"_NET_WM_FULLSCREEN_MONITORS",
+    });
+    let fullscreen_monitors = connection
+        .wait_for_reply(fullscreen_monitors)
+        .map_err(Error::from)?
+        .atom();
+
+    if fullscreen_monitors == ATOM_NONE {
+        tracing::info!(
+            "Your WM does not support _NET_WM_FULLSCREEN_MONITORS, so the editor will only cover one monitor"
+        );
+        return Ok(());
+    }
+
+    let monitors = retrieve_bounds_of_all_monitors(&connection, root)?;
+
+    let index_of_extreme = |key: fn(&Rectangle) -> f64, pick_max: bool| {
+        let monitors = monitors.iter().enumerate();
+        let extreme = if pick_max {
+            monitors.max_by(|(_, a), (_, b)| key(a).total_cmp(&key(b)))
+        } else {
+            monitors.min_by(|(_, a), (_, b)| key(a).total_cmp(&key(b)))
+        };
+
+        extreme.map_or(0, |(idx, _)| idx as u32)
+    };
+
+    let top = index_of_extreme(|m| m.y, false);
+    let bottom = index_of_extreme(|m| m.y + m.h, true);
+    let left = index_of_extreme(|m| m.x, false);
+    let right = index_of_extreme(|m| m.x + m.w, true);
+
+    // SAFETY: The window ID was handed to us by GDK, so it refers to an existing X window
+    let window = unsafe { XWindow::new(window) };
+    let event = x::ClientMessageEvent::new(
+        window,
+        fullscreen_monitors,
+        // The last item is the source indication, 1 means that we're a normal application
+        x::ClientMessageData::Data32([top, bottom, left, right, 1]),
+    );
+
+    connection.send_request(&x::SendEvent {
+        propagate: false,
+        destination: x::SendEventDest::Window(root),
+        event_mask: x::EventMask::SUBSTRUCTURE_NOTIFY | x::EventMask::SUBSTRUCTURE_REDIRECT,
+        event: &event,
+    });
+    connection.flush().map_err(Error::from)?;
+
+    Ok(())
+}
+
+pub(super) struct Recorder {
+    // Declared before the connection, as it has to be dropped first
+    shm_segment: Option<ShmSegment>,
+    connection: xcb::Connection,
+    root: XWindow,
+    pixel_format: PixelFormat,
+    region: Rectangle,
+    capture_mouse_cursor: bool,
+}
+
+impl Recorder {
+    pub(super) fn new(region: Rectangle) -> Result<Self> {
+        let (connection, _) = xcb::Connection::connect_with_extensions(
+            None,
+            &[],
+            &[
+                xcb::Extension::XFixes,
+                xcb::Extension::Test,
+                xcb::Extension::Shm,
+            ],
+        )
+        .map_err(Error::from)?;
+        let region = region.normalised();
+
+        let setup = connection.get_setup();
+        let root_screen = setup.roots().next().ok_or(Error::FailedToGetRootWindow)?;
+        let root = root_screen.root();
+        let pixel_format = PixelFormat::of_root(setup, root_screen)?;
+
+        let capture_mouse_cursor = Settings::open().capture_mouse_cursor();
+        if capture_mouse_cursor {
+            // We need to make the X server aware that we wish to use the XFIXES extension
+            let _query_version = connection.send_request(&xfixes::QueryVersion {
+                client_major_version: xfixes::MAJOR_VERSION,
+                client_minor_version: xfixes::MINOR_VERSION,
+            });
+        }
+
+        let shm_size = pixel_format.stride(region.w as usize) * region.h as usize;
+        let shm_segment = ShmSegment::new(&connection, shm_size)
+            .inspect_err(|why| tracing::info!("Not using MIT-SHM for recording: {why}"))
+            .ok();
+
+        Ok(Self {
+            shm_segment,
+            connection,
+            root,
+            pixel_format,
+            region,
+            capture_mouse_cursor,
+        })
+    }
+
+    pub(super) fn disable_cursor(&mut self) {
+        self.capture_mouse_cursor = false;
+    }
+
+    pub(super) fn capture_frame(&mut self) -> Result<Frame> {
+        let drawable = x::Drawable::Window(self.root);
+
+        let cursor = self
+            .capture_mouse_cursor
+            .then(|| self.connection.send_request(&xfixes::GetCursorImage {}));
+
+        // Frames are always 8 bits per channel, as that's all the formats we record to can hold
+        let width = self.region.w as usize;
+        let height = self.region.h as usize;
+        let pixel_format = self.pixel_format;
+        let to_frame_data = |image: &[u8]| {
+            let mut data = vec![0; 4 * width * height];
+            pixel_format.convert(
+                image,
+                width,
+                height,
+                &mut data,
+                4 * width,
+                CairoImageFormat::Rgb24,
+            );
+            data
+        };
+
+        let shm_image = self.shm_segment.as_mut().map(|segment| {
+            segment
+                .get_image(&self.connection, drawable, self.region)
+                .map(to_frame_data)
+        });
+        let mut data = match shm_image {
+            Some(Ok(data)) => data,
+            shm_image => {
+                if let Some(Err(why)) = shm_image {
+                    tracing::info!("Capturing through MIT-SHM failed, not using it: {why}");
+                    self.shm_segment = None;
+                }
+
+                to_frame_data(get_image(&self.connection, drawable, self.region)?.data())
+            }
         };
 
-        if monitor_rect.contains(cursor_position) {
-            return Ok(monitor_rect);
+        if let Some(cursor) = cursor {
+            match self.connection.wait_for_reply(cursor) {
+                Ok(cursor) => overlay_cursor(cursor, &mut data, self.region),
+                Err(why) => tracing::info!("Unable to fetch cursor data: {why:?}"),
+            }
         }
+
+        Ok(Frame {
+            width: self.region.w as u32,
+            height: self.region.h as u32,
+            data,
+        })
     }
 
-    tracing::error!("Failed to find any monitors. (How?!)");
-    unreachable!()
+    pub(super) fn scroll_down(&self) -> Result<()> {
+        // X11 reports scrolling down as presses of the fifth mouse button
+        const SCROLL_DOWN_BUTTON: u8 = 5;
+
+        if !self
+            .connection
+            .active_extensions()
+            .any(|extension| extension == xcb::Extension::Test)
+        {
+            return Err(Error::XTestUnsupported.into());
+        }
+
+        let Rectangle { x, y, w, h } = self.region;
+
+        // Scroll events go to the window under the cursor, which should be the one being captured
+        self.connection.send_request(&x::WarpPointer {
+            src_window: XWindow::none(),
+            dst_window: self.root,
+            src_x: 0,
+            src_y: 0,
+            src_width: 0,
+            src_height: 0,
+            dst_x: (x + w / 2.0) as _,
+            dst_y: (y + h / 2.0) as _,
+        });
+
+        for event in [x::ButtonPressEvent::NUMBER, x::ButtonReleaseEvent::NUMBER] {
+            self.connection.send_request(&xtest::FakeInput {
+                r#type: event as u8,
+                detail: SCROLL_DOWN_BUTTON,
+                time: x::CURRENT_TIME,
+                root: self.root,
+                root_x: 0,
+                root_y: 0,
+                deviceid: 0,
+            });
+        }
+
+        self.connection.flush().map_err(Error::from)?;
+
+        Ok(())
+    }
 }
 
+/// Captures the contents of a single window, even the parts of it that are covered by other windows.
+///
+/// This is done by asking the Composite extension for the off-screen pixmap backing the top-level
+/// window (i.e. the window manager's frame), so `with_decorations` only picks how much of that
+/// pixmap ends up in the returned image. If no compositing manager is running the window gets
+/// redirected for the duration of the capture, in that case the parts of it that were covered may
+/// not have been painted yet.
+///
+/// The returned image always has an alpha channel, pixels that lie outside the window's (bounding)
+/// shape are fully transparent.
+pub(super) fn capture_window(window: u32, with_decorations: bool) -> Result<ImageSurface> {
+    let (connection, _) = xcb::Connection::connect_with_extensions(
+        None,
+        &[],
+        &[xcb::Extension::Composite, xcb::Extension::Shape],
+    )
+    .map_err(Error::from)?;
+
+    if !connection
+        .active_extensions()
+        .any(|extension| extension == xcb::Extension::Composite)
+    {
+        return Err(Error::CompositeUnsupported.into());
+    }
+
+    // NameWindowPixmap requires at least version 0.2 of the extension
+    let query_version = connection.send_request(&composite::QueryVersion {
+        client_major_version: composite::MAJOR_VERSION,
+        client_minor_version: composite::MINOR_VERSION,
+    });
+    connection
+        .wait_for_reply(query_version)
+        .map_err(Error::from)?;
+
+    // SAFETY: The window ID comes from either `get_windows` or the user, in the latter case we'd
+    //         get a BadWindow error from the X server if it's invalid, which is handled like any
+    //         other protocol error
+    let client = unsafe { XWindow::new(window) };
+    let top_level = retrieve_top_level_window(&connection, client)?;
+
+    let geometry = connection.send_request(&x::GetGeometry {
+        drawable: x::Drawable::Window(top_level),
+    });
+    let attributes = connection.send_request(&x::GetWindowAttributes { window: top_level });
+    let geometry = connection.wait_for_reply(geometry).map_err(Error::from)?;
+    let attributes = connection.wait_for_reply(attributes).map_err(Error::from)?;
+
+    // Windows with a depth of 32 bits use ARGB visuals, whose pixels are laid out like ARgb32
+    // already. The pixels of other windows have to be converted.
+    let pixel_format = match geometry.depth() {
+        32 => None,
+        depth => Some(PixelFormat::of_visual(
+            connection.get_setup(),
+            depth,
+            attributes.visual(),
+        )?),
+    };
+
+    // The pixmap includes the border of the window, but we're not interested in that
+    let border = geometry.border_width() as i16;
+    let mut area = x::Rectangle {
+        x: border,
+        y: border,
+        width: geometry.width(),
+        height: geometry.height(),
+    };
+    let mut shaped_window = top_level;
+    // The position of the captured area inside the coordinate space used by the shape of `shaped_window`
+    let mut shape_offset = (0, 0);
+
+    if !with_decorations && client != top_level {
+        let client_extents = connection.send_request(&shape::QueryExtents {
+            destination_window: client,
+        });
+        let client_extents = connection
+            .wait_for_reply(client_extents)
+            .map_err(Error::from)?;
+
+        let client_position = connection.send_request(&x::TranslateCoordinates {
+            src_window: client,
+            dst_window: top_level,
+            src_x: client_extents.bounding_shape_extents_x(),
+            src_y: client_extents.bounding_shape_extents_y(),
+        });
+        let client_position = connection
+            .wait_for_reply(client_position)
+            .map_err(Error::from)?;
+
+        area = x::Rectangle {
+            x: border + client_position.dst_x(),
+            y: border + client_position.dst_y(),
+            width: client_extents.bounding_shape_extents_width(),
+            height: client_extents.bounding_shape_extents_height(),
+        };
+        shaped_window = client;
+        shape_offset = (
+            client_extents.bounding_shape_extents_x(),
+            client_extents.bounding_shape_extents_y(),
+        );
+    }
+
+    // Redirecting a window that a compositing manager has already redirected is harmless, the X
+    // server keeps track of who redirected what
+    connection
+        .send_and_check_request(&composite::RedirectWindow {
+            window: top_level,
+            update: composite::Redirect::Automatic,
+        })
+        .map_err(Error::XcbProtocol)?;
+
+    let pixmap: x::Pixmap = connection.generate_id();
+    let named_pixmap = connection.send_and_check_request(&composite::NameWindowPixmap {
+        window: top_level,
+        pixmap,
+    });
+
+    let image = named_pixmap.map_err(Error::XcbProtocol).and_then(|()| {
+        let image = connection.send_request(&x::GetImage {
+            format: XImageFormat::ZPixmap,
+            drawable: x::Drawable::Pixmap(pixmap),
+            x: area.x,
+            y: area.y,
+            width: area.width,
+            height: area.height,
+            plane_mask: u32::MAX,
+        });
+        let image = connection.wait_for_reply(image).map_err(Error::from);
+
+        connection.send_request(&x::FreePixmap { pixmap });
+
+        image
+    });
+
+    connection.send_request(&composite::UnredirectWindow {
+        window: top_level,
+        update: composite::Redirect::Automatic,
+    });
+    connection.flush().map_err(Error::from)?;
+
+    let image = image?;
+    let mut data = match pixel_format {
+        Some(pixel_format) => {
+            let width = area.width as usize;
+            let height = area.height as usize;
+            let mut data = vec![0; 4 * width * height];
+            pixel_format.convert(
+                image.data(),
+                width,
+                height,
+                &mut data,
+                4 * width,
+                CairoImageFormat::Rgb24,
+            );
+
+            // The unused byte may contain anything, but we want the window to be opaque
+            for pixel in data.chunks_exact_mut(4) {
+                pixel[3] = 0xff;
+            }
+
+            data
+        }
+        None => image.data().to_vec(),
+    };
+
+    // Shaped windows (e.g. ones with rounded corners that don't use an ARGB visual) would otherwise
+    // have whatever was left in the pixmap outside of their shape
+    if connection
+        .active_extensions()
+        .any(|extension| extension == xcb::Extension::Shape)
+    {
+        mask_out_of_shape_pixels(&connection, shaped_window, &mut data, area, shape_offset)?;
+    }
+
+    let width = area.width as i32;
+    let height = area.height as i32;
+    let stride = CairoImageFormat::ARgb32.stride_for_width(area.width as u32)?;
+
+    Ok(ImageSurface::create_for_data(
+        data,
+        CairoImageFormat::ARgb32,
+        width,
+        height,
+        stride,
+    )?)
+}
+
+/// Returns the ancestor of `window` that is a child of the root window, this is the window that
+/// gets redirected by the Composite extension.
+///
+/// For reparenting window managers, this is the frame that contains the decorations.
+fn retrieve_top_level_window(connection: &xcb::Connection, window: XWindow) -> Result<XWindow> {
+    let mut window = window;
+
+    loop {
+        let tree = connection.send_request(&x::QueryTree { window });
+        let tree = connection.wait_for_reply(tree).map_err(Error::from)?;
+
+        if tree.parent() == tree.root() || tree.parent().is_none() {
+            return Ok(window);
+        }
+
+        window = tree.parent();
+    }
+}
+
+/// Makes all the pixels of `data` which are outside of the bounding shape of `window` transparent.
+///
+/// `offset` is the position of the top left corner of `data` inside the coordinate space used by the
+/// shape of `window`.
+fn mask_out_of_shape_pixels(
+    connection: &xcb::Connection,
+    window: XWindow,
+    data: &mut [u8],
+    area: x::Rectangle,
+    offset: (i16, i16),
+) -> Result<()> {
+    let extents = connection.send_request(&shape::QueryExtents {
+        destination_window: window,
+    });
+    let extents = connection.wait_for_reply(extents).map_err(Error::from)?;
+
+    if !extents.bounding_shaped() {
+        return Ok(());
+    }
+
+    let rectangles = connection.send_request(&shape::GetRectangles {
+        window,
+        source_kind: shape::Sk::Bounding,
+    });
+    let rectangles = connection.wait_for_reply(rectangles).map_err(Error::from)?;
+
+    let width = area.width as i32;
+    let height = area.height as i32;
+    let mut visible = vec![false; width as usize * height as usize];
+
+    for rectangle in rectangles.rectangles() {
+        // Rectangles that start before the captured area get cut off, so the far edges must be
+        // computed before clamping
+        let start_x = rectangle.x as i32 - offset.0 as i32;
+        let start_y = rectangle.y as i32 - offset.1 as i32;
+        let left = start_x.clamp(0, width);
+        let top = start_y.clamp(0, height);
+        let right = (start_x + rectangle.width as i32).clamp(0, width);
+        let bottom = (start_y + rectangle.height as i32).clamp(0, height);
+
+        for y in top..bottom {
+            let row = (y * width) as usize;
+            visible[row + left as usize..row + right as usize].fill(true);
+        }
+    }
+
+    for (pixel, visible) in data.chunks_exact_mut(4).zip(visible) {
+        if !visible {
+            // Cairo uses premultiplied alpha, so transparent pixels must be all zeroes
+            pixel.fill(0);
+        }
+    }
+
+    Ok(())
+}
+
+/// Turns the cursor image from XFIXES into a [`Cursor`] positioned relative to `bounds`, returns
+/// `None` if the cursor is outside of `bounds`.
+fn cursor_from_reply(
+    cursor: &xfixes::GetCursorImageReply,
+    bounds: Rectangle,
+) -> Result<Option<Cursor>> {
+    if !bounds.contains(Point {
+        x: cursor.x() as _,
+        y: cursor.y() as _,
+    }) {
+        return Ok(None);
+    }
+
+    // XFIXES gives us premultiplied ARGB pixels, which is exactly what cairo wants, save for them
+    // being packed in u32s
+    let data = cursor
+        .cursor_image()
+        .iter()
+        .flat_map(|pixel| pixel.to_ne_bytes())
+        .collect::<Vec<_>>();
+
+    // Rows of ARGB32 pixels are always aligned already, so cairo doesn't add any padding to them
+    let width = cursor.width() as u32;
+    let stride = CairoImageFormat::ARgb32.stride_for_width(width)?;
+
+    let image = ImageSurface::create_for_data(
+        data,
+        CairoImageFormat::ARgb32,
+        width as i32,
+        cursor.height() as i32,
+        stride,
+    )?;
+
+    Ok(Some(Cursor {
+        image,
+        position: Point {
+            x: cursor.x() as f64 - cursor.xhot() as f64 - bounds.x,
+            y: cursor.y() as f64 - cursor.yhot() as f64 - bounds.y,
+        },
+        hotspot: Point {
+            x: cursor.xhot() as _,
+            y: cursor.yhot() as _,
+        },
+    }))
+}
+
+/// Draws the cursor on top of `screenshot`, whose pixels are in the layout of
+/// [`CairoImageFormat::Rgb24`]
 fn overlay_cursor(cursor: xfixes::GetCursorImageReply, screenshot: &mut [u8], bounds: Rectangle) {
+    // The cursor may be on a monitor (or outside of a recorded region) that we didn't capture
+    if !bounds.contains(Point {
+        x: cursor.x() as _,
+        y: cursor.y() as _,
+    }) {
+        return;
+    }
+
     // These computations give us the coords of the top left corner of the mouse cursor
     // We use saturating arithmetic because cursor.{x,y}() may be smaller than cursor.{x,y}hot() when
     // the cursor is close to the left and top edges of the screen
-    let cx = (cursor.x() as usize).saturating_sub(cursor.xhot() as usize) - bounds.x as usize;
-    let cy = (cursor.y() as usize).saturating_sub(cursor.yhot() as usize) - bounds.y as usize;
+    let cx = (cursor.x() as usize)
+        .saturating_sub(cursor.xhot() as usize)
+        .saturating_sub(bounds.x as usize);
+    let cy = (cursor.y() as usize)
+        .saturating_sub(cursor.yhot() as usize)
+        .saturating_sub(bounds.y as usize);
 
     let w = cursor.width() as usize;
     let h = cursor.height() as usize;
@@ -196,16 +987,17 @@ fn overlay_cursor(cursor: xfixes::GetCursorImageReply, screenshot: &mut [u8], bo
                     |target, source, alpha| target + (source * (255 - alpha) + 255 / 2) / 255;
                 screenshot[pixel_idx + 0] = blend(r, screenshot[pixel_idx + 0] as u32, a) as u8;
                 screenshot[pixel_idx + 1] = blend(g, screenshot[pixel_idx + 1] as u32, a) as u8;
-                screenshot[pixel_idx + 2] = blend(b, screenshot[pixel_idx + 1] as u32, a) as u8;
+                screenshot[pixel_idx + 2] = blend(b, screenshot[pixel_idx + 2] as u32, a) as u8;
             };
         }
     }
 }
 
 xcb::atoms_struct! {
-    /// This structs contains the atoms we'll use multiple times over the course of the program and as
-    /// such are cached. None of the atoms here will ever be [`xcb::x::ATOM_NONE`]
-    #[derive(Debug)]
+    /// This structs contains the atoms we'll use multiple times over the course of the program. Atoms
+    /// that no client has used are [`xcb::x::ATOM_NONE`], this is usually the case for the EWMH ones
+    /// when the WM doesn't support EWMH.
+    #[derive(Clone, Copy, Debug)]
     struct AtomsOfInterest {
         /// This corresponds to _NET_CLIENT_LIST_STACKING, querying this property on the root window
         /// gives us the list of windows in stacking order.
@@ -229,62 +1021,50 @@ xcb::atoms_struct! {
         /// https://specifications.freedesktop.org/wm-spec/latest/ar01s05.html#idm46476783496896
         /// (Same as above spec link)
         window_is_fullscreen => b"_NET_WM_STATE_FULLSCREEN",
+        /// This corresponds to _NET_ACTIVE_WINDOW, querying this property on the root window gives
+        /// us the window that has the input focus.
+        ///
+        /// https://specifications.freedesktop.org/wm-spec/latest/ar01s03.html#id-1.4.10
+        active_window => b"_NET_ACTIVE_WINDOW",
+        /// This corresponds to _NET_WM_NAME, the UTF-8 title of a window. Windows that don't set it
+        /// only have the older WM_NAME property.
+        ///
+        /// https://specifications.freedesktop.org/wm-spec/latest/ar01s05.html#id-1.6.2
+        window_name => b"_NET_WM_NAME",
+        /// The type of _NET_WM_NAME
+        utf8_string => b"UTF8_STRING",
+        /// This corresponds to _NET_WM_PID, the ID of the process that owns a window.
+        ///
+        /// https://specifications.freedesktop.org/wm-spec/latest/ar01s05.html#id-1.6.14
+        window_pid => b"_NET_WM_PID",
     }
 }
 
 impl AtomsOfInterest {
-    fn get(connection: &xcb::Connection) -> Result<&Self> {
-        static ATOMS_OF_INTEREST: OnceLock<AtomsOfInterest> = OnceLock::new();
-
-        let init = || {
-            let Self {
-                wm_client_list,
-                frame_extents,
-                window_state,
-                window_is_fullscreen,
-            } = Self::intern_all(connection).map_err(Error::from)?;
-
-            if wm_client_list == ATOM_NONE {
-                return Err(Error::WmDoesNotSupportWindowList);
-            }
-
-            if [frame_extents, window_state, window_is_fullscreen].contains(&ATOM_NONE) {
-                return Err(Error::WmDoesNotSupportFrameExtents);
-            }
-
-            Ok(Self {
-                wm_client_list,
-                frame_extents,
-                window_state,
-                window_is_fullscreen,
-            })
-        };
-
-        Ok(if let Some(val) = ATOMS_OF_INTEREST.get() {
-            val
-        } else {
-            ATOMS_OF_INTEREST
-                .set(init()?)
-                .expect("ATOMS_OF_INTEREST cannot be initialised at this point");
-            ATOMS_OF_INTEREST.get().unwrap()
-        })
+    /// The atoms are interned again for every connection rather than cached, as the ones that were
+    /// [`xcb::x::ATOM_NONE`] before (e.g. because kcshot was started before the WM) may exist by now
+    fn intern(connection: &xcb::Connection) -> Result<Self> {
+        Ok(Self::intern_all(connection).map_err(Error::from)?)
     }
 }
 
 /// Obtains a list of all windows from the display server, the list is in stacking order.
-pub(super) fn get_windows() -> Result<Vec<Window>> {
-    // Requires an WM that supports EWMH. Will gracefully fallback if not available
-
-    let wm_features = WmFeatures::get()?;
-
-    if !wm_features.can_retrieve_windows() {
-        return Err(Error::WmDoesNotSupportWindowList.into());
-    }
+///
+/// The rects of the windows are relative to the area that would be captured for `target`.
+pub(super) fn get_windows(target: &CaptureTarget) -> Result<Vec<Window>> {
+    // WMs that support EWMH tell us which windows they manage, for the others we have to look
+    // through the children of the root window ourselves
+    let supports_client_list = matches!(
+        WmFeatures::get()?.display_server_kind,
+        DisplayServerKind::X11 {
+            supports_client_list: true
+        }
+    );
 
     let (connection, _) = xcb::Connection::connect(None).map_err(Error::from)?;
     let setup = connection.get_setup();
 
-    let &AtomsOfInterest { wm_client_list, .. } = AtomsOfInterest::get(&connection)?;
+    let atoms = AtomsOfInterest::intern(&connection)?;
 
     for root_screen in setup.roots() {
         let root_window = root_screen.root();
@@ -296,54 +1076,38 @@ pub(super) fn get_windows() -> Result<Vec<Window>> {
             .wait_for_reply(pointer_cookie)
             .map_err(Error::from)?;
         if pointer_reply.same_screen() {
-            let list = connection.send_request(&x::GetProperty {
-                delete: false,
-                window: root_window,
-                property: wm_client_list,
-                r#type: ATOM_WINDOW,
-                long_offset: 0,
-                long_length: 128, // If the user has more than 128 windows on their desktop, that's their problem really
-            });
-            let list = connection.wait_for_reply(list).map_err(Error::from)?;
+            let capture_bounds = retrieve_capture_bounds(
+                &connection,
+                target,
+                Point {
+                    x: pointer_reply.root_x() as _,
+                    y: pointer_reply.root_y() as _,
+                },
+                root_window,
+            )?;
 
-            let mut windows = Vec::with_capacity(128);
+            let list = if supports_client_list {
+                retrieve_client_list(&connection, root_window, atoms.wm_client_list)?
+            } else {
+                retrieve_top_level_windows(&connection, root_window)?
+            };
+
+            let mut windows = Vec::with_capacity(list.len());
 
-            for window in list.value::<XWindow>().iter().copied() {
+            for window in list {
                 let attributes = connection.send_request(&x::GetWindowAttributes { window });
                 let attributes = connection.wait_for_reply(attributes).map_err(Error::from)?;
                 if attributes.map_state() != MapState::Viewable {
                     continue;
                 }
 
-                let window_extents = connection.send_request(&shape::QueryExtents {
-                    destination_window: window,
-                });
-                let window_extents = connection
-                    .wait_for_reply(window_extents)
-                    .map_err(Error::from)?;
-
-                let translated_window_coords = connection.send_request(&x::TranslateCoordinates {
-                    src_window: window,
-                    dst_window: root_window,
-                    src_x: window_extents.bounding_shape_extents_x(),
-                    src_y: window_extents.bounding_shape_extents_y(),
-                });
-
-                let translated_window_coords = connection
-                    .wait_for_reply(translated_window_coords)
-                    .map_err(Error::from)?;
-
-                let content_rect = Rectangle {
-                    x: translated_window_coords.dst_x() as f64,
-                    y: translated_window_coords.dst_y() as f64,
-                    w: window_extents.bounding_shape_extents_width() as f64,
-                    h: window_extents.bounding_shape_extents_height() as f64,
-                };
-
-                windows.push(Window {
-                    outer_rect: get_window_outer_rect(&connection, content_rect, window)?,
-                    content_rect,
-                });
+                windows.push(retrieve_window(
+                    &connection,
+                    &atoms,
+                    root_window,
+                    window,
+                    capture_bounds,
+                )?);
             }
 
             return Ok(windows);
@@ -353,6 +1117,289 @@ pub(super) fn get_windows() -> Result<Vec<Window>> {
     Err(super::Error::FailedToGetWindows)
 }
 
+/// Reads the whole of the `client_list` property of `root`, a page at a time, as the amount of
+/// windows the user has open isn't known beforehand
+fn retrieve_client_list(
+    connection: &xcb::Connection,
+    root: XWindow,
+    client_list: x::Atom,
+) -> Result<Vec<XWindow>> {
+    // This is in 32-bit units, i.e. in windows
+    const PAGE_LENGTH: u32 = 128;
+
+    let mut windows = Vec::with_capacity(PAGE_LENGTH as usize);
+
+    loop {
+        let page = connection.send_request(&x::GetProperty {
+            delete: false,
+            window: root,
+            property: client_list,
+            r#type: ATOM_WINDOW,
+            long_offset: windows.len() as u32,
+            long_length: PAGE_LENGTH,
+        });
+        let page = connection.wait_for_reply(page).map_err(Error::from)?;
+
+        windows.extend_from_slice(page.value::<XWindow>());
+
+        if page.bytes_after() == 0 || page.value::<XWindow>().is_empty() {
+            return Ok(windows);
+        }
+    }
+}
+
+/// Finds the top-level windows of `root` by walking its children, for WMs that don't maintain a
+/// client list. Windows are returned in stacking order, bottom-most first, like the client list.
+///
+/// Reparenting WMs put client windows inside of frame windows, so what we find here are the frames,
+/// which is what the user sees as the window anyway.
+fn retrieve_top_level_windows(connection: &xcb::Connection, root: XWindow) -> Result<Vec<XWindow>> {
+    let tree = connection.send_request(&x::QueryTree { window: root });
+    let tree = connection.wait_for_reply(tree).map_err(Error::from)?;
+
+    let attributes = tree
+        .children()
+        .iter()
+        .map(|&window| {
+            (
+                window,
+                connection.send_request(&x::GetWindowAttributes { window }),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut windows = Vec::with_capacity(attributes.len());
+    for (window, attributes) in attributes {
+        // The window may have been destroyed since we've queried the tree
+        let Ok(attributes) = connection.wait_for_reply(attributes) else {
+            continue;
+        };
+
+        // Override-redirect windows are menus, tooltips and the like, which aren't managed by the
+        // WM and aren't what one means by "window"
+        if attributes.map_state() == MapState::Viewable
+            && !attributes.override_redirect()
+            && attributes.class() == x::WindowClass::InputOutput
+        {
+            windows.push(window);
+        }
+    }
+
+    Ok(windows)
+}
+
+/// Captures `area` of `drawable` by having the X server send it to us
+fn get_image(
+    connection: &xcb::Connection,
+    drawable: x::Drawable,
+    area: Rectangle,
+) -> Result<x::GetImageReply> {
+    let image = connection.send_request(&x::GetImage {
+        format: XImageFormat::ZPixmap,
+        drawable,
+        x: area.x as _,
+        y: area.y as _,
+        width: area.w as _,
+        height: area.h as _,
+        plane_mask: u32::MAX,
+    });
+
+    Ok(connection.wait_for_reply(image).map_err(Error::from)?)
+}
+
+/// Retrieves the rects of `window`, relative to `capture_bounds`, along with its properties
+fn retrieve_window(
+    connection: &xcb::Connection,
+    atoms: &AtomsOfInterest,
+    root_window: XWindow,
+    window: XWindow,
+    capture_bounds: Rectangle,
+) -> Result<Window> {
+    let window_extents = connection.send_request(&shape::QueryExtents {
+        destination_window: window,
+    });
+    let window_extents = connection
+        .wait_for_reply(window_extents)
+        .map_err(Error::from)?;
+
+    let translated_window_coords = connection.send_request(&x::TranslateCoordinates {
+        src_window: window,
+        dst_window: root_window,
+        src_x: window_extents.bounding_shape_extents_x(),
+        src_y: window_extents.bounding_shape_extents_y(),
+    });
+
+    let translated_window_coords = connection
+        .wait_for_reply(translated_window_coords)
+        .map_err(Error::from)?;
+
+    let content_rect = Rectangle {
+        x: translated_window_coords.dst_x() as f64,
+        y: translated_window_coords.dst_y() as f64,
+        w: window_extents.bounding_shape_extents_width() as f64,
+        h: window_extents.bounding_shape_extents_height() as f64,
+    };
+
+    let outer_rect = get_window_outer_rect(connection, atoms, content_rect, window)?;
+    let WindowProperties { title, class, pid } =
+        retrieve_window_properties(connection, atoms, window)?;
+
+    // The bounding shape may not start at the origin of the window, while children are positioned
+    // relative to the origin
+    let origin = Point {
+        x: content_rect.x - window_extents.bounding_shape_extents_x() as f64,
+        y: content_rect.y - window_extents.bounding_shape_extents_y() as f64,
+    };
+    let children = retrieve_child_rects(connection, window, origin)?
+        .into_iter()
+        .map(|child| relative_to(child, capture_bounds))
+        .collect();
+
+    Ok(Window {
+        outer_rect: relative_to(outer_rect, capture_bounds),
+        content_rect: relative_to(content_rect, capture_bounds),
+        id: Some(window.resource_id()),
+        title,
+        class,
+        pid,
+        children,
+    })
+}
+
+/// Retrieves the rects of the mapped children of `window`, whose origin is at `origin` on the
+/// screen. Only direct children are looked at, as toolkits that still use child windows seldom
+/// nest them deeply.
+fn retrieve_child_rects(
+    connection: &xcb::Connection,
+    window: XWindow,
+    origin: Point,
+) -> Result<Vec<Rectangle>> {
+    let tree = connection.send_request(&x::QueryTree { window });
+    let tree = connection.wait_for_reply(tree).map_err(Error::from)?;
+
+    let cookies = tree
+        .children()
+        .iter()
+        .map(|&window| {
+            (
+                connection.send_request(&x::GetWindowAttributes { window }),
+                connection.send_request(&x::GetGeometry {
+                    drawable: x::Drawable::Window(window),
+                }),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut children = Vec::with_capacity(cookies.len());
+    for (attributes, geometry) in cookies {
+        // Children may go away while we're looking at them, which is fine
+        let (Ok(attributes), Ok(geometry)) = (
+            connection.wait_for_reply(attributes),
+            connection.wait_for_reply(geometry),
+        ) else {
+            continue;
+        };
+
+        if attributes.map_state() != MapState::Viewable
+            || attributes.class() != x::WindowClass::InputOutput
+        {
+            continue;
+        }
+
+        // The position is that of the top left corner of the border, which isn't part of the size
+        let border_width = geometry.border_width() as f64;
+        children.push(Rectangle {
+            x: origin.x + geometry.x() as f64 + border_width,
+            y: origin.y + geometry.y() as f64 + border_width,
+            w: geometry.width() as f64,
+            h: geometry.height() as f64,
+        });
+    }
+
+    Ok(children)
+}
+
+/// The properties of a window which tell the user what it is
+struct WindowProperties {
+    title: Option<String>,
+    class: Option<String>,
+    pid: Option<u32>,
+}
+
+/// Retrieves the title, class and PID of `window`. Windows aren't required to set any of these, so
+/// missing properties are not an error.
+fn retrieve_window_properties(
+    connection: &xcb::Connection,
+    atoms: &AtomsOfInterest,
+    window: XWindow,
+) -> Result<WindowProperties> {
+    let &AtomsOfInterest {
+        window_name,
+        utf8_string,
+        window_pid,
+        ..
+    } = atoms;
+
+    let get_property = |property, r#type| {
+        connection.send_request(&x::GetProperty {
+            delete: false,
+            window,
+            property,
+            r#type,
+            long_offset: 0,
+            // In 32-bit units, so titles get cut off after 4KiB
+            long_length: 1024,
+        })
+    };
+
+    let net_wm_name = (window_name != ATOM_NONE).then(|| get_property(window_name, utf8_string));
+    let wm_name = get_property(ATOM_WM_NAME, ATOM_STRING);
+    let wm_class = get_property(ATOM_WM_CLASS, ATOM_STRING);
+    let wm_pid = (window_pid != ATOM_NONE).then(|| get_property(window_pid, ATOM_CARDINAL));
+
+    let to_string =
+        |bytes: &[u8]| (!bytes.is_empty()).then(|| String::from_utf8_lossy(bytes).into_owned());
+
+    let mut title = None;
+    if let Some(net_wm_name) = net_wm_name {
+        let net_wm_name = connection
+            .wait_for_reply(net_wm_name)
+            .map_err(Error::from)?;
+        title = to_string(net_wm_name.value());
+    }
+    // WM_NAME is meant to be Latin-1, but in practice it's either ASCII or UTF-8
+    let wm_name = connection.wait_for_reply(wm_name).map_err(Error::from)?;
+    let title = title.or_else(|| to_string(wm_name.value()));
+
+    // WM_CLASS holds two null-terminated strings: the instance name and the class name
+    let wm_class = connection.wait_for_reply(wm_class).map_err(Error::from)?;
+    let class = wm_class
+        .value::<u8>()
+        .split(|&byte| byte == 0)
+        .nth(1)
+        .and_then(to_string);
+
+    let pid = match wm_pid {
+        Some(wm_pid) => {
+            let wm_pid = connection.wait_for_reply(wm_pid).map_err(Error::from)?;
+            wm_pid.value::<u32>().first().copied()
+        }
+        None => None,
+    };
+
+    Ok(WindowProperties { title, class, pid })
+}
+
+/// Translates `rect` from root window coordinates to coordinates relative to the top left corner
+/// of `bounds`
+fn relative_to(rect: Rectangle, bounds: Rectangle) -> Rectangle {
+    Rectangle {
+        x: rect.x - bounds.x,
+        y: rect.y - bounds.y,
+        ..rect
+    }
+}
+
 /// Returns the outer rect of a window
 ///
 /// The outer rect is the content rect expanded to include window borders (usually decorations)
@@ -361,6 +1408,7 @@ pub(super) fn get_windows() -> Result<Vec<Window>> {
 /// * the window is fullscreen
 fn get_window_outer_rect(
     connection: &xcb::Connection,
+    atoms: &AtomsOfInterest,
     content_rect: Rectangle,
     window: XWindow,
 ) -> Result<Rectangle> {
@@ -369,7 +1417,12 @@ fn get_window_outer_rect(
         window_state,
         window_is_fullscreen,
         ..
-    } = AtomsOfInterest::get(connection)?;
+    } = atoms;
+
+    // Without EWMH there's nothing to ask the WM about, the windows we get then are frames already
+    if frame_extents == ATOM_NONE || window_state == ATOM_NONE {
+        return Ok(content_rect);
+    }
 
     let frame_extents = connection.send_request(&x::GetProperty {
         delete: false,
@@ -409,12 +1462,13 @@ fn get_window_outer_rect(
         return Ok(content_rect);
     }
 
-    let Rectangle { x, y, w, h } = content_rect;
+    // Windows the WM doesn't decorate may not have the property at all
+    let &[left, right, top, bottom] = frame_extents.value::<u32>() else {
+        return Ok(content_rect);
+    };
+    let [left, right, top, bottom] = [left, right, top, bottom].map(|extent| extent as f64);
 
-    let left = frame_extents.value::<u32>()[0] as f64;
-    let right = frame_extents.value::<u32>()[1] as f64;
-    let top = frame_extents.value::<u32>()[2] as f64;
-    let bottom = frame_extents.value::<u32>()[3] as f64;
+    let Rectangle { x, y, w, h } = content_rect;
 
     Ok(Rectangle {
         x: x - left,
@@ -436,23 +1490,20 @@ pub(super) fn get_wm_features() -> Result<WmFeatures> {
     let supported_ewmh_atoms = connection
         .wait_for_reply(supported_ewmh_atoms)
         .map_err(Error::from)?;
-    let AtomsOfInterest {
-        wm_client_list,
-        frame_extents,
-        ..
-    } = AtomsOfInterest::get(&connection)?;
+    let AtomsOfInterest { wm_client_list, .. } = AtomsOfInterest::intern(&connection)?;
 
     // NOTE: This sets WmFeatures::is_wayland to false
     let mut wm_features = WmFeatures {
         display_server_kind: DisplayServerKind::X11 {
-            can_retrieve_windows: false,
+            supports_client_list: false,
         },
         should_use_portals: false,
+        can_use_screencopy: false,
     };
 
     if supported_ewmh_atoms.atom() == ATOM_NONE {
         tracing::info!(
-            "Your WM does not support EWMH, so kcshot won't be able to retrieve window rects"
+            "Your WM does not support EWMH, so kcshot will look for windows without its help"
         );
         return Ok(wm_features);
     }
@@ -469,25 +1520,18 @@ pub(super) fn get_wm_features() -> Result<WmFeatures> {
         property: supported_ewmh_atoms.atom(),
         r#type: ATOM_ATOM,
         long_offset: 0,
-        long_length: 50, // I think the spec defines less than this, but it's (hopefully) fine
+        long_length: 256, // The spec defines fewer than this, WMs may add a few of their own
     });
     let supported_ewmh_atoms = connection
         .wait_for_reply(supported_ewmh_atoms)
         .map_err(Error::from)?;
 
-    let mut wm_supports_retrieving_client_list = false;
-    let mut wm_supports_retrieving_window_rects = false;
-    for atom in supported_ewmh_atoms.value::<x::Atom>() {
-        if atom == wm_client_list {
-            wm_supports_retrieving_client_list = true;
-        } else if atom == frame_extents {
-            wm_supports_retrieving_window_rects = true;
-        }
-    }
+    let supports_client_list = supported_ewmh_atoms
+        .value::<x::Atom>()
+        .contains(wm_client_list);
 
     wm_features.display_server_kind = DisplayServerKind::X11 {
-        can_retrieve_windows: wm_supports_retrieving_client_list
-            && wm_supports_retrieving_window_rects,
+        supports_client_list,
     };
 
     Ok(wm_features)
@@ -0,0 +1,425 @@
+//! Converts the pixels the X server hands us into the layouts cairo understands.
+//!
+//! `GetImage` returns pixels in the format of the visual of the captured drawable, which is usually
+//! 24-bit BGRX, and so identical to [`cairo::Format::Rgb24`]. Deep colour displays use 30-bit
+//! visuals however, whose pixels match [`cairo::Format::Rgb30`] instead, and older or more unusual
+//! setups may use 16-bit visuals or other channel orders.
+
+use cairo::Format as CairoImageFormat;
+use kcshot_data::geometry::Rectangle;
+use xcb::x::{self, ImageOrder, VisualClass};
+
+use super::Error;
+use crate::Result;
+
+/// Describes how the pixels of a visual are laid out in memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct PixelFormat {
+    pub(super) bits_per_pixel: u8,
+    /// Rows are padded to a multiple of this many bits
+    pub(super) scanline_pad: u8,
+    pub(super) red_mask: u32,
+    pub(super) green_mask: u32,
+    pub(super) blue_mask: u32,
+    pub(super) byte_order: ImageOrder,
+}
+
+impl PixelFormat {
+    /// Returns the format of the pixels of `visual`, which has a depth of `depth`
+    pub(super) fn of_visual(setup: &x::Setup, depth: u8, visual: x::Visualid) -> Result<Self> {
+        let unsupported = || Error::UnsupportedVisual { depth, visual };
+
+        let visual_type = setup
+            .roots()
+            .flat_map(|screen| screen.allowed_depths())
+            .filter(|allowed_depth| allowed_depth.depth() == depth)
+            .flat_map(x::Depth::visuals)
+            .find(|visual_type| visual_type.visual_id() == visual)
+            .ok_or_else(unsupported)?;
+
+        // Other classes use colour maps, which nobody has used in decades
+        if ![VisualClass::TrueColor, VisualClass::DirectColor].contains(&visual_type.class()) {
+            return Err(unsupported().into());
+        }
+
+        let pixmap_format = setup
+            .pixmap_formats()
+            .iter()
+            .find(|format| format.depth() == depth)
+            .ok_or_else(unsupported)?;
+
+        if ![16, 24, 32].contains(&pixmap_format.bits_per_pixel()) {
+            return Err(unsupported().into());
+        }
+
+        Ok(Self {
+            bits_per_pixel: pixmap_format.bits_per_pixel(),
+            scanline_pad: pixmap_format.scanline_pad(),
+            red_mask: visual_type.red_mask(),
+            green_mask: visual_type.green_mask(),
+            blue_mask: visual_type.blue_mask(),
+            byte_order: setup.image_byte_order(),
+        })
+    }
+
+    /// Returns the format of the pixels of the root window of `screen`
+    pub(super) fn of_root(setup: &x::Setup, screen: &x::Screen) -> Result<Self> {
+        Self::of_visual(setup, screen.root_depth(), screen.root_visual())
+    }
+
+    /// Returns the number of bytes a row of `width` pixels takes up
+    pub(super) fn stride(&self, width: usize) -> usize {
+        let pad = self.scanline_pad as usize;
+        (width * self.bits_per_pixel as usize).div_ceil(pad) * pad / 8
+    }
+
+    /// Returns the cairo format that can hold these pixels without losing precision
+    pub(super) fn cairo_format(&self) -> CairoImageFormat {
+        let is_deep = [self.red_mask, self.green_mask, self.blue_mask]
+            .iter()
+            .any(|mask| mask.count_ones() > 8);
+
+        if is_deep {
+            CairoImageFormat::Rgb30
+        } else {
+            CairoImageFormat::Rgb24
+        }
+    }
+
+    /// Whether pixels in this format are laid out exactly like pixels in `format`
+    fn matches(&self, format: CairoImageFormat) -> bool {
+        let Some(target) = ChannelLayout::of(format) else {
+            return false;
+        };
+        let native_order = if cfg!(target_endian = "little") {
+            ImageOrder::LsbFirst
+        } else {
+            ImageOrder::MsbFirst
+        };
+
+        self.bits_per_pixel == 32
+            && self.byte_order == native_order
+            && [self.red_mask, self.green_mask, self.blue_mask]
+                == [target.red.mask(), target.green.mask(), target.blue.mask()]
+    }
+
+    /// Converts the pixels of an image of `width` by `height` pixels in this format into `format`,
+    /// writing them into `destination`, whose rows are `destination_stride` bytes long.
+    ///
+    /// # Panics
+    /// If `format` is not [`cairo::Format::Rgb24`] or [`cairo::Format::Rgb30`], or if either buffer
+    /// is too small.
+    pub(super) fn convert(
+        &self,
+        source: &[u8],
+        width: usize,
+        height: usize,
+        destination: &mut [u8],
+        destination_stride: usize,
+        format: CairoImageFormat,
+    ) {
+        let source_stride = self.stride(width);
+        let rows = source
+            .chunks(source_stride)
+            .zip(destination.chunks_mut(destination_stride))
+            .take(height);
+
+        if self.matches(format) {
+            for (source_row, destination_row) in rows {
+                destination_row[..4 * width].copy_from_slice(&source_row[..4 * width]);
+            }
+
+            return;
+        }
+
+        let target = ChannelLayout::of(format).expect("Only Rgb24 and Rgb30 are supported");
+        let red = Channel::from_mask(self.red_mask);
+        let green = Channel::from_mask(self.green_mask);
+        let blue = Channel::from_mask(self.blue_mask);
+        let bytes_per_pixel = self.bits_per_pixel as usize / 8;
+
+        for (source_row, destination_row) in rows {
+            let source_pixels = source_row.chunks_exact(bytes_per_pixel);
+            let destination_pixels = destination_row.chunks_exact_mut(4);
+
+            for (source_pixel, destination_pixel) in
+                source_pixels.zip(destination_pixels).take(width)
+            {
+                let pixel = self.read_pixel(source_pixel);
+
+                let converted = target.red.place(red.convert(pixel, target.red.bits))
+                    | target.green.place(green.convert(pixel, target.green.bits))
+                    | target.blue.place(blue.convert(pixel, target.blue.bits));
+
+                destination_pixel.copy_from_slice(&converted.to_ne_bytes());
+            }
+        }
+    }
+
+    /// Copies the pixels of `monitor` (which are in this format) into `screenshot`, which is a
+    /// `format` image of `screenshot_bounds`.
+    pub(super) fn blit_monitor(
+        &self,
+        monitor_data: &[u8],
+        monitor: Rectangle,
+        screenshot: &mut [u8],
+        screenshot_stride: usize,
+        screenshot_bounds: Rectangle,
+        format: CairoImageFormat,
+    ) {
+        let dx = 4 * (monitor.x - screenshot_bounds.x) as usize;
+        let dy = (monitor.y - screenshot_bounds.y) as usize;
+
+        self.convert(
+            monitor_data,
+            monitor.w as usize,
+            monitor.h as usize,
+            &mut screenshot[dy * screenshot_stride + dx..],
+            screenshot_stride,
+            format,
+        );
+    }
+
+    fn read_pixel(&self, bytes: &[u8]) -> u32 {
+        let mut pixel = [0; 4];
+
+        match self.byte_order {
+            ImageOrder::LsbFirst => {
+                pixel[..bytes.len()].copy_from_slice(bytes);
+                u32::from_le_bytes(pixel)
+            }
+            ImageOrder::MsbFirst => {
+                pixel[4 - bytes.len()..].copy_from_slice(bytes);
+                u32::from_be_bytes(pixel)
+            }
+        }
+    }
+}
+
+/// Where the channels of the cairo formats we produce are
+struct ChannelLayout {
+    red: Channel,
+    green: Channel,
+    blue: Channel,
+}
+
+impl ChannelLayout {
+    fn of(format: CairoImageFormat) -> Option<Self> {
+        let bits = match format {
+            CairoImageFormat::Rgb24 => 8,
+            CairoImageFormat::Rgb30 => 10,
+            _ => return None,
+        };
+
+        Some(Self {
+            red: Channel {
+                shift: 2 * bits,
+                bits,
+            },
+            green: Channel { shift: bits, bits },
+            blue: Channel { shift: 0, bits },
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Channel {
+    shift: u32,
+    bits: u32,
+}
+
+impl Channel {
+    fn from_mask(mask: u32) -> Self {
+        Self {
+            shift: mask.trailing_zeros() % 32,
+            bits: mask.count_ones(),
+        }
+    }
+
+    fn mask(self) -> u32 {
+        ((1 << self.bits) - 1) << self.shift
+    }
+
+    fn place(self, value: u32) -> u32 {
+        value << self.shift
+    }
+
+    /// Extracts this channel from `pixel`, scaled so that it takes up `bits` bits.
+    ///
+    /// Scaling up is lossless, as every value maps to a different one.
+    fn convert(self, pixel: u32, bits: u32) -> u32 {
+        if self.bits == 0 {
+            return 0;
+        }
+
+        let value = (pixel >> self.shift) & ((1 << self.bits) - 1);
+        if self.bits == bits {
+            return value;
+        }
+
+        let max_from = (1u64 << self.bits) - 1;
+        let max_to = (1u64 << bits) - 1;
+
+        ((value as u64 * max_to + max_from / 2) / max_from) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cairo::Format as CairoImageFormat;
+    use xcb::x::ImageOrder;
+
+    use super::PixelFormat;
+
+    const DEPTH_24: PixelFormat = PixelFormat {
+        bits_per_pixel: 32,
+        scanline_pad: 32,
+        red_mask: 0xff0000,
+        green_mask: 0xff00,
+        blue_mask: 0xff,
+        byte_order: ImageOrder::LsbFirst,
+    };
+
+    const DEPTH_30: PixelFormat = PixelFormat {
+        red_mask: 0x3ff00000,
+        green_mask: 0xffc00,
+        blue_mask: 0x3ff,
+        ..DEPTH_24
+    };
+
+    /// Packs pixels as the X server would send them to us in `format`
+    fn pack(format: PixelFormat, pixels: &[u32]) -> Vec<u8> {
+        let bytes_per_pixel = format.bits_per_pixel as usize / 8;
+
+        pixels
+            .iter()
+            .flat_map(|pixel| match format.byte_order {
+                ImageOrder::LsbFirst => pixel.to_le_bytes()[..bytes_per_pixel].to_vec(),
+                ImageOrder::MsbFirst => pixel.to_be_bytes()[4 - bytes_per_pixel..].to_vec(),
+            })
+            .collect()
+    }
+
+    fn convert(
+        format: PixelFormat,
+        data: &[u8],
+        width: usize,
+        height: usize,
+        target: CairoImageFormat,
+    ) -> Vec<u32> {
+        let mut converted = vec![0; 4 * width * height];
+        format.convert(data, width, height, &mut converted, 4 * width, target);
+
+        converted
+            .chunks_exact(4)
+            .map(|pixel| u32::from_ne_bytes(pixel.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn cairo_format() {
+        assert_eq!(DEPTH_24.cairo_format(), CairoImageFormat::Rgb24);
+        assert_eq!(DEPTH_30.cairo_format(), CairoImageFormat::Rgb30);
+    }
+
+    #[test]
+    fn depth_24() {
+        let pixels = [0x00ff8040, 0xff123456];
+        let data = pack(DEPTH_24, &pixels);
+
+        // The unused byte is kept as is, cairo ignores it
+        assert_eq!(
+            convert(DEPTH_24, &data, 2, 1, CairoImageFormat::Rgb24),
+            pixels
+        );
+    }
+
+    #[test]
+    fn depth_30_is_lossless() {
+        // Red is 1023, green is 512 and blue is 1, none of which can be represented with 8 bits
+        let pixels = [0x3ff80001, 0x00000000, 0x3fffffff];
+        let data = pack(DEPTH_30, &pixels);
+
+        assert_eq!(
+            convert(DEPTH_30, &data, 3, 1, CairoImageFormat::Rgb30),
+            pixels
+        );
+    }
+
+    #[test]
+    fn depth_30_big_endian_server() {
+        let format = PixelFormat {
+            byte_order: ImageOrder::MsbFirst,
+            ..DEPTH_30
+        };
+        let pixels = [0x3ff80001, 0x12345678 & 0x3fffffff];
+        let data = pack(format, &pixels);
+
+        assert_eq!(
+            convert(format, &data, 2, 1, CairoImageFormat::Rgb30),
+            pixels
+        );
+    }
+
+    #[test]
+    fn depth_30_to_rgb24() {
+        let data = pack(DEPTH_30, &[0x3ff80001]);
+
+        // 512 / 1023 * 255 rounds to 128, 1 / 1023 * 255 rounds to 0
+        assert_eq!(
+            convert(DEPTH_30, &data, 1, 1, CairoImageFormat::Rgb24),
+            [0xff8000]
+        );
+    }
+
+    #[test]
+    fn depth_24_to_rgb30_is_lossless() {
+        let data = pack(DEPTH_24, &[0xff8001]);
+
+        let converted = convert(DEPTH_24, &data, 1, 1, CairoImageFormat::Rgb30);
+        // 128 and 1 become 514 and 4, which turn back into 128 and 1
+        assert_eq!(converted, [0x3ff80804]);
+
+        let back = pack(DEPTH_30, &converted);
+        assert_eq!(
+            convert(DEPTH_30, &back, 1, 1, CairoImageFormat::Rgb24),
+            [0xff8001]
+        );
+    }
+
+    #[test]
+    fn bgr_channel_order() {
+        let format = PixelFormat {
+            red_mask: 0xff,
+            blue_mask: 0xff0000,
+            ..DEPTH_24
+        };
+        let data = pack(format, &[0x0000ff]);
+
+        assert_eq!(
+            convert(format, &data, 1, 1, CairoImageFormat::Rgb24),
+            [0xff0000]
+        );
+    }
+
+    #[test]
+    fn depth_16_with_padded_rows() {
+        let format = PixelFormat {
+            bits_per_pixel: 16,
+            red_mask: 0xf800,
+            green_mask: 0x7e0,
+            blue_mask: 0x1f,
+            ..DEPTH_24
+        };
+        // Rows of 3 16-bit pixels take up 8 bytes, as they're padded to 32 bits
+        assert_eq!(format.stride(3), 8);
+
+        let mut data = pack(format, &[0xf800, 0x07e0, 0x001f, 0]);
+        data.extend(pack(format, &[0xffff, 0x0000, 0x8410, 0]));
+
+        assert_eq!(
+            convert(format, &data, 3, 2, CairoImageFormat::Rgb24),
+            [0xff0000, 0x00ff00, 0x0000ff, 0xffffff, 0x000000, 0x848284]
+        );
+    }
+}
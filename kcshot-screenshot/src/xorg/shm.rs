@@ -0,0 +1,135 @@
+//! Captures through the MIT-SHM extension, which has the X server write images straight into memory
+//! that we share with it, instead of sending them over the socket in a `GetImage` reply.
+//!
+//! This only works when the X server runs on the same machine as us, so callers are expected to
+//! fall back to `GetImage` when it fails.
+
+use std::{io, ptr::NonNull};
+
+use kcshot_data::geometry::Rectangle;
+use xcb::x::{self, ImageFormat as XImageFormat};
+
+use super::Error;
+use crate::Result;
+
+/// Setting this environment variable makes kcshot always use `GetImage`, which helps when MIT-SHM
+/// misbehaves and with comparing the two.
+const DISABLE_SHM_VAR: &str = "KCSHOT_DISABLE_SHM";
+
+/// A shared memory segment that the X server can write images into.
+///
+/// The segment is marked for removal as soon as the X server has attached to it, so it goes away
+/// once both sides have detached from it, even if we crash. The X server detaches when the
+/// connection is closed, so a segment must not outlive the connection it was created with.
+pub(super) struct ShmSegment {
+    segment: xcb::shm::Seg,
+    address: NonNull<u8>,
+    size: usize,
+}
+
+// SAFETY: The mapping belongs to the segment alone, so it can be moved to another thread with it
+unsafe impl Send for ShmSegment {}
+
+impl ShmSegment {
+    /// Creates a segment which can hold images of `size` bytes and attaches the X server to it
+    pub(super) fn new(connection: &xcb::Connection, size: usize) -> Result<Self> {
+        if std::env::var_os(DISABLE_SHM_VAR).is_some() {
+            return Err(Error::ShmDisabled.into());
+        }
+
+        if !connection
+            .active_extensions()
+            .any(|extension| extension == xcb::Extension::Shm)
+        {
+            return Err(Error::ShmUnsupported.into());
+        }
+
+        // SAFETY: shmget has no preconditions
+        let id = unsafe { libc::shmget(libc::IPC_PRIVATE, size, libc::IPC_CREAT | 0o600) };
+        if id == -1 {
+            return Err(Error::Shm(io::Error::last_os_error()).into());
+        }
+
+        // SAFETY: `id` refers to the segment created above, which we let the kernel map anywhere
+        let address = unsafe { libc::shmat(id, std::ptr::null(), libc::SHM_RDONLY) };
+        let address = if address as isize == -1 {
+            Err(Error::Shm(io::Error::last_os_error()))
+        } else {
+            Ok(NonNull::new(address.cast()).expect("shmat returns -1 on failure, not null"))
+        };
+
+        let segment = address.and_then(|address| {
+            let segment = connection.generate_id();
+            let attach = connection.send_request_checked(&xcb::shm::Attach {
+                shmseg: segment,
+                shmid: id as u32,
+                read_only: false,
+            });
+
+            // Remote X servers fail here, as they can't see our memory
+            match connection.check_request(attach) {
+                Ok(()) => Ok(Self {
+                    segment,
+                    address,
+                    size,
+                }),
+                Err(why) => {
+                    // SAFETY: `address` was returned by shmat
+                    unsafe { libc::shmdt(address.as_ptr().cast()) };
+                    Err(Error::XcbProtocol(why))
+                }
+            }
+        });
+
+        // SAFETY: `id` refers to the segment created above. The segment is only destroyed once
+        // everyone has detached from it.
+        unsafe { libc::shmctl(id, libc::IPC_RMID, std::ptr::null_mut()) };
+
+        Ok(segment?)
+    }
+
+    /// Captures `area` of `drawable`, returning the pixels in the same format as `GetImage`.
+    ///
+    /// The pixels are overwritten by the next capture, which is why this borrows the segment
+    /// mutably.
+    pub(super) fn get_image(
+        &mut self,
+        connection: &xcb::Connection,
+        drawable: x::Drawable,
+        area: Rectangle,
+    ) -> Result<&[u8]> {
+        let image = connection.send_request(&xcb::shm::GetImage {
+            drawable,
+            x: area.x as _,
+            y: area.y as _,
+            width: area.w as _,
+            height: area.h as _,
+            plane_mask: u32::MAX,
+            format: XImageFormat::ZPixmap as u8,
+            shmseg: self.segment,
+            offset: 0,
+        });
+        let image = connection.wait_for_reply(image).map_err(Error::from)?;
+
+        let size = image.size() as usize;
+        if size > self.size {
+            return Err(Error::ShmSegmentTooSmall {
+                needed: size,
+                available: self.size,
+            }
+            .into());
+        }
+
+        // SAFETY: The segment is at least `size` bytes long, and the X server is done writing to it
+        // as it has replied to our request
+        Ok(unsafe { std::slice::from_raw_parts(self.address.as_ptr(), size) })
+    }
+}
+
+impl Drop for ShmSegment {
+    fn drop(&mut self) {
+        // SAFETY: `address` was returned by shmat, and the slices handed out by `get_image` borrow
+        // `self`, so they can't outlive this
+        unsafe { libc::shmdt(self.address.as_ptr().cast()) };
+    }
+}
@@ -0,0 +1,2 @@
+ALTER TABLE screenshots DROP COLUMN scale;
+ALTER TABLE screenshots DROP COLUMN monitors;
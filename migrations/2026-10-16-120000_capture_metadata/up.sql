@@ -0,0 +1,2 @@
+ALTER TABLE screenshots ADD COLUMN monitors TEXT;
+ALTER TABLE screenshots ADD COLUMN scale DOUBLE;
@@ -0,0 +1 @@
+ALTER TABLE screenshots DROP COLUMN application;
@@ -0,0 +1 @@
+ALTER TABLE screenshots ADD COLUMN application TEXT;
@@ -28,6 +28,7 @@ mod underlying {
         subclass::{application_window::ApplicationWindowImpl, prelude::*},
     };
     use kcshot_data::settings::Settings;
+    use kcshot_screenshot::CaptureTarget;
 
     use crate::{
         editor::EditorWindow, ext::DisposeExt, history, history::RowData, kcshot::KCShot,
@@ -132,7 +133,23 @@ mod underlying {
         fn on_capture_clicked(&self, _: &gtk4::Button) {
             let editing_starts_with_cropping = self.settings.editing_starts_with_cropping();
 
-            EditorWindow::show(KCShot::the().upcast_ref(), editing_starts_with_cropping);
+            EditorWindow::show(
+                KCShot::the().upcast_ref(),
+                editing_starts_with_cropping,
+                &CaptureTarget::from_settings(),
+            );
+        }
+
+        #[template_callback]
+        fn on_delayed_capture_clicked(&self, _: &gtk4::Button) {
+            let editing_starts_with_cropping = self.settings.editing_starts_with_cropping();
+
+            EditorWindow::show_after_delay(
+                KCShot::the().upcast_ref(),
+                editing_starts_with_cropping,
+                CaptureTarget::from_settings(),
+                self.settings.capture_delay(),
+            );
         }
 
         #[template_callback]
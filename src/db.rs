@@ -1,5 +1,6 @@
 use diesel::{SqliteConnection, prelude::*};
 use diesel_migrations::{EmbeddedMigrations, MigrationHarness};
+use kcshot_screenshot::CaptureMetadata;
 
 use self::models::Screenshot;
 
@@ -41,11 +42,19 @@ pub fn add_screenshot_to_history(
     path_: Option<String>,
     time_: String,
     url_: Option<String>,
+    metadata: Option<&CaptureMetadata>,
 ) -> QueryResult<()> {
     use schema::screenshots::{self, dsl::*};
 
     diesel::insert_into(screenshots::table)
-        .values((path.eq(path_), time.eq(time_), url.eq(url_)))
+        .values((
+            path.eq(path_),
+            time.eq(time_),
+            url.eq(url_),
+            monitors.eq(metadata.and_then(CaptureMetadata::monitor_names)),
+            scale.eq(metadata.map(|metadata| metadata.scale)),
+            application.eq(metadata.and_then(CaptureMetadata::application)),
+        ))
         .execute(conn)
         .map(|_| ())
 }
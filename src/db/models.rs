@@ -4,4 +4,9 @@ pub struct Screenshot {
     pub path: Option<String>,
     pub time: String,
     pub url: Option<String>,
+    /// The names of the monitors the screenshot was taken on, separated by commas
+    pub monitors: Option<String>,
+    pub scale: Option<f64>,
+    /// The class of the application whose window the screenshot shows
+    pub application: Option<String>,
 }
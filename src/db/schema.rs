@@ -4,5 +4,8 @@ table! {
         path -> Nullable<Text>,
         time -> Text,
         url -> Nullable<Text>,
+        monitors -> Nullable<Text>,
+        scale -> Nullable<Double>,
+        application -> Nullable<Text>,
     }
 }
@@ -1,14 +1,21 @@
-use gtk4::{gio, glib, prelude::*, subclass::prelude::ObjectSubclassIsExt};
-use kcshot_data::colour::Colour;
+use std::{fmt, time::Duration};
+
+use gtk4::{gdk, gio, glib, prelude::*, subclass::prelude::ObjectSubclassIsExt};
+use kcshot_data::{
+    colour::Colour,
+    settings::{Settings, ToolProperties},
+};
+use kcshot_screenshot::{CaptureMetadata, CaptureTarget, Screenshot};
 
 use self::operations::Tool;
-use crate::kcshot::KCShot;
+use crate::{kcshot::KCShot, systray};
 
 pub mod colourbutton;
 mod colourchooser;
 mod colourchooserdialog;
 mod colourwheel;
 mod operations;
+mod regions;
 mod textdialog;
 mod toolbar;
 mod underlying;
@@ -22,20 +29,113 @@ glib::wrapper! {
 }
 
 impl EditorWindow {
-    pub fn new(app: &gtk4::Application, editing_starts_with_cropping: bool) -> Self {
-        glib::Object::builder::<Self>()
+    /// Creates an editor for a screenshot of `capture_target`, returns `None` if the screenshot
+    /// couldn't be taken
+    pub fn new(
+        app: &gtk4::Application,
+        editing_starts_with_cropping: bool,
+        capture_target: &CaptureTarget,
+    ) -> Option<Self> {
+        let obj = glib::Object::builder::<Self>()
+            .property("application", app)
+            .property("editing-starts-with-cropping", editing_starts_with_cropping)
+            .build();
+
+        if let Err(why) = obj.imp().init_with_screenshot(capture_target) {
+            tracing::error!("Couldn't take a screenshot: {why}");
+            obj.destroy();
+            return None;
+        }
+
+        Some(obj)
+    }
+
+    pub fn show(
+        app: &gtk4::Application,
+        editing_starts_with_cropping: bool,
+        capture_target: &CaptureTarget,
+    ) {
+        if let Some(window) = Self::new(app, editing_starts_with_cropping, capture_target) {
+            window.present_fullscreen(capture_target);
+        }
+    }
+
+    /// Shows an editor that only lets the user pick a region of the screen, which then gets
+    /// recorded instead of saved (see [`KCShot::start_recording`]).
+    pub fn show_for_recording(app: &gtk4::Application, capture_target: &CaptureTarget) {
+        Self::show_for_region(app, capture_target, RegionUse::Recording);
+    }
+
+    /// Shows an editor that only lets the user pick a region of the screen, which then gets
+    /// captured while it's being scrolled (see [`KCShot::start_scrolling_capture`]).
+    pub fn show_for_scrolling_capture(app: &gtk4::Application, capture_target: &CaptureTarget) {
+        Self::show_for_region(app, capture_target, RegionUse::ScrollingCapture);
+    }
+
+    fn show_for_region(
+        app: &gtk4::Application,
+        capture_target: &CaptureTarget,
+        region_use: RegionUse,
+    ) {
+        // This has to happen before the editor is shown, as the cursor could move to another
+        // monitor in the meantime
+        let capture_bounds = match kcshot_screenshot::capture_bounds(capture_target) {
+            Ok(capture_bounds) => capture_bounds,
+            Err(why) => {
+                tracing::error!("Can't pick a region of the screen to {region_use}: {why}");
+                return;
+            }
+        };
+
+        let Some(window) = Self::new(app, true, capture_target) else {
+            return;
+        };
+        window
+            .imp()
+            .set_picked_region_use(region_use, capture_bounds);
+        window.present_fullscreen(capture_target);
+    }
+
+    /// Shows an editor for an image that was not just taken, like the result of a scrolling capture.
+    ///
+    /// Such images can be bigger than the screen, so the editor is shown as a regular window which
+    /// is at most `visible_height` pixels tall.
+    pub fn show_with_image(
+        app: &gtk4::Application,
+        image: cairo::ImageSurface,
+        metadata: CaptureMetadata,
+        visible_height: i32,
+    ) {
+        let editing_starts_with_cropping = Settings::open().editing_starts_with_cropping();
+
+        let window = glib::Object::builder::<Self>()
             .property("application", app)
             .property("editing-starts-with-cropping", editing_starts_with_cropping)
-            .build()
+            .build();
+        window.set_default_size(image.width(), image.height().min(visible_height));
+        window.imp().init_with_image(
+            Screenshot {
+                image,
+                cursor: None,
+                metadata,
+            },
+            vec![],
+            false,
+        );
+
+        window.present();
     }
 
-    pub fn show(app: &gtk4::Application, editing_starts_with_cropping: bool) {
-        let window = Self::new(app, editing_starts_with_cropping);
-        window.set_decorated(false);
-        window.show();
-        window.fullscreen();
+    fn present_fullscreen(&self, capture_target: &CaptureTarget) {
+        self.set_decorated(false);
+        self.show();
 
-        let surface = window
+        match gdk_monitor_for(capture_target) {
+            Some(monitor) => self.fullscreen_on_monitor(&monitor),
+            None => self.fullscreen(),
+        }
+
+        let surface = self
             .native()
             .and_then(|native| native.surface())
             .and_downcast::<gdk4_x11::X11Surface>();
@@ -43,7 +143,63 @@ impl EditorWindow {
         if let Some(surface) = surface {
             surface.set_skip_taskbar_hint(true);
             surface.set_skip_pager_hint(true);
+
+            if *capture_target == CaptureTarget::AllMonitors
+                && let Err(why) =
+                    kcshot_screenshot::span_window_across_all_monitors(surface.xid() as u32)
+            {
+                tracing::warn!("Failed to make the editor span all monitors: {why}");
+            }
+        }
+    }
+
+    /// Like [`Self::show`], but waits for `delay` seconds before taking the screenshot, counting
+    /// down on the systray icon in the meantime, or in a small window if there is no systray icon.
+    pub fn show_after_delay(
+        app: &gtk4::Application,
+        editing_starts_with_cropping: bool,
+        capture_target: CaptureTarget,
+        delay: u32,
+    ) {
+        if delay == 0 {
+            Self::show(app, editing_starts_with_cropping, &capture_target);
+            return;
         }
+
+        let app = app.clone();
+        let mut seconds_left = delay;
+        let countdown_window =
+            (!systray::is_shown()).then(|| CountdownWindow::new(&app, seconds_left));
+        systray::set_countdown(Some(seconds_left));
+
+        glib::timeout_add_local(Duration::from_secs(1), move || {
+            seconds_left -= 1;
+            if seconds_left > 0 {
+                systray::set_countdown(Some(seconds_left));
+                if let Some(countdown_window) = &countdown_window {
+                    countdown_window.set_seconds_left(seconds_left);
+                }
+                return glib::ControlFlow::Continue;
+            }
+
+            systray::set_countdown(None);
+            match &countdown_window {
+                Some(countdown_window) => {
+                    countdown_window.window.destroy();
+
+                    // Give the compositor some time to take the countdown off the screen, so it
+                    // doesn't end up in the screenshot
+                    let app = app.clone();
+                    let capture_target = capture_target.clone();
+                    glib::timeout_add_local_once(Duration::from_millis(200), move || {
+                        Self::show(&app, editing_starts_with_cropping, &capture_target);
+                    });
+                }
+                None => Self::show(&app, editing_starts_with_cropping, &capture_target),
+            }
+
+            glib::ControlFlow::Break
+        });
     }
 
     async fn pick_colour(&self) -> Colour {
@@ -51,15 +207,22 @@ impl EditorWindow {
     }
 
     fn set_current_tool(&self, tool: Tool) {
+        let properties = tool.properties();
         self.imp().with_image_mut("set_current_tool", |image| {
             image.operation_stack.set_current_tool(tool);
+            image.operation_stack.tool_properties = properties;
         });
+        self.imp().toolbar().show_tool_properties(tool, properties);
     }
 
-    fn set_line_width(&self, line_width: f64) {
-        self.imp().with_image_mut("set_line_width", |image| {
-            image.operation_stack.line_width = line_width;
+    /// Changes what `tool` draws with, and remembers it for the next time it gets used
+    fn set_tool_properties(&self, tool: Tool, properties: ToolProperties) {
+        self.imp().with_image_mut("set_tool_properties", |image| {
+            if image.operation_stack.current_tool() == tool {
+                image.operation_stack.tool_properties = properties;
+            }
         });
+        tool.save_properties(properties);
     }
 
     fn save_image(&self) {
@@ -71,9 +234,78 @@ impl EditorWindow {
                         conn,
                         self.upcast_ref(),
                         image,
-                        None,
                     );
                 });
             });
     }
 }
+
+/// A small window showing how many seconds are left until a delayed screenshot gets taken, used
+/// when there is no systray icon to show the countdown on
+struct CountdownWindow {
+    window: gtk4::Window,
+    label: gtk4::Label,
+}
+
+impl CountdownWindow {
+    fn new(app: &gtk4::Application, seconds_left: u32) -> Self {
+        let label = gtk4::Label::new(None);
+        label.add_css_class("kc-countdown");
+
+        let window = gtk4::Window::builder()
+            .application(app)
+            .title("kcshot")
+            .decorated(false)
+            .resizable(false)
+            .child(&label)
+            .build();
+
+        let this = Self { window, label };
+        this.set_seconds_left(seconds_left);
+        this.window.present();
+
+        this
+    }
+
+    fn set_seconds_left(&self, seconds_left: u32) {
+        self.label.set_text(&seconds_left.to_string());
+    }
+}
+
+/// What the region picked in an editor shown by [`EditorWindow::show_for_recording`] or
+/// [`EditorWindow::show_for_scrolling_capture`] is used for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RegionUse {
+    Recording,
+    ScrollingCapture,
+}
+
+impl fmt::Display for RegionUse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Recording => write!(f, "record"),
+            Self::ScrollingCapture => write!(f, "take a scrolling capture of"),
+        }
+    }
+}
+
+/// Returns the GDK monitor matching the monitor picked by `capture_target`, if it picks one
+fn gdk_monitor_for(capture_target: &CaptureTarget) -> Option<gdk::Monitor> {
+    let CaptureTarget::Monitor(selector) = capture_target else {
+        return None;
+    };
+
+    let monitors = kcshot_screenshot::monitors()
+        .inspect_err(|why| tracing::warn!("Failed to retrieve monitors: {why}"))
+        .ok()?;
+    let name = &selector.select(&monitors)?.name;
+
+    let gdk_monitors = gdk::Display::default()?.monitors();
+    (0..gdk_monitors.n_items())
+        .filter_map(|idx| gdk_monitors.item(idx).and_downcast::<gdk::Monitor>())
+        .find(|monitor| {
+            monitor
+                .connector()
+                .is_some_and(|connector| connector.as_str() == name.as_str())
+        })
+}
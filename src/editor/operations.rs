@@ -1,13 +1,21 @@
 use cairo::{Context, Error as CairoError};
 use gtk4::pango::FontDescription;
-use kcshot_data::{Text, colour::Colour, geometry::*};
+use kcshot_data::{
+    Text,
+    colour::Colour,
+    geometry::*,
+    settings::{ArrowHead, ToolProperties},
+};
 use pangocairo::functions::{create_layout, show_layout, update_layout};
 use rand::Rng;
 
-pub use self::{stack::*, tool::*};
+pub use self::{cursor::*, shapes::Stroke, stack::*, tool::*};
 use super::utils::CairoExt;
 
+mod cursor;
+mod hud;
 mod pixelops;
+mod selection;
 mod shapes;
 mod stack;
 mod tool;
@@ -39,39 +47,52 @@ pub enum Operation {
     Pixelate {
         rect: Rectangle,
         seed: u64,
+        /// How big the pixelation blocks are, see [`pixelops::pixelate`]
+        size: u64,
     },
     DrawLine {
         start: Point,
         end: Point,
         colour: Colour,
         line_width: f64,
+        stroke: Stroke,
     },
     DrawRectangle {
         rect: Rectangle,
         border: Colour,
         fill: Colour,
         line_width: f64,
+        stroke: Stroke,
     },
     Text {
         top_left: Point,
         text: String,
+        /// The source of `text`, see [`Text::markdown`]
+        markdown: String,
         colour: Colour,
         font_description: FontDescription,
     },
     DrawArrow {
         start: Point,
         end: Point,
+        /// Arrows are curved if they have a control point, see [`shapes::Arrow::control`]
+        control: Option<Point>,
+        head: ArrowHead,
+        double_headed: bool,
         colour: Colour,
         line_width: f64,
+        stroke: Stroke,
     },
     Highlight {
         rect: Rectangle,
+        colour: Colour,
     },
     DrawEllipse {
         ellipse: Ellipse,
         border: Colour,
         fill: Colour,
         line_width: f64,
+        stroke: Stroke,
     },
     Bubble {
         centre: Point,
@@ -85,6 +106,7 @@ pub enum Operation {
         points: Vec<Point>,
         colour: Colour,
         line_width: f64,
+        stroke: Stroke,
     },
 }
 
@@ -95,8 +117,25 @@ impl Operation {
         bubble_index: &mut i32,
         primary_colour: Colour,
         secondary_colour: Colour,
-        line_width: f64,
+        properties: ToolProperties,
     ) -> Self {
+        let ToolProperties {
+            line_width,
+            fill,
+            opacity,
+            strength,
+            stroke_style,
+            arrow_head,
+            double_headed,
+        } = properties;
+        let primary_colour = primary_colour.with_opacity(opacity);
+        let secondary_colour = secondary_colour.with_opacity(opacity);
+        let fill = if fill { primary_colour } else { INVISIBLE };
+        let stroke = Stroke {
+            style: stroke_style,
+            seed: rand::rng().random(),
+        };
+
         let rect = Rectangle {
             x: start.x,
             y: start.y,
@@ -108,6 +147,7 @@ impl Operation {
 
         match tool {
             Tool::Save => panic!("`Tool::Save` should never be converted to an `Operation`"),
+            Tool::Select => panic!("`Tool::Select` should never be converted to an `Operation`"),
             Tool::CropAndSave | Tool::Crop => Self::Crop(Rectangle {
                 x: start.x,
                 y: start.y,
@@ -120,18 +160,24 @@ impl Operation {
                 end: start,
                 colour: secondary_colour,
                 line_width,
+                stroke,
             },
             Tool::Arrow => Self::DrawArrow {
                 start,
                 end: start,
+                control: None,
+                head: arrow_head,
+                double_headed,
                 colour: secondary_colour,
                 line_width,
+                stroke,
             },
             Tool::Rectangle => Self::DrawRectangle {
                 rect,
                 border: secondary_colour,
-                fill: primary_colour,
+                fill,
                 line_width,
+                stroke,
             },
             Tool::Ellipse => Self::DrawEllipse {
                 ellipse: Ellipse {
@@ -141,15 +187,23 @@ impl Operation {
                     h: 1.0,
                 },
                 border: secondary_colour,
-                fill: primary_colour,
+                fill,
                 line_width,
+                stroke,
+            },
+            Tool::Highlight => Self::Highlight {
+                rect,
+                colour: HIGHLIGHT_COLOUR.with_opacity(opacity),
             },
-            Tool::Highlight => Self::Highlight { rect },
             Tool::Pixelate => Self::Pixelate {
                 rect,
                 seed: rand::rng().random(),
+                size: strength.max(1.0) as u64,
+            },
+            Tool::Blur => Self::Blur {
+                rect,
+                radius: strength as f32,
             },
-            Tool::Blur => Self::Blur { rect, radius: 5.0 },
             Tool::AutoincrementBubble => {
                 let bubble = Self::Bubble {
                     centre: start,
@@ -164,6 +218,7 @@ impl Operation {
             Tool::Text => Self::Text {
                 top_left: start,
                 text: String::new(),
+                markdown: String::new(),
                 // We use secondary colour here as the primary one is more likely to be transparent,
                 // given that's the default, and people are likely to use boxes and ellipses to try
                 // and bring things into attention, and in those situations the primary colour is
@@ -176,6 +231,7 @@ impl Operation {
                 points: vec![],
                 colour: secondary_colour,
                 line_width,
+                stroke,
             },
         }
     }
@@ -217,32 +273,35 @@ impl Operation {
                 pixelops::blur(cairo, *radius as usize, rect.normalised())?;
                 cairo.restore()?;
             }
-            Operation::Pixelate { rect, seed } => {
+            Operation::Pixelate { rect, seed, size } => {
                 let rect = rect.normalised();
 
-                pixelops::pixelate(cairo, &rect, *seed)?;
+                pixelops::pixelate(cairo, &rect, *seed, *size)?;
             }
             Operation::DrawLine {
                 start,
                 end,
                 colour,
                 line_width,
+                stroke,
             } => {
-                shapes::draw_line(cairo, *start, *end, *colour, *line_width)?;
+                shapes::draw_line(cairo, *start, *end, *colour, *line_width, *stroke)?;
             }
             Operation::DrawRectangle {
                 rect,
                 border,
                 fill,
                 line_width,
+                stroke,
             } => {
-                shapes::draw_rectangle(cairo, rect, *border, *fill, *line_width)?;
+                shapes::draw_rectangle(cairo, rect, *border, *fill, *line_width, *stroke)?;
             }
             Operation::Text {
                 top_left,
                 text,
                 colour,
                 font_description,
+                ..
             } => {
                 cairo.save()?;
                 draw_text_at(cairo, *top_left, text, *colour, font_description);
@@ -251,22 +310,34 @@ impl Operation {
             Operation::DrawArrow {
                 start,
                 end,
+                control,
+                head,
+                double_headed,
                 colour,
                 line_width,
+                stroke,
             } => {
-                shapes::draw_arrow(cairo, *start, *end, *colour, *line_width)?;
+                let arrow = shapes::Arrow {
+                    start: *start,
+                    end: *end,
+                    control: *control,
+                    head: *head,
+                    double_headed: *double_headed,
+                };
+                shapes::draw_arrow(cairo, &arrow, *colour, *line_width, *stroke)?;
             }
-            Operation::Highlight { rect } => {
-                shapes::draw_rectangle(cairo, rect, INVISIBLE, HIGHLIGHT_COLOUR, 1.0)?;
+            Operation::Highlight { rect, colour } => {
+                shapes::draw_rectangle(cairo, rect, INVISIBLE, *colour, 1.0, Stroke::SOLID)?;
             }
             Operation::DrawEllipse {
                 ellipse,
                 border,
                 fill,
                 line_width,
+                stroke,
             } => {
                 cairo.save()?;
-                shapes::draw_ellipse(cairo, ellipse, *border, *fill, *line_width)?;
+                shapes::draw_ellipse(cairo, ellipse, *border, *fill, *line_width, *stroke)?;
                 cairo.restore()?;
             }
             Operation::Bubble {
@@ -286,7 +357,14 @@ impl Operation {
                     h: 2.0 * BUBBLE_RADIUS,
                 };
 
-                shapes::draw_ellipse(cairo, &ellipse, INVISIBLE, *bubble_colour, 1.0)?;
+                shapes::draw_ellipse(
+                    cairo,
+                    &ellipse,
+                    INVISIBLE,
+                    *bubble_colour,
+                    1.0,
+                    Stroke::SOLID,
+                )?;
                 draw_text_centred_at(
                     cairo,
                     *centre,
@@ -300,16 +378,13 @@ impl Operation {
                 points,
                 colour,
                 line_width,
+                stroke,
             } => {
-                cairo.save()?;
-                cairo.set_line_width(*line_width);
-                cairo.set_source_colour(*colour);
                 cairo.move_to(start.x, start.y);
                 for point in points {
                     cairo.line_to(point.x, point.y);
                 }
-                cairo.stroke()?;
-                cairo.restore()?;
+                shapes::stroke(cairo, *colour, *line_width, *stroke)?;
             }
         };
 
@@ -319,16 +394,37 @@ impl Operation {
     pub fn set_text(&mut self, input_text: Text) {
         if let Operation::Text {
             text,
+            markdown,
             colour,
             font_description,
             ..
         } = self
         {
             *text = input_text.string;
+            *markdown = input_text.markdown;
             *font_description = input_text.font_description;
             *colour = input_text.colour;
         }
     }
+
+    /// Returns what was passed to [`Self::set_text`] for text operations
+    pub fn text(&self) -> Option<Text> {
+        match self {
+            Operation::Text {
+                text,
+                markdown,
+                colour,
+                font_description,
+                ..
+            } => Some(Text {
+                string: text.clone(),
+                markdown: markdown.clone(),
+                font_description: font_description.clone(),
+                colour: *colour,
+            }),
+            _ => None,
+        }
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -0,0 +1,88 @@
+use cairo::{Context, ImageSurface};
+use kcshot_data::geometry::{Point, Rectangle};
+
+use super::Error;
+
+/// The mouse cursor as it was when the screenshot was taken.
+///
+/// It is kept apart from the screenshot so that the user can move it around or hide it, and only
+/// becomes part of the image when it gets saved.
+#[derive(Debug)]
+pub struct CursorLayer {
+    image: ImageSurface,
+    /// The position of the top left corner of the cursor image
+    position: Point,
+    visible: bool,
+    /// Where the cursor was when the user started dragging it
+    drag_origin: Option<Point>,
+}
+
+impl CursorLayer {
+    pub fn new(cursor: kcshot_screenshot::Cursor) -> Self {
+        Self {
+            image: cursor.image,
+            position: cursor.position,
+            visible: true,
+            drag_origin: None,
+        }
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    pub fn set_visible(&mut self, visible: bool) {
+        self.visible = visible;
+    }
+
+    fn bounds(&self) -> Rectangle {
+        Rectangle {
+            x: self.position.x,
+            y: self.position.y,
+            w: self.image.width() as f64,
+            h: self.image.height() as f64,
+        }
+    }
+
+    /// Starts dragging the cursor if `point` is on top of it, returns whether it was
+    pub fn start_drag(&mut self, point: Point) -> bool {
+        if self.visible && self.bounds().contains(point) {
+            self.drag_origin = Some(self.position);
+        }
+
+        self.drag_origin.is_some()
+    }
+
+    /// Moves the cursor `offset` away from where it was when the drag started, returns false if the
+    /// cursor isn't being dragged
+    pub fn update_drag(&mut self, offset: Point) -> bool {
+        let Some(origin) = self.drag_origin else {
+            return false;
+        };
+
+        self.position = Point {
+            x: origin.x + offset.x,
+            y: origin.y + offset.y,
+        };
+
+        true
+    }
+
+    /// Returns whether the cursor was being dragged
+    pub fn finish_drag(&mut self) -> bool {
+        self.drag_origin.take().is_some()
+    }
+
+    pub fn draw(&self, cairo: &Context) -> Result<(), Error> {
+        if !self.visible {
+            return Ok(());
+        }
+
+        cairo.save()?;
+        cairo.set_source_surface(&self.image, self.position.x, self.position.y)?;
+        cairo.paint()?;
+        cairo.restore()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+//! The aids drawn while the user selects a region: a crosshair through the pointer, a magnified
+//! view of the pixels around it and a readout of where the selection is and how big it is.
+
+use cairo::{Context, Filter, ImageSurface};
+use gtk4::pango::FontDescription;
+use kcshot_data::{
+    colour::Colour,
+    geometry::{Point, Rectangle},
+};
+use pangocairo::functions::{create_layout, show_layout};
+
+use super::{Error, stack::WINDOW_INDICATOR_COLOUR};
+use crate::editor::utils::CairoExt;
+
+/// How many pixels of the screenshot are shown on each side of the loupe, this is odd so that the
+/// pixel under the pointer is in the middle
+const LOUPE_PIXELS: f64 = 15.0;
+/// How many times bigger pixels are inside the loupe
+const LOUPE_ZOOM: f64 = 8.0;
+const LOUPE_SIZE: f64 = LOUPE_PIXELS * LOUPE_ZOOM;
+/// How far away from the pointer the loupe is drawn, so that it doesn't hide what's being selected
+const POINTER_DISTANCE: f64 = 24.0;
+const PADDING: f64 = 4.0;
+
+const BACKGROUND: Colour = Colour {
+    red: 0,
+    green: 0,
+    blue: 0,
+    alpha: 200,
+};
+
+const FOREGROUND: Colour = Colour {
+    red: 255,
+    green: 255,
+    blue: 255,
+    alpha: 255,
+};
+
+/// Draws the selection aids for the pointer being at `pointer`, `selection` is the region that
+/// would get cropped to, if there is one
+pub fn draw(
+    cairo: &Context,
+    screenshot: &ImageSurface,
+    screen: Rectangle,
+    pointer: Point,
+    selection: Option<Rectangle>,
+) -> Result<(), Error> {
+    // Lines are drawn through the middle of pixels so that they are exactly one pixel wide
+    let pixel = Point {
+        x: pointer.x.floor(),
+        y: pointer.y.floor(),
+    };
+
+    cairo.save()?;
+    draw_crosshair(cairo, screen, pixel)?;
+
+    let readout = match selection.map(|selection| selection.normalised()) {
+        Some(Rectangle { x, y, w, h }) => format!("{w:.0} × {h:.0}\n{x:.0}, {y:.0}"),
+        None => format!("{:.0}, {:.0}", pixel.x, pixel.y),
+    };
+
+    let layout = create_layout(cairo);
+    layout.set_text(&readout);
+    layout.set_font_description(Some(&FontDescription::from_string("Sans 10")));
+    let (_, extents) = layout.pixel_extents();
+    let readout_height = extents.height() as f64 + 2.0 * PADDING;
+
+    // The loupe and readout go below and to the right of the pointer, unless that would put them
+    // off the screen
+    let total_height = LOUPE_SIZE + readout_height;
+    let x = if pixel.x + POINTER_DISTANCE + LOUPE_SIZE > screen.x + screen.w {
+        pixel.x - POINTER_DISTANCE - LOUPE_SIZE
+    } else {
+        pixel.x + POINTER_DISTANCE
+    };
+    let y = if pixel.y + POINTER_DISTANCE + total_height > screen.y + screen.h {
+        pixel.y - POINTER_DISTANCE - total_height
+    } else {
+        pixel.y + POINTER_DISTANCE
+    };
+    let top_left = Point {
+        x: x.max(screen.x),
+        y: y.max(screen.y),
+    };
+
+    draw_loupe(cairo, screenshot, top_left, pixel)?;
+
+    cairo.rectangle(
+        top_left.x,
+        top_left.y + LOUPE_SIZE,
+        LOUPE_SIZE,
+        readout_height,
+    );
+    cairo.set_source_colour(BACKGROUND);
+    cairo.fill()?;
+
+    cairo.move_to(
+        top_left.x + (LOUPE_SIZE - extents.width() as f64) / 2.0,
+        top_left.y + LOUPE_SIZE + PADDING,
+    );
+    cairo.set_source_colour(FOREGROUND);
+    show_layout(cairo, &layout);
+
+    cairo.restore()?;
+
+    Ok(())
+}
+
+fn draw_crosshair(cairo: &Context, screen: Rectangle, pixel: Point) -> Result<(), Error> {
+    cairo.set_dash(&[], 0.0);
+    cairo.set_line_width(1.0);
+    cairo.move_to(screen.x, pixel.y + 0.5);
+    cairo.line_to(screen.x + screen.w, pixel.y + 0.5);
+    cairo.move_to(pixel.x + 0.5, screen.y);
+    cairo.line_to(pixel.x + 0.5, screen.y + screen.h);
+    cairo.set_source_colour(WINDOW_INDICATOR_COLOUR);
+    cairo.stroke()?;
+
+    Ok(())
+}
+
+/// Draws the pixels around `pixel` magnified, with their top left corner at `top_left`
+fn draw_loupe(
+    cairo: &Context,
+    screenshot: &ImageSurface,
+    top_left: Point,
+    pixel: Point,
+) -> Result<(), Error> {
+    cairo.rectangle(top_left.x, top_left.y, LOUPE_SIZE, LOUPE_SIZE);
+    // Parts of the loupe can be outside of the screenshot
+    cairo.set_source_colour(BACKGROUND);
+    cairo.fill_preserve()?;
+
+    cairo.save()?;
+    cairo.clip();
+    let centre_offset = (LOUPE_PIXELS - 1.0) / 2.0 * LOUPE_ZOOM;
+    cairo.translate(top_left.x + centre_offset, top_left.y + centre_offset);
+    cairo.scale(LOUPE_ZOOM, LOUPE_ZOOM);
+    cairo.set_source_surface(screenshot, -pixel.x, -pixel.y)?;
+    // Anything but nearest neighbour would blur the edges of the pixels
+    cairo.source().set_filter(Filter::Nearest);
+    cairo.paint()?;
+    cairo.restore()?;
+
+    // Outline the pixel under the pointer with both colours, so it can be seen on any background
+    let centre = Point {
+        x: top_left.x + centre_offset,
+        y: top_left.y + centre_offset,
+    };
+    cairo.set_line_width(1.0);
+    cairo.rectangle(
+        centre.x - 0.5,
+        centre.y - 0.5,
+        LOUPE_ZOOM + 1.0,
+        LOUPE_ZOOM + 1.0,
+    );
+    cairo.set_source_colour(BACKGROUND);
+    cairo.stroke()?;
+    cairo.rectangle(
+        centre.x + 0.5,
+        centre.y + 0.5,
+        LOUPE_ZOOM - 1.0,
+        LOUPE_ZOOM - 1.0,
+    );
+    cairo.set_source_colour(FOREGROUND);
+    cairo.stroke()?;
+
+    cairo.rectangle(
+        top_left.x + 0.5,
+        top_left.y + 0.5,
+        LOUPE_SIZE - 1.0,
+        LOUPE_SIZE - 1.0,
+    );
+    cairo.set_source_colour(WINDOW_INDICATOR_COLOUR);
+    cairo.stroke()?;
+
+    Ok(())
+}
@@ -10,9 +10,6 @@ use stackblur_iter::{blur_argb as stackblur, imgref::ImgRefMut};
 use super::Error;
 use crate::editor::utils;
 
-/// How big will pixelate boxes be, in this case, we will group the rectangle into 4x4 boxes, which we will set all of its pixels to the same value
-const PIXELATE_SIZE: u64 = 4;
-
 pub fn blur(
     cairo: &Context,
     radius: usize,
@@ -154,7 +151,9 @@ fn blur_rgb(
     (pixel_data, (width * bytes_per_pixel) as i32)
 }
 
-pub fn pixelate(cairo: &Context, rect: &Rectangle, seed: u64) -> Result<(), Error> {
+/// Groups `rect` into `size`x`size` boxes, and sets all of the pixels of each box to the same value
+pub fn pixelate(cairo: &Context, rect: &Rectangle, seed: u64, size: u64) -> Result<(), Error> {
+    let size = size.max(1);
     let mut rng = StdRng::seed_from_u64(seed);
 
     let pixbuf = utils::pixbuf_for(&cairo.target(), *rect).ok_or(Error::Pixbuf(*rect))?;
@@ -166,10 +165,10 @@ pub fn pixelate(cairo: &Context, rect: &Rectangle, seed: u64) -> Result<(), Erro
     let bytes_per_pixel = pixbuf.n_channels() as u64 * (pixbuf.bits_per_sample() / 8) as u64;
     let &Rectangle { x, y, w, h } = rect;
 
-    for i in (0..(w as u64)).step_by(PIXELATE_SIZE as usize) {
-        for j in (0..(h as u64)).step_by(PIXELATE_SIZE as usize) {
-            let pixelate_size_x: u64 = PIXELATE_SIZE.min(w as u64 - i);
-            let pixelate_size_y: u64 = PIXELATE_SIZE.min(h as u64 - j);
+    for i in (0..(w as u64)).step_by(size as usize) {
+        for j in (0..(h as u64)).step_by(size as usize) {
+            let pixelate_size_x: u64 = size.min(w as u64 - i);
+            let pixelate_size_y: u64 = size.min(h as u64 - j);
 
             let sample_x: u64 = i + rng.random_range(0..pixelate_size_x);
             let sample_y: u64 = j + rng.random_range(0..pixelate_size_y);
@@ -0,0 +1,628 @@
+//! Picking operations that were already drawn with [`Tool::Select`](super::Tool::Select), so that
+//! they can be moved around, resized, deleted or put above or below other operations.
+
+use std::f64::consts::PI;
+
+use cairo::Context;
+use gtk4::pango::{self, FontDescription, prelude::*};
+use kcshot_data::{
+    colour::Colour,
+    geometry::{Ellipse, Point, Rectangle},
+};
+
+use super::{BUBBLE_RADIUS, Error, Operation, shapes};
+use crate::editor::utils::CairoExt;
+
+/// How far away from an operation clicks still pick it, so that thin lines can be picked
+const HIT_TOLERANCE: f64 = 4.0;
+/// How big the squares that can be dragged to resize the selected operation are
+const HANDLE_SIZE: f64 = 8.0;
+
+const SELECTION_COLOUR: Colour = Colour {
+    red: 0,
+    green: 127,
+    blue: 190,
+    alpha: 255,
+};
+
+/// The places on the bounds of the selected operation that can be dragged to resize it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Handle {
+    TopLeft,
+    Top,
+    TopRight,
+    Right,
+    BottomRight,
+    Bottom,
+    BottomLeft,
+    Left,
+}
+
+impl Handle {
+    const ALL: [Self; 8] = [
+        Self::TopLeft,
+        Self::Top,
+        Self::TopRight,
+        Self::Right,
+        Self::BottomRight,
+        Self::Bottom,
+        Self::BottomLeft,
+        Self::Left,
+    ];
+
+    /// Returns the handle of `bounds` that is under `point`, if there is one
+    pub fn at(bounds: Rectangle, point: Point) -> Option<Self> {
+        Self::ALL.into_iter().find(|handle| {
+            let centre = handle.centre(bounds);
+            (point.x - centre.x).abs() <= HANDLE_SIZE / 2.0 + HIT_TOLERANCE / 2.0
+                && (point.y - centre.y).abs() <= HANDLE_SIZE / 2.0 + HIT_TOLERANCE / 2.0
+        })
+    }
+
+    fn centre(self, Rectangle { x, y, w, h }: Rectangle) -> Point {
+        let (fx, fy) = match self {
+            Self::TopLeft => (0.0, 0.0),
+            Self::Top => (0.5, 0.0),
+            Self::TopRight => (1.0, 0.0),
+            Self::Right => (1.0, 0.5),
+            Self::BottomRight => (1.0, 1.0),
+            Self::Bottom => (0.5, 1.0),
+            Self::BottomLeft => (0.0, 1.0),
+            Self::Left => (0.0, 0.5),
+        };
+
+        Point {
+            x: x + fx * w,
+            y: y + fy * h,
+        }
+    }
+
+    /// Returns `bounds` after this handle was dragged `offset` away from where it was
+    pub fn drag(self, bounds: Rectangle, offset: Point) -> Rectangle {
+        let Rectangle {
+            mut x,
+            mut y,
+            mut w,
+            mut h,
+        } = bounds;
+
+        if matches!(self, Self::TopLeft | Self::BottomLeft | Self::Left) {
+            x += offset.x;
+            w -= offset.x;
+        }
+        if matches!(self, Self::TopRight | Self::BottomRight | Self::Right) {
+            w += offset.x;
+        }
+        if matches!(self, Self::TopLeft | Self::TopRight | Self::Top) {
+            y += offset.y;
+            h -= offset.y;
+        }
+        if matches!(self, Self::BottomLeft | Self::BottomRight | Self::Bottom) {
+            h += offset.y;
+        }
+
+        Rectangle { x, y, w, h }
+    }
+}
+
+impl Operation {
+    /// Returns the smallest rectangle containing the operation, or `None` if the operation can't be
+    /// selected
+    pub(super) fn bounds(&self) -> Option<Rectangle> {
+        Some(match self {
+            Operation::Crop(_) => return None,
+            Operation::Blur { rect, .. }
+            | Operation::Pixelate { rect, .. }
+            | Operation::DrawRectangle { rect, .. }
+            | Operation::Highlight { rect, .. } => rect.normalised(),
+            Operation::DrawArrow {
+                start,
+                end,
+                control: Some(control),
+                ..
+            } => bounds_of_points(shapes::quadratic_extremes(*start, *control, *end)),
+            Operation::DrawLine { start, end, .. } | Operation::DrawArrow { start, end, .. } => {
+                bounds_of_points([*start, *end])
+            }
+            Operation::DrawEllipse { ellipse, .. } => {
+                let (centre, rx, ry) = ellipse_axes(ellipse);
+                Rectangle {
+                    x: centre.x - rx,
+                    y: centre.y - ry,
+                    w: 2.0 * rx,
+                    h: 2.0 * ry,
+                }
+            }
+            Operation::Text {
+                top_left,
+                text,
+                font_description,
+                ..
+            } => text_bounds(*top_left, text, font_description),
+            Operation::Bubble { centre, .. } => Rectangle {
+                x: centre.x - BUBBLE_RADIUS,
+                y: centre.y - BUBBLE_RADIUS,
+                w: 2.0 * BUBBLE_RADIUS,
+                h: 2.0 * BUBBLE_RADIUS,
+            },
+            Operation::Pencil { start, points, .. } => {
+                bounds_of_points(std::iter::once(*start).chain(points.iter().copied()))
+            }
+        })
+    }
+
+    /// Text and bubbles keep their size, they can only be moved
+    pub(super) fn is_resizable(&self) -> bool {
+        !matches!(
+            self,
+            Operation::Crop(_) | Operation::Text { .. } | Operation::Bubble { .. }
+        )
+    }
+
+    /// Returns whether clicking on `point` picks this operation
+    pub(super) fn contains(&self, point: Point) -> bool {
+        match self {
+            Operation::Crop(_) => false,
+            Operation::DrawArrow {
+                start,
+                end,
+                control: Some(control),
+                line_width,
+                ..
+            } => {
+                let tolerance = line_width / 2.0 + HIT_TOLERANCE;
+                shapes::flatten_quadratic(*start, *control, *end)
+                    .windows(2)
+                    .any(|segment| distance_to_segment(point, segment[0], segment[1]) <= tolerance)
+            }
+            Operation::DrawLine {
+                start,
+                end,
+                line_width,
+                ..
+            }
+            | Operation::DrawArrow {
+                start,
+                end,
+                line_width,
+                ..
+            } => distance_to_segment(point, *start, *end) <= line_width / 2.0 + HIT_TOLERANCE,
+            Operation::DrawRectangle {
+                rect,
+                fill,
+                line_width,
+                ..
+            } => {
+                let tolerance = line_width / 2.0 + HIT_TOLERANCE;
+                let outer = grow(rect.normalised(), tolerance);
+                let inner = grow(rect.normalised(), -tolerance);
+
+                contains(outer, point) && (fill.alpha != 0 || !contains(inner, point))
+            }
+            Operation::DrawEllipse {
+                ellipse,
+                fill,
+                line_width,
+                ..
+            } => {
+                let tolerance = line_width / 2.0 + HIT_TOLERANCE;
+                let (centre, rx, ry) = ellipse_axes(ellipse);
+                let inside = |rx: f64, ry: f64| {
+                    rx > 0.0
+                        && ry > 0.0
+                        && ((point.x - centre.x) / rx).powi(2) + ((point.y - centre.y) / ry).powi(2)
+                            <= 1.0
+                };
+
+                inside(rx + tolerance, ry + tolerance)
+                    && (fill.alpha != 0 || !inside(rx - tolerance, ry - tolerance))
+            }
+            Operation::Bubble { centre, .. } => (point - *centre).dist() <= BUBBLE_RADIUS,
+            Operation::Pencil {
+                start,
+                points,
+                line_width,
+                ..
+            } => {
+                let tolerance = line_width / 2.0 + HIT_TOLERANCE;
+                if points.is_empty() {
+                    return (point - *start).dist() <= tolerance;
+                }
+
+                let mut previous = *start;
+                points.iter().any(|&next| {
+                    let distance = distance_to_segment(point, previous, next);
+                    previous = next;
+                    distance <= tolerance
+                })
+            }
+            Operation::Blur { .. }
+            | Operation::Pixelate { .. }
+            | Operation::Highlight { .. }
+            | Operation::Text { .. } => self.bounds().is_some_and(|bounds| contains(bounds, point)),
+        }
+    }
+
+    /// Maps every point of the operation from `from` onto `to`, which moves the operation if they
+    /// have the same size and resizes it otherwise
+    pub(super) fn transform(&mut self, from: Rectangle, to: Rectangle) {
+        let map = |Point { x, y }: Point| Point {
+            x: map_coordinate(x, from.x, from.w, to.x, to.w),
+            y: map_coordinate(y, from.y, from.h, to.y, to.h),
+        };
+        let map_rect = |Rectangle { x, y, w, h }: Rectangle| {
+            let top_left = map(Point { x, y });
+            let bottom_right = map(Point { x: x + w, y: y + h });
+
+            Rectangle {
+                x: top_left.x,
+                y: top_left.y,
+                w: bottom_right.x - top_left.x,
+                h: bottom_right.y - top_left.y,
+            }
+        };
+
+        match self {
+            Operation::Crop(_) => {}
+            Operation::Blur { rect, .. }
+            | Operation::Pixelate { rect, .. }
+            | Operation::DrawRectangle { rect, .. }
+            | Operation::Highlight { rect, .. } => *rect = map_rect(*rect),
+            Operation::DrawLine { start, end, .. } => {
+                *start = map(*start);
+                *end = map(*end);
+            }
+            Operation::DrawArrow {
+                start,
+                end,
+                control,
+                ..
+            } => {
+                *start = map(*start);
+                *end = map(*end);
+                *control = control.map(map);
+            }
+            Operation::DrawEllipse { ellipse, .. } => {
+                let Rectangle { x, y, w, h } = map_rect(Rectangle {
+                    x: ellipse.x,
+                    y: ellipse.y,
+                    w: ellipse.w,
+                    h: ellipse.h,
+                });
+                *ellipse = Ellipse { x, y, w, h };
+            }
+            Operation::Text { top_left, .. } => *top_left = map(*top_left),
+            Operation::Bubble { centre, .. } => *centre = map(*centre),
+            Operation::Pencil { start, points, .. } => {
+                *start = map(*start);
+                for point in points {
+                    *point = map(*point);
+                }
+            }
+        }
+    }
+
+    /// Returns where the handle for bending the operation is, if it can be bent. Arrows are bent by
+    /// dragging the point halfway along them.
+    pub(super) fn bend_handle(&self) -> Option<Point> {
+        match self {
+            Operation::DrawArrow {
+                start,
+                end,
+                control,
+                ..
+            } => Some(match control {
+                Some(control) => shapes::quadratic_point(*start, *control, *end, 0.5),
+                None => (*start + *end) * 0.5,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Bends the operation so that the point halfway along it is `middle`, see
+    /// [`Self::bend_handle`]
+    pub(super) fn bend_through(&mut self, middle: Point) {
+        if let Operation::DrawArrow {
+            start,
+            end,
+            control,
+            ..
+        } = self
+        {
+            *control = Some(shapes::control_through(*start, middle, *end));
+        }
+    }
+}
+
+/// Returns whether `point` is on the bend handle at `handle`, see [`Operation::bend_handle`]
+pub fn is_on_bend_handle(handle: Point, point: Point) -> bool {
+    (point - handle).dist() <= HANDLE_SIZE / 2.0 + HIT_TOLERANCE / 2.0
+}
+
+/// Draws the handle for bending an operation, which is round to set it apart from the ones for
+/// resizing
+pub fn draw_bend_handle(cairo: &Context, handle: Point) -> Result<(), Error> {
+    cairo.save()?;
+
+    cairo.set_line_width(1.0);
+    cairo.set_dash(&[], 0.0);
+    cairo.new_sub_path();
+    cairo.arc(handle.x, handle.y, HANDLE_SIZE / 2.0, 0.0, 2.0 * PI);
+    cairo.set_source_colour(Colour {
+        red: 255,
+        green: 255,
+        blue: 255,
+        alpha: 255,
+    });
+    cairo.fill_preserve()?;
+    cairo.set_source_colour(SELECTION_COLOUR);
+    cairo.stroke()?;
+
+    cairo.restore()?;
+
+    Ok(())
+}
+
+/// Outlines `bounds`, with handles for resizing on it if `resizable` is set
+pub fn draw_selection(cairo: &Context, bounds: Rectangle, resizable: bool) -> Result<(), Error> {
+    cairo.save()?;
+
+    cairo.set_line_width(1.0);
+    cairo.set_dash(&[4.0, 4.0], 0.0);
+    cairo.rectangle(bounds.x, bounds.y, bounds.w, bounds.h);
+    cairo.set_source_colour(SELECTION_COLOUR);
+    cairo.stroke()?;
+
+    if resizable {
+        cairo.set_dash(&[], 0.0);
+        for handle in Handle::ALL {
+            let centre = handle.centre(bounds);
+            cairo.rectangle(
+                centre.x - HANDLE_SIZE / 2.0,
+                centre.y - HANDLE_SIZE / 2.0,
+                HANDLE_SIZE,
+                HANDLE_SIZE,
+            );
+        }
+        cairo.set_source_colour(Colour {
+            red: 255,
+            green: 255,
+            blue: 255,
+            alpha: 255,
+        });
+        cairo.fill_preserve()?;
+        cairo.set_source_colour(SELECTION_COLOUR);
+        cairo.stroke()?;
+    }
+
+    cairo.restore()?;
+
+    Ok(())
+}
+
+/// Maps `value` from the span starting at `from` that is `from_len` long onto the one starting at
+/// `to` that is `to_len` long. Spans without a length can only be moved.
+fn map_coordinate(value: f64, from: f64, from_len: f64, to: f64, to_len: f64) -> f64 {
+    if from_len == 0.0 {
+        value + to - from
+    } else {
+        to + (value - from) * to_len / from_len
+    }
+}
+
+/// Returns the centre of the ellipse that gets drawn for `ellipse`, along with its radii
+fn ellipse_axes(ellipse: &Ellipse) -> (Point, f64, f64) {
+    // See shapes::draw_ellipse, the ellipse is a circle with a radius of 1 on a unit square
+    let centre = Point {
+        x: ellipse.x + ellipse.w / 2.0,
+        y: ellipse.y + ellipse.h / 2.0,
+    };
+
+    (centre, ellipse.w.abs(), ellipse.h.abs())
+}
+
+fn text_bounds(top_left: Point, text: &str, font_description: &FontDescription) -> Rectangle {
+    let context = pangocairo::FontMap::default().create_context();
+    let layout = pango::Layout::new(&context);
+    layout.set_markup(text);
+    layout.set_font_description(Some(font_description));
+
+    let (_, extents) = layout.pixel_extents();
+
+    Rectangle {
+        x: top_left.x + extents.x() as f64,
+        y: top_left.y + extents.y() as f64,
+        w: extents.width() as f64,
+        h: extents.height() as f64,
+    }
+}
+
+fn bounds_of_points(points: impl IntoIterator<Item = Point>) -> Rectangle {
+    let mut points = points.into_iter();
+    let Some(first) = points.next() else {
+        return Rectangle {
+            x: 0.0,
+            y: 0.0,
+            w: 0.0,
+            h: 0.0,
+        };
+    };
+
+    points.fold(
+        Rectangle {
+            x: first.x,
+            y: first.y,
+            w: 0.0,
+            h: 0.0,
+        },
+        |bounds, point| {
+            bounds.union(&Rectangle {
+                x: point.x,
+                y: point.y,
+                w: 0.0,
+                h: 0.0,
+            })
+        },
+    )
+}
+
+fn distance_to_segment(point: Point, start: Point, end: Point) -> f64 {
+    let segment = end - start;
+    let length_squared = segment.x * segment.x + segment.y * segment.y;
+    if length_squared == 0.0 {
+        return (point - start).dist();
+    }
+
+    let along = point - start;
+    let t = ((along.x * segment.x + along.y * segment.y) / length_squared).clamp(0.0, 1.0);
+
+    (point - (start + segment * t)).dist()
+}
+
+fn grow(Rectangle { x, y, w, h }: Rectangle, by: f64) -> Rectangle {
+    Rectangle {
+        x: x - by,
+        y: y - by,
+        w: w + 2.0 * by,
+        h: h + 2.0 * by,
+    }
+}
+
+/// Like [`Rectangle::contains`], but the edges count as inside too
+fn contains(Rectangle { x, y, w, h }: Rectangle, point: Point) -> bool {
+    (x..=x + w).contains(&point.x) && (y..=y + h).contains(&point.y)
+}
+
+#[cfg(test)]
+mod tests {
+    use kcshot_data::{
+        colour::Colour,
+        geometry::{Point, Rectangle},
+    };
+
+    use super::{Handle, map_coordinate};
+    use crate::editor::operations::{INVISIBLE, Operation, Stroke};
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    fn rect(x: f64, y: f64, w: f64, h: f64) -> Rectangle {
+        Rectangle { x, y, w, h }
+    }
+
+    fn line(start: Point, end: Point) -> Operation {
+        Operation::DrawLine {
+            start,
+            end,
+            colour: Colour::BLACK,
+            line_width: 2.0,
+            stroke: Stroke::SOLID,
+        }
+    }
+
+    fn rectangle(rect: Rectangle, fill: Colour) -> Operation {
+        Operation::DrawRectangle {
+            rect,
+            border: Colour::BLACK,
+            fill,
+            line_width: 2.0,
+            stroke: Stroke::SOLID,
+        }
+    }
+
+    #[test]
+    fn dragging_corner_handles() {
+        let bounds = rect(10.0, 10.0, 20.0, 20.0);
+
+        assert_eq!(
+            Handle::BottomRight.drag(bounds, point(5.0, -5.0)),
+            rect(10.0, 10.0, 25.0, 15.0)
+        );
+        assert_eq!(
+            Handle::TopLeft.drag(bounds, point(2.0, 3.0)),
+            rect(12.0, 13.0, 18.0, 17.0)
+        );
+    }
+
+    #[test]
+    fn dragging_edge_handles_only_changes_one_axis() {
+        let bounds = rect(10.0, 10.0, 20.0, 20.0);
+
+        assert_eq!(
+            Handle::Top.drag(bounds, point(7.0, 4.0)),
+            rect(10.0, 14.0, 20.0, 16.0)
+        );
+        assert_eq!(
+            Handle::Right.drag(bounds, point(7.0, 4.0)),
+            rect(10.0, 10.0, 27.0, 20.0)
+        );
+    }
+
+    #[test]
+    fn handles_are_found_around_their_centre() {
+        let bounds = rect(10.0, 10.0, 20.0, 20.0);
+
+        assert_eq!(Handle::at(bounds, point(11.0, 9.0)), Some(Handle::TopLeft));
+        assert_eq!(Handle::at(bounds, point(20.0, 30.0)), Some(Handle::Bottom));
+        assert_eq!(Handle::at(bounds, point(20.0, 20.0)), None);
+    }
+
+    #[test]
+    fn bounds_are_normalised() {
+        let blur = Operation::Blur {
+            rect: rect(10.0, 10.0, -5.0, -5.0),
+            radius: 1.0,
+        };
+        assert_eq!(blur.bounds(), Some(rect(5.0, 5.0, 5.0, 5.0)));
+
+        let line = line(point(10.0, 0.0), point(0.0, 5.0));
+        assert_eq!(line.bounds(), Some(rect(0.0, 0.0, 10.0, 5.0)));
+
+        assert_eq!(Operation::Crop(rect(0.0, 0.0, 1.0, 1.0)).bounds(), None);
+    }
+
+    #[test]
+    fn lines_contain_points_close_to_them() {
+        let line = line(point(0.0, 0.0), point(10.0, 0.0));
+
+        assert!(line.contains(point(5.0, 4.0)));
+        assert!(!line.contains(point(5.0, 6.0)));
+        assert!(!line.contains(point(20.0, 0.0)));
+    }
+
+    #[test]
+    fn only_filled_rectangles_contain_their_inside() {
+        let bounds = rect(0.0, 0.0, 100.0, 100.0);
+
+        let outline = rectangle(bounds, INVISIBLE);
+        assert!(outline.contains(point(0.0, 50.0)));
+        assert!(!outline.contains(point(50.0, 50.0)));
+
+        let filled = rectangle(bounds, Colour::BLACK);
+        assert!(filled.contains(point(50.0, 50.0)));
+        assert!(!filled.contains(point(150.0, 50.0)));
+    }
+
+    #[test]
+    fn transform_moves_and_resizes() {
+        let mut moved = rectangle(rect(0.0, 0.0, 10.0, 10.0), INVISIBLE);
+        moved.transform(rect(0.0, 0.0, 10.0, 10.0), rect(5.0, 5.0, 10.0, 10.0));
+        assert_eq!(moved.bounds(), Some(rect(5.0, 5.0, 10.0, 10.0)));
+
+        let mut resized = line(point(0.0, 0.0), point(10.0, 10.0));
+        resized.transform(rect(0.0, 0.0, 10.0, 10.0), rect(0.0, 0.0, 20.0, 5.0));
+        assert_eq!(resized.bounds(), Some(rect(0.0, 0.0, 20.0, 5.0)));
+    }
+
+    #[test]
+    fn transform_moves_spans_without_a_length() {
+        // Horizontal lines have no height, so they can only be moved vertically
+        let mut line = line(point(0.0, 5.0), point(10.0, 5.0));
+        line.transform(rect(0.0, 5.0, 10.0, 0.0), rect(0.0, 7.0, 20.0, 4.0));
+        assert_eq!(line.bounds(), Some(rect(0.0, 7.0, 20.0, 0.0)));
+
+        assert_eq!(map_coordinate(3.0, 1.0, 0.0, 4.0, 10.0), 6.0);
+        assert_eq!(map_coordinate(3.0, 1.0, 4.0, 4.0, 8.0), 8.0);
+    }
+}
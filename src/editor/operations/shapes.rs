@@ -1,7 +1,12 @@
 use std::f64::consts::PI;
 
-use cairo::Context;
-use kcshot_data::{colour::Colour, geometry::*};
+use cairo::{Context, LineCap, LineJoin, PathSegment};
+use kcshot_data::{
+    colour::Colour,
+    geometry::*,
+    settings::{ArrowHead, StrokeStyle},
+};
+use rand::{Rng, SeedableRng, prelude::StdRng};
 
 use super::Error;
 use crate::editor::utils::CairoExt;
@@ -10,6 +15,156 @@ use crate::editor::utils::CairoExt;
 const ARROWHEAD_LENGTH_RATIO: f64 = 0.1;
 /// How open/closed the arrowhead will be
 const ARROWHEAD_APERTURE: f64 = PI / 6.0;
+/// How big the dots at the ends of arrows are, relative to their line width
+const DOT_RADIUS_RATIO: f64 = 1.5;
+/// How many straight segments curves are split into when they are hit-tested
+const CURVE_SEGMENTS: usize = 32;
+/// How far apart the points sketchy strokes wobble around are
+const SKETCH_WAVELENGTH: f64 = 16.0;
+/// How many times sketchy strokes go over their path
+const SKETCH_PASSES: usize = 2;
+
+/// How an outline is drawn
+#[derive(Clone, Copy, Debug)]
+pub struct Stroke {
+    pub style: StrokeStyle,
+    /// Keeps the wobble of sketchy strokes the same every time they are drawn
+    pub seed: u64,
+}
+
+impl Stroke {
+    pub const SOLID: Self = Self {
+        style: StrokeStyle::Solid,
+        seed: 0,
+    };
+}
+
+/// Strokes and clears the current path, like [`Context::stroke`] but in the style of `stroke`
+pub fn stroke(
+    cairo: &Context,
+    colour: Colour,
+    line_width: f64,
+    stroke: Stroke,
+) -> Result<(), Error> {
+    cairo.save()?;
+    cairo.set_source_colour(colour);
+    cairo.set_line_width(line_width);
+
+    match stroke.style {
+        StrokeStyle::Solid => cairo.set_dash(&[], 0.0),
+        StrokeStyle::Dashed => cairo.set_dash(&[3.0 * line_width, 2.0 * line_width], 0.0),
+        StrokeStyle::Dotted => {
+            // Dashes of length zero with round caps are drawn as dots
+            cairo.set_line_cap(LineCap::Round);
+            cairo.set_dash(&[0.0, 2.0 * line_width], 0.0);
+        }
+        StrokeStyle::Sketchy => {
+            let path = cairo.copy_path_flat()?;
+            cairo.new_path();
+
+            let mut rng = StdRng::seed_from_u64(stroke.seed);
+            let amplitude = (line_width / 2.0).max(1.0);
+            for _ in 0..SKETCH_PASSES {
+                for polyline in polylines(&path) {
+                    let mut points = sketch(&polyline, amplitude, &mut rng).into_iter();
+                    if let Some(first) = points.next() {
+                        cairo.move_to(first.x, first.y);
+                    }
+                    for point in points {
+                        cairo.line_to(point.x, point.y);
+                    }
+                }
+            }
+
+            cairo.set_dash(&[], 0.0);
+            cairo.set_line_cap(LineCap::Round);
+            cairo.set_line_join(LineJoin::Round);
+        }
+    }
+
+    cairo.stroke()?;
+    cairo.restore()?;
+
+    Ok(())
+}
+
+/// Splits a flattened path into the lines it's made of
+fn polylines(path: &cairo::Path) -> Vec<Vec<Point>> {
+    let mut polylines: Vec<Vec<Point>> = vec![];
+
+    for segment in path.iter() {
+        match segment {
+            PathSegment::MoveTo(point) => polylines.push(vec![point.into()]),
+            PathSegment::LineTo(point) => match polylines.last_mut() {
+                Some(polyline) => polyline.push(point.into()),
+                None => polylines.push(vec![point.into()]),
+            },
+            PathSegment::ClosePath => {
+                if let Some(polyline) = polylines.last_mut()
+                    && let Some(&first) = polyline.first()
+                {
+                    polyline.push(first);
+                }
+            }
+            // Flattened paths don't have any curves
+            PathSegment::CurveTo(..) => {}
+        }
+    }
+
+    polylines
+}
+
+/// Moves the points of `polyline` around by up to `amplitude` so it looks like it was drawn by hand.
+///
+/// Offsets are picked every [`SKETCH_WAVELENGTH`] along the line and blended in between, so that the
+/// line wobbles instead of looking noisy.
+fn sketch(polyline: &[Point], amplitude: f64, rng: &mut impl Rng) -> Vec<Point> {
+    let mut random_offset = || Point {
+        x: rng.random_range(-amplitude..=amplitude),
+        y: rng.random_range(-amplitude..=amplitude),
+    };
+    let mut offsets = vec![];
+    let mut travelled = 0.0;
+
+    let points = subdivide(polyline, SKETCH_WAVELENGTH / 2.0);
+    let mut previous = points.first().copied();
+    points
+        .into_iter()
+        .map(|point| {
+            if let Some(previous) = previous.replace(point) {
+                travelled += (point - previous).dist();
+            }
+
+            let position = travelled / SKETCH_WAVELENGTH;
+            let idx = position.floor() as usize;
+            while offsets.len() < idx + 2 {
+                offsets.push(random_offset());
+            }
+
+            let t = position.fract();
+            point + offsets[idx] * (1.0 - t) + offsets[idx + 1] * t
+        })
+        .collect()
+}
+
+/// Adds points to `polyline` so that none of its segments are longer than `max_length`, keeping
+/// the points it already had
+fn subdivide(polyline: &[Point], max_length: f64) -> Vec<Point> {
+    let Some(&first) = polyline.first() else {
+        return vec![];
+    };
+
+    let mut points = vec![first];
+    for segment in polyline.windows(2) {
+        let (start, end) = (segment[0], segment[1]);
+        let pieces = ((end - start).dist() / max_length).ceil().max(1.0);
+        for i in 1..=pieces as usize {
+            points.push(start + (end - start) * (i as f64 / pieces));
+        }
+    }
+
+    points
+}
 
 pub fn draw_rectangle(
     cairo: &Context,
@@ -17,6 +172,7 @@ pub fn draw_rectangle(
     border: Colour,
     fill: Colour,
     line_width: f64,
+    border_stroke: Stroke,
 ) -> Result<(), Error> {
     cairo.save()?;
     let Rectangle { x, y, w, h } = rect.normalised();
@@ -25,9 +181,7 @@ pub fn draw_rectangle(
     cairo.set_source_colour(fill);
     cairo.fill_preserve()?;
 
-    cairo.set_source_colour(border);
-    cairo.set_line_width(line_width);
-    cairo.stroke()?;
+    stroke(cairo, border, line_width, border_stroke)?;
     cairo.restore()?;
 
     Ok(())
@@ -77,6 +231,7 @@ pub fn draw_ellipse(
     border: Colour,
     fill: Colour,
     line_width: f64,
+    border_stroke: Stroke,
 ) -> Result<(), Error> {
     cairo.save()?;
     // Avoid initial line from previous point if one exists
@@ -91,10 +246,8 @@ pub fn draw_ellipse(
     cairo.fill_preserve()?;
     cairo.restore()?;
 
-    cairo.set_source_colour(border);
-    cairo.set_line_width(line_width);
     // 4. Draw a border around it
-    cairo.stroke()?;
+    stroke(cairo, border, line_width, border_stroke)?;
 
     Ok(())
 }
@@ -105,50 +258,287 @@ pub fn draw_line(
     Point { x: x2, y: y2 }: Point,
     colour: Colour,
     line_width: f64,
+    line_stroke: Stroke,
 ) -> Result<(), Error> {
     cairo.move_to(x1, y1);
     cairo.line_to(x2, y2);
-    cairo.set_source_colour(colour);
-    cairo.set_line_width(line_width);
-    cairo.stroke()?;
+    stroke(cairo, colour, line_width, line_stroke)?;
 
     Ok(())
 }
 
+/// The ends of an arrow and what it looks like between them
+#[derive(Clone, Copy, Debug)]
+pub struct Arrow {
+    pub start: Point,
+    pub end: Point,
+    /// The control point of the quadratic Bézier curve the arrow follows, arrows without one are
+    /// straight
+    pub control: Option<Point>,
+    pub head: ArrowHead,
+    /// Whether there's a head at the start of the arrow too
+    pub double_headed: bool,
+}
+
 pub fn draw_arrow(
     cairo: &Context,
-    start: Point,
-    end: Point,
+    arrow: &Arrow,
     colour: Colour,
     line_width: f64,
+    body_stroke: Stroke,
 ) -> Result<(), Error> {
-    let angle = get_line_angle(start, end);
-    let length = (end - start).dist();
-    let arrow_length = length * ARROWHEAD_LENGTH_RATIO;
+    let Arrow {
+        start,
+        end,
+        control,
+        head,
+        double_headed,
+    } = *arrow;
+    let head_length = (end - start).dist() * ARROWHEAD_LENGTH_RATIO;
 
     cairo.move_to(start.x, start.y);
-    cairo.line_to(end.x, end.y);
+    match control {
+        Some(control) => {
+            // Cairo only draws cubic curves, but every quadratic curve can be turned into one
+            let first = start + (control - start) * (2.0 / 3.0);
+            let second = end + (control - end) * (2.0 / 3.0);
+            cairo.curve_to(first.x, first.y, second.x, second.y, end.x, end.y);
+        }
+        None => cairo.line_to(end.x, end.y),
+    }
+    stroke(cairo, colour, line_width, body_stroke)?;
 
-    // Since cos(theta) = adjacent / hypotenuse, x1 = arrow_length * cos(theta)
-    let x1 = -arrow_length * (angle - ARROWHEAD_APERTURE).cos();
-    let x2 = -arrow_length * (angle + ARROWHEAD_APERTURE).cos();
+    // Dashes and dots would break the head up, but it should still look hand-drawn if the body does
+    let head_stroke = match body_stroke.style {
+        StrokeStyle::Sketchy => body_stroke,
+        _ => Stroke::SOLID,
+    };
 
-    // Since sin(theta) = opposite / hypotenuse, y1 = arrow_length * sin(theta)
-    let y1 = -arrow_length * (angle - ARROWHEAD_APERTURE).sin();
-    let y2 = -arrow_length * (angle + ARROWHEAD_APERTURE).sin();
+    // Heads point the way the curve goes at their tip
+    let draw_head = |tip: Point, from: Point| -> Result<(), Error> {
+        match head {
+            ArrowHead::Open => {
+                let [left, right] = arrowhead_barbs(tip, get_line_angle(from, tip), head_length);
+                cairo.move_to(left.x, left.y);
+                cairo.line_to(tip.x, tip.y);
+                cairo.line_to(right.x, right.y);
+                stroke(cairo, colour, line_width, head_stroke)?;
+            }
+            ArrowHead::Filled => {
+                let [left, right] = arrowhead_barbs(tip, get_line_angle(from, tip), head_length);
+                cairo.move_to(left.x, left.y);
+                cairo.line_to(tip.x, tip.y);
+                cairo.line_to(right.x, right.y);
+                cairo.close_path();
+                cairo.set_source_colour(colour);
+                cairo.fill_preserve()?;
+                stroke(cairo, colour, line_width, head_stroke)?;
+            }
+            ArrowHead::None => {}
+            ArrowHead::Dot => {
+                cairo.new_sub_path();
+                cairo.arc(tip.x, tip.y, line_width * DOT_RADIUS_RATIO, 0.0, 2.0 * PI);
+                cairo.set_source_colour(colour);
+                cairo.fill()?;
+            }
+        }
 
-    cairo.rel_move_to(x1, y1);
-    cairo.line_to(end.x, end.y);
-    cairo.rel_line_to(x2, y2);
+        Ok(())
+    };
 
-    cairo.set_source_colour(colour);
-    cairo.set_line_width(line_width);
-    cairo.stroke()?;
+    draw_head(end, control.unwrap_or(start))?;
+    if double_headed {
+        draw_head(start, control.unwrap_or(end))?;
+    }
 
     Ok(())
 }
 
+/// Returns the ends of the two lines of an arrowhead that is `length` long, whose tip is at `tip`
+/// and which points in the direction of `angle`
+pub fn arrowhead_barbs(tip: Point, angle: f64, length: f64) -> [Point; 2] {
+    // Since cos(theta) = adjacent / hypotenuse, x = length * cos(theta), and similarly for y and
+    // sin(theta)
+    [angle - ARROWHEAD_APERTURE, angle + ARROWHEAD_APERTURE].map(|barb_angle| Point {
+        x: tip.x - length * barb_angle.cos(),
+        y: tip.y - length * barb_angle.sin(),
+    })
+}
+
+/// Returns the point `t` (between 0 and 1) of the way along the quadratic Bézier curve from
+/// `start` to `end`
+pub fn quadratic_point(start: Point, control: Point, end: Point, t: f64) -> Point {
+    start * ((1.0 - t) * (1.0 - t)) + control * (2.0 * (1.0 - t) * t) + end * (t * t)
+}
+
+/// Returns the control point of the quadratic Bézier curve from `start` to `end` which passes
+/// through `middle` halfway along it
+pub fn control_through(start: Point, middle: Point, end: Point) -> Point {
+    middle * 2.0 - (start + end) * 0.5
+}
+
+/// Returns the points of the quadratic Bézier curve from `start` to `end` which are furthest out,
+/// the curve fits in the smallest rectangle containing them
+pub fn quadratic_extremes(start: Point, control: Point, end: Point) -> Vec<Point> {
+    // Besides its ends, the curve only turns around where its derivative is zero on an axis
+    let turning_point = |start: f64, control: f64, end: f64| {
+        let denominator = start - 2.0 * control + end;
+        let t = (start - control) / denominator;
+        (denominator != 0.0 && t > 0.0 && t < 1.0).then_some(t)
+    };
+
+    let mut extremes = vec![start, end];
+    extremes.extend(
+        [
+            turning_point(start.x, control.x, end.x),
+            turning_point(start.y, control.y, end.y),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|t| quadratic_point(start, control, end, t)),
+    );
+
+    extremes
+}
+
+/// Returns points along the quadratic Bézier curve from `start` to `end`, so that it can be
+/// treated as a line made of straight segments
+pub fn flatten_quadratic(start: Point, control: Point, end: Point) -> Vec<Point> {
+    (0..=CURVE_SEGMENTS)
+        .map(|i| quadratic_point(start, control, end, i as f64 / CURVE_SEGMENTS as f64))
+        .collect()
+}
+
 fn get_line_angle(start: Point, end: Point) -> f64 {
     let Point { x, y } = end - start;
     y.atan2(x)
 }
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use kcshot_data::geometry::Point;
+    use rand::{SeedableRng, prelude::StdRng};
+
+    use super::{
+        CURVE_SEGMENTS, arrowhead_barbs, control_through, flatten_quadratic, quadratic_extremes,
+        quadratic_point, sketch, subdivide,
+    };
+
+    fn point(x: f64, y: f64) -> Point {
+        Point { x, y }
+    }
+
+    fn assert_close(actual: Point, expected: Point) {
+        assert!(
+            (actual - expected).dist() < 1e-9,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn arrowhead_barbs_are_behind_the_tip() {
+        let [left, right] = arrowhead_barbs(point(10.0, 0.0), 0.0, 2.0);
+
+        // The aperture is 30 degrees on each side
+        let back = 2.0 * (PI / 6.0).cos();
+        assert_close(left, point(10.0 - back, 1.0));
+        assert_close(right, point(10.0 - back, -1.0));
+    }
+
+    #[test]
+    fn quadratic_curve_points() {
+        let (start, control, end) = (point(0.0, 0.0), point(5.0, 10.0), point(10.0, 0.0));
+
+        assert_close(quadratic_point(start, control, end, 0.0), start);
+        assert_close(quadratic_point(start, control, end, 1.0), end);
+        assert_close(quadratic_point(start, control, end, 0.5), point(5.0, 5.0));
+    }
+
+    #[test]
+    fn control_through_middle() {
+        let (start, end) = (point(0.0, 0.0), point(10.0, 0.0));
+
+        let control = control_through(start, point(5.0, 5.0), end);
+
+        assert_close(control, point(5.0, 10.0));
+        // Bending a straight line through its own middle keeps it straight
+        assert_close(
+            control_through(start, point(5.0, 0.0), end),
+            point(5.0, 0.0),
+        );
+    }
+
+    #[test]
+    fn quadratic_extremes_include_the_peak() {
+        let (start, control, end) = (point(0.0, 0.0), point(5.0, 10.0), point(10.0, 0.0));
+
+        let extremes = quadratic_extremes(start, control, end);
+
+        // The curve only turns around on the y axis, at its middle
+        assert_eq!(extremes.len(), 3);
+        assert_close(extremes[2], point(5.0, 5.0));
+        assert!(extremes.iter().all(|p| p.y <= 5.0 + 1e-9));
+    }
+
+    #[test]
+    fn quadratic_extremes_of_a_straight_curve() {
+        let (start, control, end) = (point(0.0, 0.0), point(5.0, 5.0), point(10.0, 10.0));
+
+        assert_eq!(quadratic_extremes(start, control, end).len(), 2);
+    }
+
+    #[test]
+    fn flattened_curve_keeps_its_ends() {
+        let (start, control, end) = (point(0.0, 0.0), point(5.0, 10.0), point(10.0, 0.0));
+
+        let points = flatten_quadratic(start, control, end);
+
+        assert_eq!(points.len(), CURVE_SEGMENTS + 1);
+        assert_close(points[0], start);
+        assert_close(points[CURVE_SEGMENTS], end);
+    }
+
+    #[test]
+    fn subdivide_keeps_corners() {
+        let polyline = [point(0.0, 0.0), point(10.0, 0.0), point(10.0, 3.0)];
+
+        let points = subdivide(&polyline, 4.0);
+
+        // 10 is split into 3 pieces, 3 fits into a single one
+        assert_eq!(points.len(), 5);
+        assert_eq!((points[3].x, points[3].y), (10.0, 0.0));
+        assert_eq!((points[4].x, points[4].y), (10.0, 3.0));
+        assert!(
+            points
+                .windows(2)
+                .all(|pair| (pair[1] - pair[0]).dist() <= 4.0)
+        );
+    }
+
+    #[test]
+    fn subdivide_empty() {
+        assert!(subdivide(&[], 4.0).is_empty());
+    }
+
+    #[test]
+    fn sketch_stays_close_and_is_repeatable() {
+        let polyline = [point(0.0, 0.0), point(100.0, 0.0)];
+
+        let first = sketch(&polyline, 2.0, &mut StdRng::seed_from_u64(7));
+        let second = sketch(&polyline, 2.0, &mut StdRng::seed_from_u64(7));
+
+        assert_eq!(
+            first.len(),
+            subdivide(&polyline, super::SKETCH_WAVELENGTH / 2.0).len()
+        );
+        assert!(first.iter().all(|p| p.y.abs() <= 2.0));
+        assert!(
+            first
+                .iter()
+                .zip(&second)
+                .all(|(a, b)| a.x == b.x && a.y == b.y)
+        );
+    }
+}
@@ -1,77 +1,193 @@
-use cairo::Context;
+use cairo::{Context, ImageSurface};
+use gtk4::pango::{self, EllipsizeMode, FontDescription};
 use kcshot_data::{
     Text,
     colour::Colour,
     geometry::{Point, Rectangle},
+    settings::ToolProperties,
 };
 use kcshot_screenshot::Window;
+use pangocairo::functions::{create_layout, show_layout};
 use tracing::{error, warn};
 
-use super::{Operation, Tool};
+use super::{CursorLayer, Operation, Tool};
 use crate::{
-    editor::{operations::shapes, utils::CairoExt},
+    editor::{
+        operations::{
+            hud,
+            selection::{self, Handle},
+            shapes,
+        },
+        regions,
+        utils::CairoExt,
+    },
     log_if_err,
 };
 
+/// The colour in which the window the user is hovering over gets outlined
+pub(super) const WINDOW_INDICATOR_COLOUR: Colour = Colour {
+    red: 0,
+    green: 127,
+    blue: 190,
+    alpha: 255,
+};
+
 #[derive(Debug)]
 pub struct OperationStack {
     // The stack itself
     operations: Vec<Operation>,
-    undone_operations: Vec<Operation>,
+    /// The changes that revert what the user did to `operations`, most recent last
+    undo_stack: Vec<Change>,
+    /// The changes that redo what the user undid, most recently undone last
+    redo_stack: Vec<Change>,
 
     // State relating to the operation going on right now
     current_tool: Tool,
     current_operation: Option<Operation>,
     autoincrement_bubble_number: i32,
+    /// The mouse cursor, drawn below all the other operations
+    cursor: Option<CursorLayer>,
     pub primary_colour: Colour,
     pub secondary_colour: Colour,
-    pub line_width: f64,
+    /// What the current tool draws with
+    pub tool_properties: ToolProperties,
+
+    // State relating to the select tool
+    /// The operation picked with the select tool, as an index into `operations`
+    selected: Option<usize>,
+    selection_drag: Option<SelectionDrag>,
+    /// Whether the arrow placed last can be bent with the arrow tool, which is only the case until
+    /// the next click, so that new arrows can still start in the middle of it
+    bendable_arrow: bool,
 
     // State relating to crop selection
     pub selection_mode: SelectionMode,
     is_in_crop_drag: bool,
     /// This in in stacking order
     windows: Vec<Window>,
-    current_window: Option<usize>,
+    /// The monitors shown in the screenshot, relative to it
+    monitors: Vec<Rectangle>,
+    /// Boxes found in the screenshot itself, see [`regions`]
+    regions: Vec<Rectangle>,
+    /// The regions under the pointer, which the user can cycle through
+    candidates: Vec<Candidate>,
+    current_candidate: usize,
+    /// The region picked by the crop tool when editing started with cropping
+    cropped_candidate: Option<Candidate>,
     ignore_windows: bool,
+    /// Where the pointer was last seen, for drawing the selection aids
+    pointer: Option<Point>,
 
     /// Used for arrows, lines, pencil and the contours of rectangles
     editing_started_with_cropping: bool,
     pub screen_dimensions: Rectangle,
 }
 
+/// Where the selected operation can be moved to in the order operations are drawn in
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ZOrder {
+    /// Above the operation right above it
+    Raise,
+    /// Below the operation right below it
+    Lower,
+    /// Above all other operations
+    Top,
+    /// Below all other operations
+    Bottom,
+}
+
+/// What dragging the selected operation around does to it
+#[derive(Clone, Copy, Debug)]
+enum DragAction {
+    Move,
+    Resize(Handle),
+    /// See [`Operation::bend_handle`]
+    Bend,
+}
+
+/// The selected operation while it is being moved, resized or bent
+#[derive(Debug)]
+struct SelectionDrag {
+    action: DragAction,
+    /// The operation as it was when the drag started
+    original: Operation,
+    bounds: Rectangle,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum SelectionMode {
     WindowsWithDecorations,
     WindowsWithoutDecorations,
 }
 
+/// A region the user can crop to by clicking on it, instead of drawing the crop region themselves.
+///
+/// Windows refer to [`OperationStack::windows`] by index.
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Candidate {
+    /// A window along with its decorations
+    Frame(usize),
+    /// Only the contents of a window
+    Content(usize),
+    /// A window inside of a window
+    Child(usize, Rectangle),
+    /// A box found in the screenshot, along with the window it's in, if it's in one
+    Region(Option<usize>, Rectangle),
+    Monitor(Rectangle),
+    /// The whole screenshot, picking this is the same as not picking anything
+    Screen,
+}
+
+impl Candidate {
+    fn window(self) -> Option<usize> {
+        match self {
+            Self::Frame(idx) | Self::Content(idx) | Self::Child(idx, _) => Some(idx),
+            Self::Region(idx, _) => idx,
+            Self::Monitor(_) | Self::Screen => None,
+        }
+    }
+}
+
 impl OperationStack {
     pub fn new(
         windows: Vec<Window>,
+        monitors: Vec<Rectangle>,
+        cursor: Option<CursorLayer>,
         screen_dimensions: Rectangle,
         editing_started_with_cropping: bool,
         primary_colour: Colour,
         secondary_colour: Colour,
     ) -> Self {
+        let current_tool = if editing_started_with_cropping {
+            Tool::Crop
+        } else {
+            Tool::CropAndSave
+        };
+
         Self {
             operations: vec![],
-            undone_operations: vec![],
-            current_tool: if editing_started_with_cropping {
-                Tool::Crop
-            } else {
-                Tool::CropAndSave
-            },
+            undo_stack: vec![],
+            redo_stack: vec![],
+            current_tool,
             current_operation: None,
             autoincrement_bubble_number: 1,
+            cursor,
             primary_colour,
             secondary_colour,
+            selected: None,
+            selection_drag: None,
+            bendable_arrow: false,
             windows,
-            current_window: None,
+            monitors,
+            regions: vec![],
+            candidates: vec![],
+            current_candidate: 0,
+            cropped_candidate: None,
             is_in_crop_drag: false,
             ignore_windows: false,
+            pointer: None,
             selection_mode: SelectionMode::WindowsWithDecorations,
-            line_width: 4.0,
+            tool_properties: current_tool.properties(),
             editing_started_with_cropping,
             screen_dimensions,
         }
@@ -79,35 +195,230 @@ impl OperationStack {
 
     pub fn set_current_tool(&mut self, tool: Tool) {
         self.current_tool = tool;
+
+        if tool != Tool::Select {
+            self.selected = None;
+            self.selection_drag = None;
+        }
     }
 
     pub fn current_tool(&self) -> Tool {
         self.current_tool
     }
 
+    /// Finds the regions under the pointer, keeping the one the user cycled to highlighted for as
+    /// long as the pointer stays above the same regions
     pub fn set_current_window(&mut self, x: f64, y: f64) {
+        self.pointer = Some(Point { x, y });
+
         if self.ignore_windows {
-            self.current_window = None;
+            self.candidates.clear();
             return;
         }
 
-        for (idx, window) in self.windows.iter().enumerate().rev() {
-            if window.outer_rect.contains(Point { x, y }) {
-                self.current_window = Some(idx);
+        let candidates = self.candidates_at(Point { x, y });
+        if candidates != self.candidates {
+            // Clicking outside of windows and boxes crops to the whole screen, like it did before
+            // cycling through regions was possible
+            self.current_candidate = match candidates.first() {
+                Some(Candidate::Monitor(_) | Candidate::Screen) | None => candidates.len() - 1,
+                _ => 0,
+            };
+            self.candidates = candidates;
+        }
+    }
+
+    /// Sets the boxes found in the screenshot, which become candidates the next time the pointer
+    /// moves
+    pub fn set_regions(&mut self, regions: Vec<Rectangle>) {
+        self.regions = regions;
+    }
+
+    /// Returns the regions under `point`: the topmost window's frame and its contents, the boxes
+    /// found in the screenshot inside of it and its child windows, then the monitor and finally the
+    /// whole screen. Outside of windows, the boxes come first.
+    fn candidates_at(&self, point: Point) -> Vec<Candidate> {
+        let mut candidates = vec![];
+
+        let window = self
+            .windows
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, window)| window.outer_rect.contains(point));
+
+        // The window is what users crop to most of the time, so the boxes in it are only reached
+        // by cycling through the candidates
+        if let Some((idx, window)) = window {
+            candidates.push(Candidate::Frame(idx));
+            if window.content_rect != window.outer_rect {
+                candidates.push(Candidate::Content(idx));
+            }
+        }
+
+        // Boxes that are the window itself are already candidates, while parts of boxes can be
+        // covered by the window
+        candidates.extend(
+            self.regions
+                .iter()
+                .filter(|region| region.contains(point))
+                .filter(|&&region| {
+                    window.is_none_or(|(_, window)| {
+                        encloses(window.outer_rect, region)
+                            && !regions::nearly_equal(region, window.outer_rect)
+                            && !regions::nearly_equal(region, window.content_rect)
+                    })
+                })
+                .map(|&region| Candidate::Region(window.map(|(idx, _)| idx), region)),
+        );
+
+        if let Some((idx, window)) = window {
+            candidates.extend(
+                window
+                    .children
+                    .iter()
+                    .rev()
+                    .filter(|child| child.contains(point))
+                    .filter(|&&child| child != window.outer_rect && child != window.content_rect)
+                    .map(|&child| Candidate::Child(idx, child)),
+            );
+        }
+
+        if let Some(&monitor) = self
+            .monitors
+            .iter()
+            .find(|monitor| monitor.contains(point))
+            .filter(|&&monitor| monitor != self.screen_dimensions)
+        {
+            candidates.push(Candidate::Monitor(monitor));
+        }
+
+        candidates.push(Candidate::Screen);
+
+        candidates
+    }
+
+    /// Highlights the next region under the pointer, or the previous one if `forwards` is false.
+    ///
+    /// Returns whether there was anything to cycle through.
+    pub fn cycle_candidates(&mut self, forwards: bool) -> bool {
+        let len = self.candidates.len();
+        if len < 2 || !self.current_tool.is_cropping_tool() || self.is_in_crop_drag {
+            return false;
+        }
+
+        // Holding Shift turns frames into the contents that come right after them, which shouldn't
+        // be highlighted twice in a row
+        let previous = self.current_candidate();
+        for _ in 0..len {
+            self.current_candidate = if forwards {
+                (self.current_candidate + 1) % len
+            } else {
+                (self.current_candidate + len - 1) % len
+            };
+
+            if self.current_candidate() != previous {
                 break;
             }
         }
+
+        true
+    }
+
+    /// Returns the highlighted region under the pointer
+    fn current_candidate(&self) -> Option<Candidate> {
+        let candidate = *self.candidates.get(self.current_candidate)?;
+
+        // Holding Shift is a shortcut for picking the contents of the window
+        Some(match (candidate, self.selection_mode) {
+            (Candidate::Frame(idx), SelectionMode::WindowsWithoutDecorations) => {
+                Candidate::Content(idx)
+            }
+            _ => candidate,
+        })
+    }
+
+    fn candidate_rect(&self, candidate: Candidate) -> Rectangle {
+        match candidate {
+            Candidate::Frame(idx) => self.windows[idx].outer_rect,
+            Candidate::Content(idx) => self.windows[idx].content_rect,
+            Candidate::Child(_, rect) | Candidate::Region(_, rect) | Candidate::Monitor(rect) => {
+                rect
+            }
+            Candidate::Screen => self.screen_dimensions,
+        }
     }
 
     pub fn set_ignore_windows(&mut self, b: bool) {
         self.ignore_windows = b;
     }
 
+    pub fn has_cursor(&self) -> bool {
+        self.cursor.is_some()
+    }
+
+    pub fn set_cursor_visible(&mut self, visible: bool) {
+        if let Some(cursor) = self.cursor.as_mut() {
+            cursor.set_visible(visible);
+        }
+    }
+
+    /// Starts dragging the cursor around if it was clicked, returns whether it was.
+    ///
+    /// Cropping tools take precedence, as the cursor is usually on top of the window the user wants
+    /// to crop to.
+    pub fn start_cursor_drag(&mut self, point: Point) -> bool {
+        if self.current_tool.is_cropping_tool() {
+            return false;
+        }
+
+        self.cursor
+            .as_mut()
+            .is_some_and(|cursor| cursor.start_drag(point))
+    }
+
+    /// Returns false if the cursor isn't being dragged, in which case the drag belongs to the
+    /// current operation
+    pub fn update_cursor_drag(&mut self, offset_x: f64, offset_y: f64) -> bool {
+        self.cursor.as_mut().is_some_and(|cursor| {
+            cursor.update_drag(Point {
+                x: offset_x,
+                y: offset_y,
+            })
+        })
+    }
+
+    /// Returns whether the cursor was being dragged
+    pub fn finish_cursor_drag(&mut self) -> bool {
+        self.cursor
+            .as_mut()
+            .is_some_and(|cursor| cursor.finish_drag())
+    }
+
     pub fn start_operation_at(&mut self, point: Point) {
-        self.undone_operations.clear();
+        let bendable_arrow = std::mem::take(&mut self.bendable_arrow);
+
+        if self.current_tool == Tool::Select {
+            self.start_selection_drag(point);
+            return;
+        }
+
+        // Arrows can be bent right after they were placed, by dragging their middle around
+        if bendable_arrow
+            && self.current_tool == Tool::Arrow
+            && self.current_operation.is_none()
+            && let Some(idx) = self.operations.len().checked_sub(1)
+            && matches!(self.handle_under(idx, point), Some(DragAction::Bend))
+        {
+            self.selected = Some(idx);
+            self.start_selection_drag(point);
+            return;
+        }
+
+        self.redo_stack.clear();
 
         if let Some(old_operation) = self.current_operation.take() {
-            self.operations.push(old_operation);
+            self.push_operation(old_operation);
         }
 
         self.current_operation = Some(Operation::create_default_for_tool(
@@ -116,27 +427,197 @@ impl OperationStack {
             &mut self.autoincrement_bubble_number,
             self.primary_colour,
             self.secondary_colour,
-            self.line_width,
+            self.tool_properties,
         ));
     }
 
-    pub fn undo(&mut self) {
-        if self.operations.len() == 1 && matches!(self.operations[0], Operation::Crop(_)) {
-            return;
+    /// Adds `operation` on top of the others, undoing this removes it again.
+    ///
+    /// Crops can't be undone, as in "crop-first" mode every other operation depends on them.
+    fn push_operation(&mut self, operation: Operation) {
+        let is_crop = matches!(operation, Operation::Crop(_));
+
+        self.operations.push(operation);
+        if !is_crop {
+            self.record(Change::Remove(self.operations.len() - 1));
         }
+    }
 
-        if let Some(op) = self.operations.pop() {
-            self.undone_operations.push(op);
+    /// Remembers how to revert a change that was just made to `operations`
+    fn record(&mut self, revert: Change) {
+        self.undo_stack.push(revert);
+        self.redo_stack.clear();
+    }
+
+    pub fn undo(&mut self) {
+        if let Some(change) = self.undo_stack.pop() {
+            let redo = change.apply(&mut self.operations);
+            self.redo_stack.push(redo);
+            self.selected = None;
+            self.bendable_arrow = false;
         }
     }
 
     pub fn redo(&mut self) {
-        if let Some(op) = self.undone_operations.pop() {
-            self.operations.push(op);
+        if let Some(change) = self.redo_stack.pop() {
+            let undo = change.apply(&mut self.operations);
+            self.undo_stack.push(undo);
+            self.selected = None;
+            self.bendable_arrow = false;
+        }
+    }
+
+    /// Picks the handle of the selected operation under `point`, or otherwise the topmost operation
+    /// under it, to be dragged around
+    fn start_selection_drag(&mut self, point: Point) {
+        let action = self.selected.and_then(|idx| self.handle_under(idx, point));
+
+        if action.is_none() {
+            self.selected = self
+                .operations
+                .iter()
+                .rposition(|operation| operation.contains(point));
+        }
+
+        self.selection_drag = self.selected.and_then(|idx| {
+            let original = self.operations[idx].clone();
+
+            Some(SelectionDrag {
+                action: action.unwrap_or(DragAction::Move),
+                bounds: original.bounds()?,
+                original,
+            })
+        });
+    }
+
+    /// Returns what dragging the handle of the operation at `idx` that is under `point` does, if
+    /// there is a handle there
+    fn handle_under(&self, idx: usize, point: Point) -> Option<DragAction> {
+        let operation = &self.operations[idx];
+        if operation
+            .bend_handle()
+            .is_some_and(|handle| selection::is_on_bend_handle(handle, point))
+        {
+            return Some(DragAction::Bend);
+        }
+
+        if !operation.is_resizable() {
+            return None;
+        }
+
+        Handle::at(operation.bounds()?, point).map(DragAction::Resize)
+    }
+
+    /// Returns where the handle for bending an operation should be drawn, if there is one. That's
+    /// the selected operation for the select tool, and the arrow that was just placed for the arrow
+    /// tool.
+    fn bend_handle(&self) -> Option<Point> {
+        let operation = match self.current_tool {
+            Tool::Select => &self.operations[self.selected?],
+            Tool::Arrow if self.bendable_arrow && self.current_operation.is_none() => {
+                self.operations.last()?
+            }
+            _ => return None,
+        };
+
+        operation.bend_handle()
+    }
+
+    fn update_selection_drag(&mut self, offset: Point) {
+        let (Some(idx), Some(drag)) = (self.selected, &self.selection_drag) else {
+            return;
+        };
+
+        let mut operation = drag.original.clone();
+        match drag.action {
+            DragAction::Move => operation.transform(
+                drag.bounds,
+                Rectangle {
+                    x: drag.bounds.x + offset.x,
+                    y: drag.bounds.y + offset.y,
+                    ..drag.bounds
+                },
+            ),
+            DragAction::Resize(handle) => {
+                operation.transform(drag.bounds, handle.drag(drag.bounds, offset));
+            }
+            DragAction::Bend => {
+                if let Some(middle) = operation.bend_handle() {
+                    operation.bend_through(middle + offset);
+                }
+            }
         }
+        self.operations[idx] = operation;
+    }
+
+    /// Returns the operation picked with the select tool along with its text, if it's a text
+    /// operation
+    pub fn selected_text(&self) -> Option<(usize, Text)> {
+        let idx = self.selected?;
+        Some((idx, self.operations[idx].text()?))
+    }
+
+    /// Replaces the text of the text operation at `idx`, which came from [`Self::selected_text`]
+    pub fn edit_text(&mut self, idx: usize, text: Text) {
+        let Some(operation) = self.operations.get(idx) else {
+            return;
+        };
+
+        let mut edited = operation.clone();
+        edited.set_text(text);
+        let previous = std::mem::replace(&mut self.operations[idx], edited);
+        self.record(Change::Replace(idx, previous));
+    }
+
+    /// Removes the operation picked with the select tool, returns whether there was one
+    pub fn delete_selected(&mut self) -> bool {
+        let Some(idx) = self.selected.take() else {
+            return false;
+        };
+
+        self.selection_drag = None;
+        let operation = self.operations.remove(idx);
+        self.record(Change::Insert(idx, operation));
+
+        true
+    }
+
+    /// Moves the operation picked with the select tool above or below others, returns whether it
+    /// moved
+    pub fn reorder_selected(&mut self, order: ZOrder) -> bool {
+        let Some(from) = self.selected else {
+            return false;
+        };
+
+        // In "crop-first" mode the crop has to stay the first operation
+        let bottom = usize::from(matches!(self.operations.first(), Some(Operation::Crop(_))));
+        let top = self.operations.len() - 1;
+        let to = match order {
+            ZOrder::Raise => (from + 1).min(top),
+            ZOrder::Lower => from.saturating_sub(1).max(bottom),
+            ZOrder::Top => top,
+            ZOrder::Bottom => bottom,
+        };
+        if to == from || self.selection_drag.is_some() {
+            return false;
+        }
+
+        let revert = Change::Reorder { from, to }.apply(&mut self.operations);
+        self.record(revert);
+        self.selected = Some(to);
+
+        true
     }
 
     pub fn update_current_operation_end_coordinate(&mut self, new_width: f64, new_height: f64) {
+        if self.current_tool == Tool::Select || self.selection_drag.is_some() {
+            self.update_selection_drag(Point {
+                x: new_width,
+                y: new_height,
+            });
+            return;
+        }
+
         let Some(current_operation) = self.current_operation.as_mut() else {
             return;
         };
@@ -146,7 +627,7 @@ impl OperationStack {
             | Operation::Blur { rect, .. }
             | Operation::Pixelate { rect, .. }
             | Operation::DrawRectangle { rect, .. }
-            | Operation::Highlight { rect } => {
+            | Operation::Highlight { rect, .. } => {
                 rect.w = new_width;
                 rect.h = new_height;
             }
@@ -170,6 +651,64 @@ impl OperationStack {
             }),
             Operation::Bubble { .. } | Operation::Text { .. } => {}
         }
+
+        // The crop follows the pointer, which may not send motion events while dragging
+        if let Operation::Crop(rect) = current_operation {
+            self.pointer = Some(Point {
+                x: rect.x + rect.w,
+                y: rect.y + rect.h,
+            });
+        }
+    }
+
+    /// Moves the crop selection by `dx` and `dy` pixels, or moves its bottom right corner instead if
+    /// `resize` is set.
+    ///
+    /// Returns whether there was a selection to adjust, which is only the case while a cropping tool
+    /// is used. While the crop is being dragged, the pointer decides its size, so it can only be
+    /// moved.
+    pub fn nudge_crop(&mut self, dx: f64, dy: f64, resize: bool) -> bool {
+        if !self.current_tool.is_cropping_tool() {
+            return false;
+        }
+
+        if let Some(Operation::Crop(rect)) = &mut self.current_operation {
+            if resize {
+                return false;
+            }
+
+            rect.x += dx;
+            rect.y += dy;
+            return true;
+        }
+
+        if !self.editing_started_with_cropping {
+            return false;
+        }
+
+        let Some(Operation::Crop(rect)) = self.operations.first_mut() else {
+            return false;
+        };
+        if should_crop_selected_window_or_screen(*rect) {
+            return false;
+        }
+
+        let previous = *rect;
+        let mut nudged = rect.normalised();
+        if resize {
+            nudged.w = (nudged.w + dx).max(1.0);
+            nudged.h = (nudged.h + dy).max(1.0);
+        } else {
+            nudged.x += dx;
+            nudged.y += dy;
+        }
+        *rect = nudged;
+        self.record(Change::Replace(0, Operation::Crop(previous)));
+
+        // The selection isn't the region the user picked anymore
+        self.cropped_candidate = None;
+
+        true
     }
 
     pub fn set_text(&mut self, text: Text) {
@@ -191,23 +730,43 @@ impl OperationStack {
     }
 
     pub fn finish_current_operation(&mut self) {
+        if let Some(drag) = self.selection_drag.take() {
+            // Clicks that don't move anything only select
+            let bend = |operation: &Operation| operation.bend_handle().map(|Point { x, y }| (x, y));
+            if let Some(idx) = self.selected
+                && (self.operations[idx].bounds() != Some(drag.bounds)
+                    || bend(&self.operations[idx]) != bend(&drag.original))
+            {
+                self.record(Change::Replace(idx, drag.original));
+            }
+
+            // Arrows get bent with the arrow tool too, but only the select tool keeps a selection
+            if self.current_tool != Tool::Select {
+                self.selected = None;
+            }
+            return;
+        }
+
         if let Some(mut operation) = self.current_operation.take() {
             if self.current_tool == Tool::Crop {
                 self.ignore_windows = true;
+                self.cropped_candidate = None;
                 if let Operation::Crop(rect) = operation
                     && should_crop_selected_window_or_screen(rect)
-                    && let Some(current_window) = self.current_window
+                    && let Some(candidate) = self.current_candidate()
+                    && candidate != Candidate::Screen
                 {
-                    // FIXME: We should allow selecting the content rect somehow
-                    operation = Operation::Crop(self.windows[current_window].outer_rect);
+                    operation = Operation::Crop(self.candidate_rect(candidate));
+                    self.cropped_candidate = Some(candidate);
                 }
             }
 
-            self.operations.push(operation);
+            self.bendable_arrow = self.current_tool == Tool::Arrow;
+            self.push_operation(operation);
         }
     }
 
-    pub fn crop_region(&self, point: Option<Point>) -> Option<Rectangle> {
+    pub fn crop_region(&self) -> Option<Rectangle> {
         // We do this in order to support both "crop-first" and "crop-last" modes
         let crop_rect = match self.operations.last() {
             Some(Operation::Crop(rect)) => Some(rect),
@@ -220,17 +779,8 @@ impl OperationStack {
         if let Some(rect) = crop_rect {
             if should_crop_selected_window_or_screen(*rect) {
                 if !self.ignore_windows {
-                    let point =
-                        point.expect("If we're trying to select windows, the point should be set, as it only makes sense for it to be unset when saving in crop-first mode, where save doesn't involve clicking anywhere");
-
-                    self.windows
-                        .iter()
-                        .rev()
-                        .find(|window| window.outer_rect.contains(point))
-                        .map(|window| match self.selection_mode {
-                            SelectionMode::WindowsWithDecorations => window.outer_rect,
-                            SelectionMode::WindowsWithoutDecorations => window.content_rect,
-                        })
+                    self.current_candidate()
+                        .map(|candidate| self.candidate_rect(candidate))
                 } else {
                     None
                 }
@@ -242,7 +792,52 @@ impl OperationStack {
         }
     }
 
+    /// Returns the region [`Self::crop_region`] would crop to, if the user picked one rather than
+    /// drawing the crop region themselves
+    fn cropped_candidate(&self) -> Option<Candidate> {
+        if self.cropped_candidate.is_some() {
+            return self.cropped_candidate;
+        }
+
+        match self.operations.last() {
+            Some(Operation::Crop(rect))
+                if should_crop_selected_window_or_screen(*rect) && !self.ignore_windows =>
+            {
+                self.current_candidate()
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns the window that [`Self::crop_region`] would crop to (a part of), if the user picked
+    /// one rather than drawing the crop region themselves
+    pub fn cropped_window(&self) -> Option<&Window> {
+        let idx = self.cropped_candidate()?.window()?;
+        Some(&self.windows[idx])
+    }
+
+    /// Like [`Self::cropped_window`], but only if the user picked the whole window, along with
+    /// whether its decorations are included
+    pub fn cropped_whole_window(&self) -> Option<(&Window, SelectionMode)> {
+        match self.cropped_candidate()? {
+            Candidate::Frame(idx) => {
+                Some((&self.windows[idx], SelectionMode::WindowsWithDecorations))
+            }
+            Candidate::Content(idx) => {
+                Some((&self.windows[idx], SelectionMode::WindowsWithoutDecorations))
+            }
+            _ => None,
+        }
+    }
+
     pub fn execute(&self, cairo: &Context, is_in_draw_event: bool) {
+        // The cursor was part of the screen, so annotations go on top of it
+        if let Some(cursor) = &self.cursor
+            && let Err(why) = cursor.draw(cairo)
+        {
+            error!("Got error trying to draw the cursor: {why}");
+        }
+
         for operation in self.operations.iter() {
             if let Err(why) =
                 operation.execute(cairo, is_in_draw_event, !self.editing_started_with_cropping)
@@ -258,42 +853,55 @@ impl OperationStack {
             error!("Got error trying to execute {operation:?}: {why}");
         }
 
+        if is_in_draw_event
+            && self.current_tool == Tool::Select
+            && let Some(operation) = self.selected.map(|idx| &self.operations[idx])
+            && let Some(bounds) = operation.bounds()
+            && let Err(why) = selection::draw_selection(cairo, bounds, operation.is_resizable())
+        {
+            error!("Got error trying to draw the selection: {why}");
+        }
+
+        if is_in_draw_event
+            && let Some(handle) = self.bend_handle()
+            && let Err(why) = selection::draw_bend_handle(cairo, handle)
+        {
+            error!("Got error trying to draw the bend handle: {why}");
+        }
+
         // We only want to draw window "crop indicators" when:
         //  * we're not saving the screenshot
         //  * the user's tool is the CropAndSave tool
         //  * we are not in a crop drag
         //  * we are not in "ignore windows" mode (entered by holding Ctrl)
+        //  * the user hasn't picked the whole screen
+        let candidate = self
+            .current_candidate()
+            .filter(|&candidate| candidate != Candidate::Screen);
         let should_draw_windows = is_in_draw_event
             && self.current_tool().is_cropping_tool()
             && !self.is_in_crop_drag
-            && !self.ignore_windows;
+            && !self.ignore_windows
+            && candidate.is_some();
 
         // We only want to dim around the "manual selection"/whole screen if
         //  * we won't be drawing windows (they have their own dimming logic)
         //  * editing started with cropping (we don't want to dim in the crop last mode)
         let should_dim_manual_selection_or_whole_screen =
-            (!should_draw_windows || self.windows.is_empty()) && self.editing_started_with_cropping;
+            !should_draw_windows && self.editing_started_with_cropping;
 
         if should_dim_manual_selection_or_whole_screen {
             self.dimmen_manual_selection_or_whole_screen(cairo);
         }
 
-        if should_draw_windows && let Some(idx) = self.current_window {
-            let Rectangle { x, y, w, h } = match self.selection_mode {
-                SelectionMode::WindowsWithDecorations => self.windows[idx].outer_rect,
-                SelectionMode::WindowsWithoutDecorations => self.windows[idx].content_rect,
-            };
+        if should_draw_windows && let Some(candidate) = candidate {
+            let Rectangle { x, y, w, h } = self.candidate_rect(candidate);
             log_if_err!(cairo.save());
 
             cairo.rectangle(x, y, w, h);
             // When we are in draw events (aka this is being shown to the user), we want to make it clear
             // they are selecting the region which will be cropped
-            cairo.set_source_colour(Colour {
-                red: 0,
-                green: 127,
-                blue: 190,
-                alpha: 255,
-            });
+            cairo.set_source_colour(WINDOW_INDICATOR_COLOUR);
 
             if !self.editing_started_with_cropping {
                 cairo.set_dash(&[4.0, 21.0, 4.0], 0.0);
@@ -310,10 +918,49 @@ impl OperationStack {
                 log_if_err!(cairo.fill());
             }
 
+            if let Some(window) = candidate.window().map(|idx| &self.windows[idx])
+                && let Some(title) = window.title.as_deref().or(window.class.as_deref())
+            {
+                draw_window_title(cairo, Point { x, y }, title);
+            }
+
             log_if_err!(cairo.restore());
         }
     }
 
+    /// Draws a crosshair, a magnified view of the pixels around the pointer and where the selection
+    /// is and how big it is, so that the user can select regions down to the pixel
+    pub fn draw_selection_aids(&self, cairo: &Context, screenshot: &ImageSurface) {
+        if !self.current_tool.is_cropping_tool() {
+            return;
+        }
+
+        let Some(pointer) = self.pointer else {
+            return;
+        };
+
+        let selection = match self.current_operation {
+            Some(Operation::Crop(rect)) if !should_crop_selected_window_or_screen(rect) => {
+                Some(rect)
+            }
+            _ if self.ignore_windows => None,
+            _ => self
+                .current_candidate()
+                .filter(|&candidate| candidate != Candidate::Screen)
+                .map(|candidate| self.candidate_rect(candidate)),
+        };
+
+        if let Err(why) = hud::draw(
+            cairo,
+            screenshot,
+            self.screen_dimensions,
+            pointer,
+            selection,
+        ) {
+            error!("Got error trying to draw the selection aids: {why}");
+        }
+    }
+
     fn dimmen_manual_selection_or_whole_screen(&self, cairo: &Context) {
         if let Some(Operation::Crop(rect)) = self.current_operation {
             shapes::dimmen_rectangle_around(cairo, self.screen_dimensions, rect.normalised());
@@ -339,9 +986,608 @@ impl OperationStack {
     }
 }
 
+/// A change to [`OperationStack::operations`] that can be undone
+#[derive(Debug)]
+enum Change {
+    Insert(usize, Operation),
+    Remove(usize),
+    Replace(usize, Operation),
+    Reorder { from: usize, to: usize },
+}
+
+impl Change {
+    /// Makes the change, and returns the change that reverts it
+    fn apply(self, operations: &mut Vec<Operation>) -> Self {
+        match self {
+            Change::Insert(idx, operation) => {
+                operations.insert(idx, operation);
+                Change::Remove(idx)
+            }
+            Change::Remove(idx) => Change::Insert(idx, operations.remove(idx)),
+            Change::Replace(idx, operation) => {
+                Change::Replace(idx, std::mem::replace(&mut operations[idx], operation))
+            }
+            Change::Reorder { from, to } => {
+                let operation = operations.remove(from);
+                operations.insert(to, operation);
+                Change::Reorder { from: to, to: from }
+            }
+        }
+    }
+}
+
+/// Draws `title` in a box at `top_left`, so the user knows which window they're about to crop to
+fn draw_window_title(cairo: &Context, top_left: Point, title: &str) {
+    const PADDING: f64 = 4.0;
+
+    let layout = create_layout(cairo);
+    layout.set_text(title);
+    layout.set_font_description(Some(&FontDescription::from_string("Sans 10")));
+    layout.set_ellipsize(EllipsizeMode::End);
+    layout.set_width(400 * pango::SCALE);
+
+    let (_, extents) = layout.pixel_extents();
+    let w = extents.width() as f64 + 2.0 * PADDING;
+    let h = extents.height() as f64 + 2.0 * PADDING;
+    // Windows can start above the screen, so we keep the title on the screen
+    let x = top_left.x.max(0.0);
+    let y = top_left.y.max(0.0);
+
+    cairo.set_dash(&[], 0.0);
+    cairo.rectangle(x, y, w, h);
+    cairo.set_source_colour(WINDOW_INDICATOR_COLOUR);
+    log_if_err!(cairo.fill());
+
+    cairo.move_to(x + PADDING, y + PADDING);
+    cairo.set_source_colour(Colour {
+        red: 255,
+        green: 255,
+        blue: 255,
+        alpha: 255,
+    });
+    show_layout(cairo, &layout);
+}
+
+/// Whether `inner` lies entirely inside of `outer`
+fn encloses(outer: Rectangle, inner: Rectangle) -> bool {
+    inner.x >= outer.x
+        && inner.y >= outer.y
+        && inner.x + inner.w <= outer.x + outer.w
+        && inner.y + inner.h <= outer.y + outer.h
+}
+
 /// If the width or height of the rectangle are 0, or the area of the rectangle covers
 /// less than a pixel, we consider the entire screen or window under the cursor to be
 /// the crop region
 fn should_crop_selected_window_or_screen(rect: Rectangle) -> bool {
     rect.area() < 1.0
 }
+
+#[cfg(test)]
+mod tests {
+    use kcshot_data::{
+        colour::Colour,
+        geometry::{Point, Rectangle},
+    };
+    use kcshot_screenshot::Window;
+
+    use super::{Candidate, Change, OperationStack, SelectionMode, ZOrder};
+    use crate::editor::operations::{Operation, Tool};
+
+    fn rect(x: f64, y: f64, w: f64, h: f64) -> Rectangle {
+        Rectangle { x, y, w, h }
+    }
+
+    fn window(outer_rect: Rectangle, content_rect: Rectangle, children: Vec<Rectangle>) -> Window {
+        Window {
+            outer_rect,
+            content_rect,
+            id: None,
+            title: None,
+            class: None,
+            pid: None,
+            children,
+        }
+    }
+
+    /// Creates a stack for cropping a 100x100 screenshot, without going through the settings like
+    /// [`OperationStack::new`] does
+    fn stack(windows: Vec<Window>, monitors: Vec<Rectangle>) -> OperationStack {
+        let current_tool = Tool::Crop;
+        let black = Colour {
+            red: 0,
+            green: 0,
+            blue: 0,
+            alpha: 255,
+        };
+
+        OperationStack {
+            operations: vec![],
+            undo_stack: vec![],
+            redo_stack: vec![],
+            current_tool,
+            current_operation: None,
+            autoincrement_bubble_number: 1,
+            cursor: None,
+            primary_colour: black,
+            secondary_colour: black,
+            selected: None,
+            selection_drag: None,
+            bendable_arrow: false,
+            windows,
+            monitors,
+            regions: vec![],
+            candidates: vec![],
+            current_candidate: 0,
+            cropped_candidate: None,
+            is_in_crop_drag: false,
+            ignore_windows: false,
+            pointer: None,
+            selection_mode: SelectionMode::WindowsWithDecorations,
+            tool_properties: current_tool.default_properties(),
+            editing_started_with_cropping: true,
+            screen_dimensions: rect(0.0, 0.0, 100.0, 100.0),
+        }
+    }
+
+    /// A window with a title bar and a child window, with a box found in it, on the left monitor
+    fn stack_with_window() -> OperationStack {
+        let mut stack = stack(
+            vec![window(
+                rect(0.0, 0.0, 40.0, 40.0),
+                rect(0.0, 10.0, 40.0, 30.0),
+                vec![rect(10.0, 20.0, 10.0, 10.0)],
+            )],
+            vec![rect(0.0, 0.0, 50.0, 100.0), rect(50.0, 0.0, 50.0, 100.0)],
+        );
+        stack.set_regions(vec![rect(5.0, 15.0, 20.0, 20.0)]);
+        stack
+    }
+
+    /// Creates an operation that can be told apart from others by `id`
+    fn operation(id: f64) -> Operation {
+        Operation::Crop(Rectangle {
+            x: id,
+            y: 0.0,
+            w: 1.0,
+            h: 1.0,
+        })
+    }
+
+    fn ids(operations: &[Operation]) -> Vec<f64> {
+        operations
+            .iter()
+            .map(|operation| match operation {
+                Operation::Crop(rect) | Operation::DrawRectangle { rect, .. } => rect.x,
+                _ => unreachable!("Only crops and rectangles are used in these tests"),
+            })
+            .collect()
+    }
+
+    /// Draws a 20x20 rectangle at `x`, which tells it apart from others in [`ids`]
+    fn draw_rectangle(stack: &mut OperationStack, x: f64) {
+        stack.set_current_tool(Tool::Rectangle);
+        stack.start_operation_at(Point { x, y: 0.0 });
+        stack.update_current_operation_end_coordinate(20.0, 20.0);
+        stack.finish_current_operation();
+    }
+
+    /// Draws an arrow from (0, 0) to (40, 0), its middle is at (20, 0)
+    fn draw_arrow(stack: &mut OperationStack) {
+        stack.set_current_tool(Tool::Arrow);
+        stack.start_operation_at(Point { x: 0.0, y: 0.0 });
+        stack.update_current_operation_end_coordinate(40.0, 0.0);
+        stack.finish_current_operation();
+    }
+
+    /// Drags from `start` by `offset` with the current tool
+    fn drag(stack: &mut OperationStack, start: Point, offset: Point) {
+        stack.start_operation_at(start);
+        stack.update_current_operation_end_coordinate(offset.x, offset.y);
+        stack.finish_current_operation();
+    }
+
+    fn is_near(point: Option<Point>, expected: Point) -> bool {
+        point.is_some_and(|point| (point - expected).dist() < 1e-9)
+    }
+
+    fn is_bent_through(operation: &Operation, middle: Point) -> bool {
+        matches!(
+            operation,
+            Operation::DrawArrow {
+                control: Some(_),
+                ..
+            }
+        ) && is_near(operation.bend_handle(), middle)
+    }
+
+    /// Clicks on `point` with the select tool
+    fn select(stack: &mut OperationStack, point: Point) {
+        stack.set_current_tool(Tool::Select);
+        stack.start_operation_at(point);
+        stack.finish_current_operation();
+    }
+
+    /// A stack with rectangles at 0, 30 and 60 drawn on it
+    fn stack_with_rectangles() -> OperationStack {
+        let mut stack = stack(vec![], vec![]);
+        for x in [0.0, 30.0, 60.0] {
+            draw_rectangle(&mut stack, x);
+        }
+        stack
+    }
+
+    /// Applies `change` like the operation stack does, then undoes and redoes it, checking the
+    /// order of the operations after each step
+    fn round_trip(change: Change, after: &[f64]) {
+        let mut operations = (0..4)
+            .map(|id| operation(f64::from(id)))
+            .collect::<Vec<_>>();
+        let before = ids(&operations);
+
+        let undo = change.apply(&mut operations);
+        assert_eq!(ids(&operations), after);
+
+        let redo = undo.apply(&mut operations);
+        assert_eq!(ids(&operations), before);
+
+        let undo = redo.apply(&mut operations);
+        assert_eq!(ids(&operations), after);
+
+        undo.apply(&mut operations);
+        assert_eq!(ids(&operations), before);
+    }
+
+    #[test]
+    fn delete_round_trip() {
+        round_trip(Change::Remove(1), &[0.0, 2.0, 3.0]);
+    }
+
+    #[test]
+    fn insert_round_trip() {
+        round_trip(
+            Change::Insert(2, operation(9.0)),
+            &[0.0, 1.0, 9.0, 2.0, 3.0],
+        );
+    }
+
+    #[test]
+    fn replace_round_trip() {
+        round_trip(Change::Replace(3, operation(9.0)), &[0.0, 1.0, 2.0, 9.0]);
+    }
+
+    #[test]
+    fn reorder_round_trip() {
+        round_trip(Change::Reorder { from: 0, to: 2 }, &[1.0, 2.0, 0.0, 3.0]);
+        round_trip(Change::Reorder { from: 3, to: 1 }, &[0.0, 3.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn candidates_start_with_the_window() {
+        let stack = stack_with_window();
+
+        assert_eq!(
+            stack.candidates_at(Point { x: 12.0, y: 22.0 }),
+            [
+                Candidate::Frame(0),
+                Candidate::Content(0),
+                Candidate::Region(Some(0), rect(5.0, 15.0, 20.0, 20.0)),
+                Candidate::Child(0, rect(10.0, 20.0, 10.0, 10.0)),
+                Candidate::Monitor(rect(0.0, 0.0, 50.0, 100.0)),
+                Candidate::Screen,
+            ]
+        );
+        // The title bar is only part of the frame
+        assert_eq!(
+            stack.candidates_at(Point { x: 30.0, y: 5.0 }),
+            [
+                Candidate::Frame(0),
+                Candidate::Content(0),
+                Candidate::Monitor(rect(0.0, 0.0, 50.0, 100.0)),
+                Candidate::Screen,
+            ]
+        );
+    }
+
+    #[test]
+    fn candidates_outside_of_windows() {
+        let mut stack = stack_with_window();
+        stack.set_regions(vec![rect(60.0, 60.0, 20.0, 20.0)]);
+
+        assert_eq!(
+            stack.candidates_at(Point { x: 70.0, y: 70.0 }),
+            [
+                Candidate::Region(None, rect(60.0, 60.0, 20.0, 20.0)),
+                Candidate::Monitor(rect(50.0, 0.0, 50.0, 100.0)),
+                Candidate::Screen,
+            ]
+        );
+    }
+
+    #[test]
+    fn candidates_skip_repeated_rects() {
+        // Without decorations, and with a child window that fills the window
+        let whole = rect(0.0, 0.0, 40.0, 40.0);
+        let mut stack = stack(
+            vec![
+                window(rect(20.0, 20.0, 40.0, 40.0), whole, vec![]),
+                window(whole, whole, vec![whole]),
+            ],
+            vec![rect(0.0, 0.0, 100.0, 100.0)],
+        );
+        stack.set_regions(vec![whole]);
+
+        // Only the topmost window counts, and a monitor that is the whole screen isn't one
+        assert_eq!(
+            stack.candidates_at(Point { x: 30.0, y: 30.0 }),
+            [Candidate::Frame(1), Candidate::Screen]
+        );
+    }
+
+    #[test]
+    fn cycling_through_candidates() {
+        let mut stack = stack_with_window();
+        stack.set_current_window(12.0, 22.0);
+        assert_eq!(stack.current_candidate(), Some(Candidate::Frame(0)));
+
+        assert!(stack.cycle_candidates(false));
+        assert_eq!(stack.current_candidate(), Some(Candidate::Screen));
+        assert!(stack.cycle_candidates(true));
+        assert!(stack.cycle_candidates(true));
+        assert_eq!(stack.current_candidate(), Some(Candidate::Content(0)));
+
+        // The highlighted candidate stays while the pointer is above the same ones
+        stack.set_current_window(13.0, 23.0);
+        assert_eq!(stack.current_candidate(), Some(Candidate::Content(0)));
+        stack.set_current_window(30.0, 5.0);
+        assert_eq!(stack.current_candidate(), Some(Candidate::Frame(0)));
+
+        // Outside of windows and boxes the whole screen is picked
+        stack.set_current_window(70.0, 70.0);
+        assert_eq!(stack.current_candidate(), Some(Candidate::Screen));
+    }
+
+    #[test]
+    fn cycling_while_holding_shift_skips_the_frame() {
+        let mut stack = stack_with_window();
+        stack.set_current_window(12.0, 22.0);
+        stack.selection_mode = SelectionMode::WindowsWithoutDecorations;
+        assert_eq!(stack.current_candidate(), Some(Candidate::Content(0)));
+
+        assert!(stack.cycle_candidates(true));
+        assert_eq!(
+            stack.current_candidate(),
+            Some(Candidate::Region(Some(0), rect(5.0, 15.0, 20.0, 20.0)))
+        );
+        assert!(stack.cycle_candidates(false));
+        assert_eq!(stack.current_candidate(), Some(Candidate::Content(0)));
+        assert!(stack.cycle_candidates(false));
+        assert_eq!(stack.current_candidate(), Some(Candidate::Screen));
+        assert!(stack.cycle_candidates(true));
+        assert_eq!(stack.current_candidate(), Some(Candidate::Content(0)));
+    }
+
+    #[test]
+    fn cycling_needs_a_cropping_tool() {
+        let mut stack = stack_with_window();
+        stack.set_current_window(12.0, 22.0);
+
+        stack.set_current_tool(Tool::Arrow);
+        assert!(!stack.cycle_candidates(true));
+
+        stack.set_current_tool(Tool::Crop);
+        stack.set_is_in_crop_drag(true);
+        assert!(!stack.cycle_candidates(true));
+        assert_eq!(stack.current_candidate(), Some(Candidate::Frame(0)));
+    }
+
+    #[test]
+    fn undoing_and_redoing_drawing() {
+        let mut stack = stack_with_rectangles();
+        assert_eq!(ids(&stack.operations), [0.0, 30.0, 60.0]);
+
+        stack.undo();
+        stack.undo();
+        assert_eq!(ids(&stack.operations), [0.0]);
+        stack.redo();
+        assert_eq!(ids(&stack.operations), [0.0, 30.0]);
+
+        // Drawing something new forgets what was undone
+        draw_rectangle(&mut stack, 90.0);
+        stack.redo();
+        assert_eq!(ids(&stack.operations), [0.0, 30.0, 90.0]);
+    }
+
+    #[test]
+    fn selecting_and_dragging() {
+        let mut stack = stack_with_rectangles();
+
+        select(&mut stack, Point { x: 40.0, y: 10.0 });
+        assert_eq!(stack.selected, Some(1));
+        // Clicking on nothing drops the selection
+        select(&mut stack, Point { x: 50.0, y: 50.0 });
+        assert_eq!(stack.selected, None);
+
+        stack.start_operation_at(Point { x: 40.0, y: 10.0 });
+        stack.update_current_operation_end_coordinate(5.0, 30.0);
+        stack.finish_current_operation();
+        assert_eq!(stack.selected, Some(1));
+        assert_eq!(
+            stack.operations[1].bounds(),
+            Some(Rectangle {
+                x: 35.0,
+                y: 30.0,
+                w: 20.0,
+                h: 20.0,
+            })
+        );
+
+        // Clicks that don't move anything can't be undone
+        select(&mut stack, Point { x: 45.0, y: 40.0 });
+        assert_eq!(stack.undo_stack.len(), 4);
+
+        stack.undo();
+        assert_eq!(ids(&stack.operations), [0.0, 30.0, 60.0]);
+        assert_eq!(stack.selected, None);
+        stack.redo();
+        assert_eq!(ids(&stack.operations), [0.0, 35.0, 60.0]);
+    }
+
+    #[test]
+    fn deleting() {
+        let mut stack = stack_with_rectangles();
+        assert!(!stack.delete_selected());
+
+        select(&mut stack, Point { x: 40.0, y: 10.0 });
+        assert!(stack.delete_selected());
+        assert_eq!(ids(&stack.operations), [0.0, 60.0]);
+        assert!(!stack.delete_selected());
+
+        stack.undo();
+        assert_eq!(ids(&stack.operations), [0.0, 30.0, 60.0]);
+        stack.redo();
+        assert_eq!(ids(&stack.operations), [0.0, 60.0]);
+    }
+
+    #[test]
+    fn reordering() {
+        let mut stack = stack_with_rectangles();
+        assert!(!stack.reorder_selected(ZOrder::Top));
+
+        select(&mut stack, Point { x: 10.0, y: 10.0 });
+        assert!(!stack.reorder_selected(ZOrder::Lower));
+        assert!(stack.reorder_selected(ZOrder::Top));
+        assert_eq!(ids(&stack.operations), [30.0, 60.0, 0.0]);
+        assert!(!stack.reorder_selected(ZOrder::Raise));
+        assert!(stack.reorder_selected(ZOrder::Lower));
+        assert_eq!(ids(&stack.operations), [30.0, 0.0, 60.0]);
+        assert_eq!(stack.selected, Some(1));
+
+        stack.undo();
+        assert_eq!(ids(&stack.operations), [30.0, 60.0, 0.0]);
+        stack.undo();
+        assert_eq!(ids(&stack.operations), [0.0, 30.0, 60.0]);
+        stack.redo();
+        stack.redo();
+        assert_eq!(ids(&stack.operations), [30.0, 0.0, 60.0]);
+    }
+
+    #[test]
+    fn the_crop_stays_at_the_bottom() {
+        let mut stack = stack(vec![], vec![]);
+        stack.start_operation_at(Point { x: 0.0, y: 0.0 });
+        stack.update_current_operation_end_coordinate(100.0, 100.0);
+        stack.finish_current_operation();
+        draw_rectangle(&mut stack, 30.0);
+        draw_rectangle(&mut stack, 60.0);
+
+        select(&mut stack, Point { x: 70.0, y: 10.0 });
+        assert!(stack.reorder_selected(ZOrder::Bottom));
+        assert_eq!(ids(&stack.operations), [0.0, 60.0, 30.0]);
+        assert!(!stack.reorder_selected(ZOrder::Lower));
+
+        // Crops can't be undone
+        stack.undo();
+        stack.undo();
+        stack.undo();
+        stack.undo();
+        assert_eq!(ids(&stack.operations), [0.0]);
+    }
+
+    #[test]
+    fn bending_the_arrow_that_was_just_placed() {
+        let mut stack = stack(vec![], vec![]);
+        draw_arrow(&mut stack);
+        assert!(is_near(stack.bend_handle(), Point { x: 20.0, y: 0.0 }));
+
+        drag(
+            &mut stack,
+            Point { x: 20.0, y: 0.0 },
+            Point { x: 0.0, y: 10.0 },
+        );
+        assert_eq!(stack.operations.len(), 1);
+        assert!(is_bent_through(
+            &stack.operations[0],
+            Point { x: 20.0, y: 10.0 }
+        ));
+        assert_eq!(stack.selected, None);
+        assert!(matches!(
+            stack.undo_stack.last(),
+            Some(Change::Replace(
+                0,
+                Operation::DrawArrow { control: None, .. }
+            ))
+        ));
+
+        stack.undo();
+        assert!(matches!(
+            stack.operations[0],
+            Operation::DrawArrow { control: None, .. }
+        ));
+        stack.redo();
+        assert!(is_bent_through(
+            &stack.operations[0],
+            Point { x: 20.0, y: 10.0 }
+        ));
+    }
+
+    #[test]
+    fn arrows_can_start_in_the_middle_of_the_last_one() {
+        let mut stack = stack(vec![], vec![]);
+        draw_arrow(&mut stack);
+
+        // Only the first click after placing an arrow can bend it
+        drag(
+            &mut stack,
+            Point { x: 20.0, y: 0.0 },
+            Point { x: 0.0, y: 10.0 },
+        );
+        assert!(stack.bend_handle().is_none());
+        drag(
+            &mut stack,
+            Point { x: 20.0, y: 10.0 },
+            Point { x: 0.0, y: 10.0 },
+        );
+        assert_eq!(stack.operations.len(), 2);
+
+        // Undoing gives up on bending the arrow placed last
+        stack.undo();
+        assert!(stack.bend_handle().is_none());
+        drag(
+            &mut stack,
+            Point { x: 20.0, y: 10.0 },
+            Point { x: 10.0, y: 0.0 },
+        );
+        assert_eq!(stack.operations.len(), 2);
+        assert!(matches!(
+            stack.operations[1],
+            Operation::DrawArrow { control: None, .. }
+        ));
+    }
+
+    #[test]
+    fn bending_with_the_select_tool() {
+        let mut stack = stack(vec![], vec![]);
+        draw_arrow(&mut stack);
+
+        select(&mut stack, Point { x: 10.0, y: 0.0 });
+        assert!(is_near(stack.bend_handle(), Point { x: 20.0, y: 0.0 }));
+        drag(
+            &mut stack,
+            Point { x: 20.0, y: 0.0 },
+            Point { x: 0.0, y: -10.0 },
+        );
+        assert!(is_bent_through(
+            &stack.operations[0],
+            Point { x: 20.0, y: -10.0 }
+        ));
+        // The select tool keeps the arrow selected, so it can be bent again
+        assert_eq!(stack.selected, Some(0));
+
+        stack.undo();
+        assert!(matches!(
+            stack.operations[0],
+            Operation::DrawArrow { control: None, .. }
+        ));
+    }
+}
@@ -1,4 +1,5 @@
-use gtk4::glib;
+use gtk4::{glib, prelude::*};
+use kcshot_data::settings::{ArrowHead, Settings, StrokeStyle, ToolProperties};
 
 /// This enum is like [Operations] but without any associated data
 #[derive(Clone, Copy, Debug, PartialEq, Eq, glib::Enum)]
@@ -21,6 +22,8 @@ pub enum Tool {
     // Unlike CropAndSave, this one is not visible
     Crop = 11,
     Save = 12,
+
+    Select = 13,
 }
 
 impl Tool {
@@ -41,6 +44,7 @@ impl Tool {
                 "Nothing should try to get the associated path of the simple Crop tool, as it intentionally does not have a button"
             ),
             Tool::Save => "/kc/kcshot/editor/tool-checkmark.png",
+            Tool::Select => "/kc/kcshot/editor/tool-select.png",
         }
     }
 
@@ -58,6 +62,7 @@ impl Tool {
             'i' | 'I' => AutoincrementBubble,
             't' | 'T' => Text,
             'p' | 'P' => Pencil,
+            's' | 'S' => Select,
             _ => None?,
         })
     }
@@ -79,6 +84,7 @@ impl Tool {
                 "Nothing should try to get the tooltip of the simple Crop tool, as it does not have a button"
             ),
             Tool::Save => "Save current screenshot",
+            Tool::Select => "<u>S</u>elect tool",
         }
     }
 
@@ -89,4 +95,88 @@ impl Tool {
     pub const fn is_cropping_tool(self) -> bool {
         matches!(self, Self::CropAndSave | Self::Crop)
     }
+
+    pub const fn has_line_width(self) -> bool {
+        matches!(
+            self,
+            Self::Line | Self::Arrow | Self::Rectangle | Self::Ellipse | Self::Pencil
+        )
+    }
+
+    pub const fn has_arrow_heads(self) -> bool {
+        matches!(self, Self::Arrow)
+    }
+
+    pub const fn has_fill(self) -> bool {
+        matches!(self, Self::Rectangle | Self::Ellipse)
+    }
+
+    /// Text is left out as its colour is picked in the text dialog
+    pub const fn has_opacity(self) -> bool {
+        matches!(
+            self,
+            Self::Line
+                | Self::Arrow
+                | Self::Rectangle
+                | Self::Ellipse
+                | Self::Highlight
+                | Self::AutoincrementBubble
+                | Self::Pencil
+        )
+    }
+
+    /// What [`ToolProperties::strength`] is for this tool, if it makes use of it
+    pub const fn strength_label(self) -> Option<&'static str> {
+        match self {
+            Self::Blur => Some("Blur radius"),
+            Self::Pixelate => Some("Block size"),
+            _ => None,
+        }
+    }
+
+    /// The properties this tool was last used with, or its defaults if it hasn't been used yet
+    pub fn properties(self) -> ToolProperties {
+        Settings::open()
+            .tool_properties()
+            .get(&self.settings_name())
+            .copied()
+            .unwrap_or_else(|| self.default_properties())
+    }
+
+    pub fn save_properties(self, properties: ToolProperties) {
+        let settings = Settings::open();
+        let mut tool_properties = settings.tool_properties();
+        tool_properties.insert(self.settings_name(), properties);
+
+        if let Err(why) = settings.try_set_tool_properties(tool_properties) {
+            tracing::warn!("Failed to save the properties of {self:?}: {why}");
+        }
+    }
+
+    pub(super) fn default_properties(self) -> ToolProperties {
+        ToolProperties {
+            line_width: 4.0,
+            fill: true,
+            opacity: 1.0,
+            strength: match self {
+                Self::Blur => 5.0,
+                Self::Pixelate => 4.0,
+                _ => 0.0,
+            },
+            stroke_style: StrokeStyle::Solid,
+            arrow_head: ArrowHead::Open,
+            double_headed: false,
+        }
+    }
+
+    /// Tools are stored in settings under their nick, e.g. `autoincrement-bubble`
+    fn settings_name(self) -> String {
+        glib::EnumClass::with_type(Self::static_type())
+            .and_then(|class| {
+                class
+                    .value(self as i32)
+                    .map(|value| value.nick().to_owned())
+            })
+            .unwrap_or_else(|| format!("{self:?}"))
+    }
 }
@@ -0,0 +1,413 @@
+//! Finds boxes in screenshots, such as dialogs inside of a browser, panels and cards, so they can be
+//! picked like windows can.
+//!
+//! Boxes are found by looking for long horizontal edges, i.e. places where the brightness changes
+//! sharply from one row to the next, and pairing up edges which are above each other and whose ends
+//! are joined by vertical edges.
+
+use std::{
+    cmp::Reverse,
+    collections::HashMap,
+    ops::{Range, RangeInclusive},
+    thread::Builder as ThreadBuilder,
+};
+
+use kcshot_data::geometry::Rectangle;
+use tokio::sync::oneshot;
+
+/// How much the brightness has to change between neighbouring pixels for there to be an edge
+const EDGE_THRESHOLD: u8 = 24;
+/// Boxes smaller than this on either side aren't worth snapping to, this also keeps us from finding
+/// boxes inside of text
+const MIN_SIDE: usize = 48;
+/// Rounded corners make the edges of a box shorter than the box, by up to this much on each end
+const MAX_CORNER_RADIUS: usize = 16;
+/// How many pixels the ends of the top and bottom edges of a box may be apart
+const ALIGNMENT_TOLERANCE: usize = 2;
+/// How much of a vertical side of a box has to be an edge
+const MIN_SIDE_COVERAGE: f64 = 0.9;
+/// Screenshots of grids, e.g. of spreadsheets, would otherwise give the user too many boxes to cycle
+/// through
+const MAX_REGIONS: usize = 512;
+
+/// Detects boxes in a `CAIRO_FORMAT_RGB24` image on another thread, the returned receiver gets them
+/// once that's done.
+pub fn detect_in_background(
+    pixels: Vec<u8>,
+    width: usize,
+    height: usize,
+    stride: usize,
+) -> std::io::Result<oneshot::Receiver<Vec<Rectangle>>> {
+    let (tx, rx) = oneshot::channel();
+
+    ThreadBuilder::new()
+        .name("region detection thread".into())
+        .spawn(move || {
+            // The editor may have been closed already, in which case nobody cares about the boxes
+            let _ = tx.send(detect(&pixels, width, height, stride));
+        })?;
+
+    Ok(rx)
+}
+
+/// Returns the boxes in a `CAIRO_FORMAT_RGB24` image, the smallest ones first
+pub fn detect(pixels: &[u8], width: usize, height: usize, stride: usize) -> Vec<Rectangle> {
+    let luma = Luma::from_rgb24(pixels, width, height, stride);
+    let segments = luma.horizontal_segments();
+
+    // Bottom edges are looked up by where they start
+    let mut segments_by_start = HashMap::<usize, Vec<&Segment>>::new();
+    for segment in &segments {
+        segments_by_start
+            .entry(segment.x0)
+            .or_default()
+            .push(segment);
+    }
+
+    let mut columns = ColumnEdges::new(&luma);
+    let mut boxes = vec![];
+
+    for top in &segments {
+        let x0_range = top.x0.saturating_sub(ALIGNMENT_TOLERANCE)..=top.x0 + ALIGNMENT_TOLERANCE;
+        let mut bottoms = x0_range
+            .filter_map(|x0| segments_by_start.get(&x0))
+            .flatten()
+            .filter(|bottom| {
+                bottom.y >= top.y + MIN_SIDE && bottom.x1.abs_diff(top.x1) <= ALIGNMENT_TOLERANCE
+            })
+            .collect::<Vec<_>>();
+        bottoms.sort_by_key(|bottom| bottom.y);
+
+        // Pairing an edge with every edge below it would find every combination of rows in a grid,
+        // so we only keep the smallest and the biggest box. The latter is the whole box when the
+        // former is e.g. the title of a card that's separated from its contents by a line.
+        let mut found = bottoms
+            .into_iter()
+            .filter_map(|bottom| columns.find_box(top, bottom));
+        let smallest = found.next();
+        let biggest = found.last();
+
+        boxes.extend(smallest.into_iter().chain(biggest));
+    }
+
+    boxes.retain(|rect| rect.w < width as f64 || rect.h < height as f64);
+    // Both sides of a border are edges, of the boxes they make we want to keep the outer one
+    boxes.sort_by(|a, b| b.area().total_cmp(&a.area()));
+
+    let mut regions: Vec<Rectangle> = Vec::with_capacity(boxes.len().min(MAX_REGIONS));
+    for rect in boxes {
+        if regions.len() == MAX_REGIONS {
+            break;
+        }
+
+        if !regions.iter().any(|region| nearly_equal(*region, rect)) {
+            regions.push(rect);
+        }
+    }
+
+    regions.reverse();
+    regions
+}
+
+/// Whether the sides of `a` and `b` are at most [`ALIGNMENT_TOLERANCE`] pixels apart
+pub fn nearly_equal(a: Rectangle, b: Rectangle) -> bool {
+    let tolerance = ALIGNMENT_TOLERANCE as f64;
+
+    (a.x - b.x).abs() <= tolerance
+        && (a.y - b.y).abs() <= tolerance
+        && (a.x + a.w - b.x - b.w).abs() <= tolerance
+        && (a.y + a.h - b.y - b.h).abs() <= tolerance
+}
+
+/// A horizontal edge between rows `y - 1` and `y`, which goes from column `x0` up to, but not
+/// including, `x1`
+#[derive(Debug)]
+struct Segment {
+    y: usize,
+    x0: usize,
+    x1: usize,
+}
+
+/// The brightness of every pixel of an image
+struct Luma {
+    width: usize,
+    height: usize,
+    data: Vec<u8>,
+}
+
+impl Luma {
+    fn from_rgb24(pixels: &[u8], width: usize, height: usize, stride: usize) -> Self {
+        let mut data = Vec::with_capacity(width * height);
+
+        for row in pixels.chunks(stride).take(height) {
+            // Pixels are stored native-endian, with the upper 8 bits unused
+            data.extend(row[..width * 4].chunks_exact(4).map(|pixel| {
+                let [_, red, green, blue] =
+                    u32::from_ne_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]).to_be_bytes();
+                ((red as u32 * 77 + green as u32 * 150 + blue as u32 * 29) >> 8) as u8
+            }));
+        }
+
+        Self {
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn at(&self, x: usize, y: usize) -> u8 {
+        self.data[y * self.width + x]
+    }
+
+    /// Whether there's an edge between `(x, y - 1)` and `(x, y)`
+    fn horizontal_edge(&self, x: usize, y: usize) -> bool {
+        y > 0 && y < self.height && self.at(x, y).abs_diff(self.at(x, y - 1)) >= EDGE_THRESHOLD
+    }
+
+    /// Whether there's an edge between `(x - 1, y)` and `(x, y)`
+    fn vertical_edge(&self, x: usize, y: usize) -> bool {
+        x > 0 && x < self.width && self.at(x, y).abs_diff(self.at(x - 1, y)) >= EDGE_THRESHOLD
+    }
+
+    /// Returns the horizontal edges which are long enough to be a side of a box, from top to bottom
+    fn horizontal_segments(&self) -> Vec<Segment> {
+        let mut segments = vec![];
+
+        for y in 1..self.height {
+            let mut start = None;
+
+            for x in 0..=self.width {
+                let is_edge = x < self.width && self.horizontal_edge(x, y);
+
+                match (start, is_edge) {
+                    (None, true) => start = Some(x),
+                    (Some(x0), false) => {
+                        // Rounded corners make the edge shorter than the box
+                        if x - x0 + 2 * MAX_CORNER_RADIUS >= MIN_SIDE {
+                            segments.push(Segment { y, x0, x1: x });
+                        }
+                        start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        segments
+    }
+}
+
+/// Counts the vertical edges in columns of an image, the counts of each column are computed the
+/// first time they're needed
+struct ColumnEdges<'luma> {
+    luma: &'luma Luma,
+    /// How many vertical edges there are above each row of a column
+    prefix_sums: HashMap<usize, Vec<u32>>,
+}
+
+impl<'luma> ColumnEdges<'luma> {
+    fn new(luma: &'luma Luma) -> Self {
+        Self {
+            luma,
+            prefix_sums: HashMap::new(),
+        }
+    }
+
+    fn count(&mut self, x: usize, rows: Range<usize>) -> u32 {
+        let luma = self.luma;
+        let prefix_sums = self.prefix_sums.entry(x).or_insert_with(|| {
+            let mut sums = Vec::with_capacity(luma.height + 1);
+            sums.push(0);
+            for y in 0..luma.height {
+                sums.push(sums[y] + luma.vertical_edge(x, y) as u32);
+            }
+            sums
+        });
+
+        prefix_sums[rows.end] - prefix_sums[rows.start]
+    }
+
+    /// Returns the box whose top and bottom sides are `top` and `bottom`, if vertical edges join
+    /// their ends
+    fn find_box(&mut self, top: &Segment, bottom: &Segment) -> Option<Rectangle> {
+        let height = bottom.y - top.y;
+        // Rounded corners make the vertical sides shorter than the box as well
+        let inset = MAX_CORNER_RADIUS.min(height / 4);
+        let rows = top.y + inset..bottom.y - inset;
+
+        let left_edge = top.x0.min(bottom.x0);
+        let left_columns = left_edge.saturating_sub(MAX_CORNER_RADIUS)..=top.x0.max(bottom.x0) + 1;
+        let right_edge = top.x1.max(bottom.x1);
+        let right_columns = top.x1.min(bottom.x1).saturating_sub(1)
+            ..=(right_edge + MAX_CORNER_RADIUS).min(self.luma.width);
+
+        let left = self.find_side(left_columns, left_edge, rows.clone())?;
+        let right = self.find_side(right_columns, right_edge, rows)?;
+
+        (right >= left + MIN_SIDE).then_some(Rectangle {
+            x: left as f64,
+            y: top.y as f64,
+            w: (right - left) as f64,
+            h: height as f64,
+        })
+    }
+
+    /// Returns the column out of `columns` that has an edge in the most of `rows`, if there is one
+    /// that has enough of them. Of columns with as many edges, the one closest to `expected` wins,
+    /// so that the sides of boxes next to each other aren't mixed up.
+    fn find_side(
+        &mut self,
+        columns: RangeInclusive<usize>,
+        expected: usize,
+        rows: Range<usize>,
+    ) -> Option<usize> {
+        let needed = (rows.len() as f64 * MIN_SIDE_COVERAGE).ceil() as u32;
+
+        columns
+            .map(|x| (x, self.count(x, rows.clone())))
+            .filter(|&(_, count)| count >= needed)
+            .max_by_key(|&(x, count)| (count, Reverse(x.abs_diff(expected))))
+            .map(|(x, _)| x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::detect;
+
+    const BACKGROUND: u32 = 0x00f0f0f0;
+
+    /// Creates a `width`x`height` RGB24 image with the given boxes drawn in `colour` on it
+    fn image(width: usize, height: usize, boxes: &[(usize, usize, usize, usize, u32)]) -> Vec<u8> {
+        let mut pixels = vec![BACKGROUND; width * height];
+
+        for &(x, y, w, h, colour) in boxes {
+            for row in y..y + h {
+                pixels[row * width + x..row * width + x + w].fill(colour);
+            }
+        }
+
+        pixels.into_iter().flat_map(u32::to_ne_bytes).collect()
+    }
+
+    #[test]
+    fn filled_box() {
+        let pixels = image(200, 150, &[(20, 30, 100, 80, 0x00202020)]);
+
+        let regions = detect(&pixels, 200, 150, 200 * 4);
+
+        assert_eq!(regions.len(), 1);
+        let region = regions[0];
+        assert_eq!(
+            (region.x, region.y, region.w, region.h),
+            (20.0, 30.0, 100.0, 80.0)
+        );
+    }
+
+    #[test]
+    fn nested_boxes_smallest_first() {
+        let pixels = image(
+            300,
+            300,
+            &[
+                (10, 10, 250, 250, 0x00303030),
+                (60, 60, 100, 100, 0x00c0c0c0),
+            ],
+        );
+
+        let regions = detect(&pixels, 300, 300, 300 * 4);
+
+        assert_eq!(regions.len(), 2);
+        assert_eq!((regions[0].x, regions[0].w), (60.0, 100.0));
+        assert_eq!((regions[1].x, regions[1].w), (10.0, 250.0));
+    }
+
+    #[test]
+    fn outline() {
+        let border = 0x00000000;
+        let pixels = image(
+            200,
+            200,
+            &[
+                (50, 50, 100, 1, border),
+                (50, 149, 100, 1, border),
+                (50, 50, 1, 100, border),
+                (149, 50, 1, 100, border),
+            ],
+        );
+
+        let regions = detect(&pixels, 200, 200, 200 * 4);
+
+        // Both sides of the border are edges, but only the outer one is a box we care about
+        assert_eq!(regions.len(), 1);
+        let region = regions[0];
+        assert_eq!(
+            (region.x, region.y, region.w, region.h),
+            (50.0, 50.0, 100.0, 100.0)
+        );
+    }
+
+    #[test]
+    fn rounded_corners() {
+        const RADIUS: usize = 8;
+        let (width, height) = (200, 150);
+        let mut pixels = image(width, height, &[(20, 30, 100, 80, 0x00202020)]);
+
+        // Cuts the corners of the box off, leaving a quarter of a circle in each
+        for (corner_x, corner_y) in [(20, 30), (112, 30), (20, 102), (112, 102)] {
+            for y in corner_y..corner_y + RADIUS {
+                for x in corner_x..corner_x + RADIUS {
+                    let centre_x = if corner_x == 20 { 20 + RADIUS } else { 111 };
+                    let centre_y = if corner_y == 30 { 30 + RADIUS } else { 101 };
+                    if x.abs_diff(centre_x).pow(2) + y.abs_diff(centre_y).pow(2) > RADIUS.pow(2) {
+                        let idx = (y * width + x) * 4;
+                        pixels[idx..idx + 4].copy_from_slice(&BACKGROUND.to_ne_bytes());
+                    }
+                }
+            }
+        }
+
+        let regions = detect(&pixels, width, height, width * 4);
+
+        assert_eq!(regions.len(), 1);
+        let region = regions[0];
+        assert_eq!(
+            (region.x, region.y, region.w, region.h),
+            (20.0, 30.0, 100.0, 80.0)
+        );
+    }
+
+    #[test]
+    fn lines_are_not_boxes() {
+        let pixels = image(
+            200,
+            200,
+            &[(10, 50, 180, 2, 0x00000000), (10, 120, 180, 2, 0x00000000)],
+        );
+
+        assert!(detect(&pixels, 200, 200, 200 * 4).is_empty());
+    }
+
+    #[test]
+    fn small_boxes_are_ignored() {
+        let pixels = image(100, 100, &[(10, 10, 20, 20, 0x00000000)]);
+
+        assert!(detect(&pixels, 100, 100, 100 * 4).is_empty());
+    }
+
+    #[test]
+    fn padded_rows() {
+        let width = 120;
+        let stride = (width + 8) * 4;
+        let unpadded = image(width, 100, &[(10, 10, 60, 60, 0x00000000)]);
+        let pixels = unpadded
+            .chunks(width * 4)
+            .flat_map(|row| row.iter().copied().chain([0xff; 32]))
+            .collect::<Vec<_>>();
+
+        let regions = detect(&pixels, width, 100, stride);
+
+        assert_eq!(regions.len(), 1);
+        assert_eq!((regions[0].x, regions[0].w), (10.0, 60.0));
+    }
+}
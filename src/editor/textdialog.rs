@@ -1,4 +1,5 @@
-use gtk4::glib;
+use gtk4::{glib, prelude::*, subclass::prelude::*};
+use kcshot_data::Text;
 
 mod parse;
 mod text_input;
@@ -14,9 +15,27 @@ impl TextDialog {
     pub fn new(editor: &super::EditorWindow) -> Self {
         glib::Object::builder().property("editor", editor).build()
     }
+
+    /// Creates a dialog for changing the text of the text operation at `idx`, prefilled with `text`.
+    ///
+    /// The text keeps its own colour, the editor's secondary colour is left untouched.
+    pub fn for_editing(editor: &super::EditorWindow, idx: usize, text: &Text) -> Self {
+        let dialog = Self::new(editor);
+        dialog.set_title(Some("kcshot - Edit text"));
+
+        let imp = dialog.imp();
+        imp.edited_operation.set(Some(idx));
+        imp.text_input.set_markdown(&text.markdown);
+        imp.text_input.set_font_description(&text.font_description);
+        imp.text_input.set_colour_override(text.colour);
+
+        dialog
+    }
 }
 
 mod underlying {
+    use std::cell::Cell;
+
     use gtk4::{
         CompositeTemplate,
         glib::{self, Properties, WeakRef},
@@ -34,9 +53,11 @@ mod underlying {
     pub struct TextDialog {
         #[property(get, set, construct_only)]
         editor: WeakRef<EditorWindow>,
+        /// The text operation being edited, new text is added if there isn't one
+        pub(super) edited_operation: Cell<Option<usize>>,
 
         #[template_child]
-        text_input: TemplateChild<TextInput>,
+        pub(super) text_input: TemplateChild<TextInput>,
     }
 
     #[glib::object_subclass]
@@ -82,6 +103,7 @@ mod underlying {
             //       calls `with_image`, which will fail inside `with_image_mut`
             let text = Text {
                 string: self.text_input.text(),
+                markdown: self.text_input.markdown(),
                 font_description: self.text_input.font_description(),
                 colour: self.text_input.colour(),
             };
@@ -90,8 +112,12 @@ mod underlying {
                 editor
                     .imp()
                     .with_image_mut("text dialog response", |image| {
-                        image.operation_stack.set_text(text);
-                        image.operation_stack.finish_current_operation();
+                        if let Some(idx) = self.edited_operation.get() {
+                            image.operation_stack.edit_text(idx, text);
+                        } else {
+                            image.operation_stack.set_text(text);
+                            image.operation_stack.finish_current_operation();
+                        }
                     });
             } else {
                 tracing::warn!(
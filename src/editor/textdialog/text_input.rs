@@ -16,19 +16,39 @@ impl TextInput {
     }
 
     pub(super) fn text(&self) -> String {
-        let input = self.imp().input_view.get();
-        let buffer = input.buffer();
+        parse::markdown2pango(&self.markdown())
+    }
+
+    /// Returns what the user typed, before it got turned into Pango markup
+    pub(super) fn markdown(&self) -> String {
+        let buffer = self.imp().input_view.get().buffer();
 
-        let markdown = buffer
+        buffer
             .text(&buffer.start_iter(), &buffer.end_iter(), true)
-            .to_string();
+            .to_string()
+    }
+
+    pub(super) fn set_markdown(&self, markdown: &str) {
+        self.imp().input_view.get().buffer().set_text(markdown);
+    }
+
+    pub(super) fn set_font_description(&self, font_description: &pango::FontDescription) {
+        self.imp().font_button.get().set_font_desc(font_description);
+    }
 
-        parse::markdown2pango(&markdown)
+    /// Makes the text use `colour` instead of the editor's secondary colour, so that editing text
+    /// that was drawn in another colour doesn't change what the user draws with
+    pub(super) fn set_colour_override(&self, colour: Colour) {
+        self.imp().colour_override.set(Some(colour));
+        self.imp().colour_button_drawing_area.queue_draw();
     }
 
     #[track_caller]
     pub(super) fn colour(&self) -> Colour {
-        self.editor().unwrap().secondary_colour()
+        self.imp()
+            .colour_override
+            .get()
+            .unwrap_or_else(|| self.editor().unwrap().secondary_colour())
     }
 
     #[track_caller]
@@ -42,6 +62,8 @@ impl TextInput {
 }
 
 mod underlying {
+    use std::cell::Cell;
+
     use gtk4::{
         CompositeTemplate,
         glib::{self, Properties, WeakRef},
@@ -67,9 +89,12 @@ mod underlying {
         editor: WeakRef<EditorWindow>,
         #[property(get, set)]
         parent_dialog: WeakRef<TextDialog>,
+        /// Used instead of the editor's secondary colour if set, see
+        /// [`super::TextInput::set_colour_override`]
+        pub(super) colour_override: Cell<Option<Colour>>,
 
         #[template_child]
-        colour_button_drawing_area: TemplateChild<gtk4::DrawingArea>,
+        pub(super) colour_button_drawing_area: TemplateChild<gtk4::DrawingArea>,
         #[template_child]
         pub(super) font_button: TemplateChild<gtk4::FontButton>,
         #[template_child]
@@ -112,12 +137,10 @@ mod underlying {
                 move |_this, cairo, _w, _h| {
                     cairo.set_operator(cairo::Operator::Over);
 
-                    let editor = text_input.editor().unwrap();
-
-                    let secondary_colour = editor.secondary_colour();
-                    if secondary_colour.alpha != 0 {
+                    let colour = text_input.colour();
+                    if colour.alpha != 0 {
                         cairo.rectangle(0.0, 0.0, SIZE, SIZE);
-                        cairo.set_source_colour(secondary_colour);
+                        cairo.set_source_colour(colour);
                         log_if_err!(cairo.fill());
                     } else {
                         // Instead of drawing nothing (what a fully transparent colour is) we draw a
@@ -160,10 +183,16 @@ mod underlying {
         async fn on_colour_button_clicked(&self, _: &gtk4::Button) {
             let editor = self.obj().editor().unwrap();
 
-            let dialog = ColourChooserDialog::new(&editor, editor.secondary_colour());
+            let dialog = ColourChooserDialog::new(&editor, self.obj().colour());
             dialog.set_transient_for(self.parent_dialog.upgrade().as_ref());
             dialog.show();
-            editor.set_secondary_colour(dialog.colour().await);
+
+            let colour = dialog.colour().await;
+            if self.colour_override.get().is_some() {
+                self.colour_override.set(Some(colour));
+            } else {
+                editor.set_secondary_colour(colour);
+            }
 
             self.colour_button_drawing_area.queue_draw();
         }
@@ -1,4 +1,5 @@
 use gtk4::{gdk, glib, prelude::*, subclass::prelude::*};
+use kcshot_data::settings::ToolProperties;
 
 use self::toolbutton::ToolButton;
 use super::operations::Tool;
@@ -49,6 +50,11 @@ impl ToolbarWidget {
 
         false
     }
+
+    /// Makes the properties popover show only what applies to `tool`, with the values in `properties`
+    pub fn show_tool_properties(&self, tool: Tool, properties: ToolProperties) {
+        self.imp().show_tool_properties(tool, properties);
+    }
 }
 
 mod underlying {
@@ -60,6 +66,7 @@ mod underlying {
         prelude::*,
         subclass::prelude::*,
     };
+    use kcshot_data::settings::{ArrowHead, StrokeStyle, ToolProperties};
 
     use super::toolbutton::{ToolButton, should_start_saving_immediately};
     use crate::{
@@ -84,6 +91,40 @@ mod underlying {
         primary: TemplateChild<ColourButton>,
         #[template_child]
         secondary: TemplateChild<ColourButton>,
+        #[template_child]
+        line_width_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        line_width_spinner: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        stroke_style_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        stroke_style_dropdown: TemplateChild<gtk4::DropDown>,
+        #[template_child]
+        fill_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        fill_switch: TemplateChild<gtk4::Switch>,
+        #[template_child]
+        opacity_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        opacity_scale: TemplateChild<gtk4::Scale>,
+        #[template_child]
+        strength_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        strength_spinner: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        arrow_head_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        arrow_head_dropdown: TemplateChild<gtk4::DropDown>,
+        #[template_child]
+        double_headed_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        double_headed_switch: TemplateChild<gtk4::Switch>,
+
+        /// The tool whose properties are being shown
+        tool: Cell<Option<Tool>>,
+        /// Set while the properties of a tool are being put into the popover, so that doesn't count
+        /// as the user changing them
+        is_showing_tool: Cell<bool>,
     }
 
     #[glib::object_subclass]
@@ -159,8 +200,108 @@ mod underlying {
         }
 
         #[template_callback]
-        fn on_line_width_changed(&self, spinner: &gtk4::SpinButton) {
-            self.editor().set_line_width(spinner.value());
+        fn on_line_width_changed(&self, _: &gtk4::SpinButton) {
+            self.on_properties_changed();
+        }
+
+        #[template_callback]
+        fn on_stroke_style_changed(&self, _: &gtk4::DropDown, _: &glib::ParamSpec) {
+            self.on_properties_changed();
+        }
+
+        #[template_callback]
+        fn on_fill_toggled(&self, _: &gtk4::Switch, _: &glib::ParamSpec) {
+            self.on_properties_changed();
+        }
+
+        #[template_callback]
+        fn on_opacity_changed(&self, _: &gtk4::Scale) {
+            self.on_properties_changed();
+        }
+
+        #[template_callback]
+        fn on_strength_changed(&self, _: &gtk4::SpinButton) {
+            self.on_properties_changed();
+        }
+
+        #[template_callback]
+        fn on_arrow_head_changed(&self, _: &gtk4::DropDown, _: &glib::ParamSpec) {
+            self.on_properties_changed();
+        }
+
+        #[template_callback]
+        fn on_double_headed_toggled(&self, _: &gtk4::Switch, _: &glib::ParamSpec) {
+            self.on_properties_changed();
+        }
+
+        fn on_properties_changed(&self) {
+            if self.is_showing_tool.get() {
+                return;
+            }
+
+            if let Some(tool) = self.tool.get() {
+                let properties = ToolProperties {
+                    line_width: self.line_width_spinner.value(),
+                    fill: self.fill_switch.is_active(),
+                    opacity: self.opacity_scale.value(),
+                    strength: self.strength_spinner.value(),
+                    stroke_style: StrokeStyle::ALL
+                        .get(self.stroke_style_dropdown.selected() as usize)
+                        .copied()
+                        .unwrap_or_default(),
+                    arrow_head: ArrowHead::ALL
+                        .get(self.arrow_head_dropdown.selected() as usize)
+                        .copied()
+                        .unwrap_or_default(),
+                    double_headed: self.double_headed_switch.is_active(),
+                };
+                self.editor().set_tool_properties(tool, properties);
+            }
+        }
+
+        pub(super) fn show_tool_properties(&self, tool: Tool, properties: ToolProperties) {
+            self.tool.set(Some(tool));
+
+            self.is_showing_tool.set(true);
+            self.line_width_spinner.set_value(properties.line_width);
+            self.fill_switch.set_active(properties.fill);
+            self.opacity_scale.set_value(properties.opacity);
+            self.strength_spinner.set_value(properties.strength);
+            let stroke_style = StrokeStyle::ALL
+                .iter()
+                .position(|&style| style == properties.stroke_style)
+                .unwrap_or_default();
+            self.stroke_style_dropdown.set_selected(stroke_style as u32);
+            let arrow_head = ArrowHead::ALL
+                .iter()
+                .position(|&head| head == properties.arrow_head)
+                .unwrap_or_default();
+            self.arrow_head_dropdown.set_selected(arrow_head as u32);
+            self.double_headed_switch
+                .set_active(properties.double_headed);
+            self.is_showing_tool.set(false);
+
+            self.line_width_label.set_visible(tool.has_line_width());
+            self.line_width_spinner.set_visible(tool.has_line_width());
+            self.stroke_style_label.set_visible(tool.has_line_width());
+            self.stroke_style_dropdown
+                .set_visible(tool.has_line_width());
+            self.fill_label.set_visible(tool.has_fill());
+            self.fill_switch.set_visible(tool.has_fill());
+            self.opacity_label.set_visible(tool.has_opacity());
+            self.opacity_scale.set_visible(tool.has_opacity());
+
+            self.arrow_head_label.set_visible(tool.has_arrow_heads());
+            self.arrow_head_dropdown.set_visible(tool.has_arrow_heads());
+            self.double_headed_label.set_visible(tool.has_arrow_heads());
+            self.double_headed_switch
+                .set_visible(tool.has_arrow_heads());
+
+            let strength_label = tool.strength_label();
+            self.strength_label
+                .set_label(strength_label.unwrap_or_default());
+            self.strength_label.set_visible(strength_label.is_some());
+            self.strength_spinner.set_visible(strength_label.is_some());
         }
     }
 }
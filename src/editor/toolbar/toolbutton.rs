@@ -47,7 +47,7 @@ mod underlying {
     #[properties(wrapper_type = super::ToolButton)]
     pub struct ToolButton {
         #[property(get, set)]
-        spinner: RefCell<Option<gtk4::SpinButton>>,
+        properties: RefCell<Option<gtk4::MenuButton>>,
         #[property(get, set)]
         primary: RefCell<Option<gtk4::Button>>,
         #[property(get, set)]
@@ -68,7 +68,7 @@ mod underlying {
     impl Default for ToolButton {
         fn default() -> Self {
             Self {
-                spinner: Default::default(),
+                properties: Default::default(),
                 primary: Default::default(),
                 secondary: Default::default(),
                 editor: Default::default(),
@@ -134,8 +134,8 @@ mod underlying {
         fn on_toggled(&self, toggle: &gtk4::ToggleButton) {
             // This can probably be achieved using property bindings... somehow
 
-            if let Some(spinner) = &*self.spinner.borrow() {
-                spinner.set_visible(toggle.is_active());
+            if let Some(properties) = &*self.properties.borrow() {
+                properties.set_visible(toggle.is_active());
             }
 
             if let Some(primary) = &*self.primary.borrow() {
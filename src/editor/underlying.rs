@@ -1,7 +1,9 @@
 use std::{
     backtrace::Backtrace,
     cell::{Cell, OnceCell, RefCell},
+    collections::HashMap,
     marker::PhantomData,
+    time::Duration,
 };
 
 use cairo::Context;
@@ -18,13 +20,14 @@ use kcshot_data::{
     geometry::{Point, Rectangle},
     settings::Settings,
 };
+use kcshot_screenshot::{CaptureMetadata, CaptureTarget, Screenshot};
 use tokio::sync::mpsc::{self, Receiver, Sender};
 use tracing::error;
 
-use super::{Colour, textdialog::TextDialog, toolbar, utils::ContextLogger};
+use super::{Colour, RegionUse, regions, textdialog::TextDialog, toolbar, utils::ContextLogger};
 use crate::{
     editor::{
-        operations::{OperationStack, SelectionMode, Tool},
+        operations::{CursorLayer, OperationStack, SelectionMode, Tool, ZOrder},
         utils,
     },
     ext::DisposeExt,
@@ -37,6 +40,13 @@ use crate::{
 #[derive(Debug)]
 pub(super) struct Image {
     surface: cairo::ImageSurface,
+    metadata: CaptureMetadata,
+    /// Whether the image shows the screen at `metadata.bounds` as it is, which isn't the case for
+    /// e.g. scrolling captures. Only regions of such images are worth capturing again.
+    shows_screen: bool,
+    /// The windows with an ID captured on their own (along with their decorations) right after the
+    /// screenshot, keyed by their ID, see [`EditorWindow::capture_cropped_window`]
+    window_captures: HashMap<u32, cairo::ImageSurface>,
     pub(super) operation_stack: OperationStack,
 }
 
@@ -90,6 +100,9 @@ pub struct EditorWindow {
     secondary_colour: PhantomData<Colour>,
 
     pub(super) image: RefCell<Option<Image>>,
+    /// Set when the editor is only used for picking a region of the screen, along with the position
+    /// of the screenshot inside the virtual screen
+    picked_region_use: Cell<Option<(RegionUse, Rectangle)>>,
 
     #[template_child]
     overlay: TemplateChild<gtk4::Overlay>,
@@ -153,6 +166,7 @@ impl Default for EditorWindow {
         Self {
             editing_started_with_cropping: Default::default(),
             image: Default::default(),
+            picked_region_use: Default::default(),
             overlay: Default::default(),
             drawing_area: Default::default(),
             toolbar: Default::default(),
@@ -190,12 +204,70 @@ impl ObjectImpl for EditorWindow {
         self.parent_constructed();
         let obj = self.obj();
 
-        let image = kcshot_screenshot::take_screenshot(KCShot::the().tokio_rt())
-            .expect("Couldn't take a screenshot");
-        let windows = kcshot_screenshot::get_windows().unwrap_or_else(|why| {
+        self.overlay.connect_get_child_position({
+            let obj = obj.clone();
+            move |_, widget| obj.imp().on_get_child_position(widget)
+        });
+
+        self.drawing_area.set_draw_func(clone!(
+            #[weak]
+            obj,
+            move |_, cairo, _, _| {
+                obj.imp().with_image("draw event", |image| {
+                    EditorWindow::do_draw(image, cairo, true);
+                });
+            }
+        ));
+
+        self.setup_actions();
+    }
+
+    fn dispose(&self) {
+        self.obj().dispose_children();
+        self.with_image_mut("dispose", |image| image.surface.finish());
+    }
+}
+
+impl EditorWindow {
+    /// Takes the screenshot that will be edited and adds the widgets that depend on it existing.
+    ///
+    /// This must be called right after construction, e.g. the toolbar binds to the colours stored
+    /// inside the image's operation stack. If the screenshot can't be taken, the editor is left
+    /// without an image and must not be shown.
+    pub(super) fn init_with_screenshot(
+        &self,
+        capture_target: &CaptureTarget,
+    ) -> Result<(), kcshot_screenshot::Error> {
+        let screenshot =
+            kcshot_screenshot::take_screenshot(KCShot::the().tokio_rt(), capture_target)?;
+        let windows = kcshot_screenshot::get_windows(capture_target).unwrap_or_else(|why| {
             tracing::info!("Got while trying to retrieve windows: {why}");
             vec![]
         });
+
+        self.init_with_image(screenshot, windows, true);
+
+        Ok(())
+    }
+
+    /// Like [`Self::init_with_screenshot`], but for a screenshot that was captured beforehand, see
+    /// [`Image::shows_screen`] for `shows_screen`
+    pub(super) fn init_with_image(
+        &self,
+        screenshot: Screenshot,
+        windows: Vec<kcshot_screenshot::Window>,
+        shows_screen: bool,
+    ) {
+        let Screenshot {
+            image,
+            cursor,
+            metadata,
+        } = screenshot;
+
+        // The image may be bigger than the window, in which case the drawing area gets scrolled
+        self.drawing_area.set_content_width(image.width());
+        self.drawing_area.set_content_height(image.height());
+
         let screen_dimensions = Rectangle {
             x: 0.0,
             y: 0.0,
@@ -203,11 +275,36 @@ impl ObjectImpl for EditorWindow {
             h: image.height() as f64,
         };
 
+        // Monitors are positioned inside the virtual screen, which the screenshot may not start at
+        let monitors = metadata
+            .monitors
+            .iter()
+            .map(|monitor| Rectangle {
+                x: monitor.rect.x - metadata.bounds.x,
+                y: monitor.rect.y - metadata.bounds.y,
+                ..monitor.rect
+            })
+            .collect();
+
+        // Windows are captured now, so that what gets saved is what was on the screen when the
+        // screenshot was taken rather than whatever the window shows once the user is done editing
+        let window_captures = if shows_screen {
+            Self::capture_windows(&windows)
+        } else {
+            HashMap::new()
+        };
+
+        let has_cursor = cursor.is_some();
         let settings = Settings::open();
         self.image.replace(Some(Image {
             surface: image,
+            metadata,
+            shows_screen,
+            window_captures,
             operation_stack: OperationStack::new(
                 windows,
+                monitors,
+                cursor.map(CursorLayer::new),
                 screen_dimensions,
                 self.editing_started_with_cropping.get(),
                 settings.last_used_primary_colour(),
@@ -215,36 +312,77 @@ impl ObjectImpl for EditorWindow {
             ),
         }));
 
-        self.overlay.connect_get_child_position({
-            let obj = obj.clone();
-            move |_, widget| obj.imp().on_get_child_position(widget)
-        });
+        self.detect_regions();
+
+        let obj = self.obj();
+        if let Some(toggle_cursor) = obj
+            .lookup_action("toggle-cursor")
+            .and_downcast::<gio::SimpleAction>()
+        {
+            toggle_cursor.set_enabled(has_cursor);
+        }
 
         let toolbar = self.toolbar.get_or_init(|| {
             toolbar::ToolbarWidget::new(&obj, self.editing_started_with_cropping.get())
         });
         self.overlay.add_overlay(toolbar);
+    }
 
-        self.drawing_area.set_draw_func(clone!(
+    /// Looks for boxes in the screenshot in the background, so that they can be picked like windows
+    fn detect_regions(&self) {
+        let pixels = self.with_image("detect regions", |image| {
+            let surface = &image.surface;
+            // The detection only understands 8 bits per channel
+            if !matches!(
+                surface.format(),
+                cairo::Format::Rgb24 | cairo::Format::ARgb32
+            ) {
+                return None;
+            }
+
+            let mut pixels = vec![];
+            surface.with_data(|data| pixels = data.to_vec()).ok()?;
+
+            Some((
+                pixels,
+                surface.width() as usize,
+                surface.height() as usize,
+                surface.stride() as usize,
+            ))
+        });
+        let Some(Some((pixels, width, height, stride))) = pixels else {
+            return;
+        };
+
+        let regions = match regions::detect_in_background(pixels, width, height, stride) {
+            Ok(regions) => regions,
+            Err(why) => {
+                tracing::warn!("Failed to start looking for boxes in the screenshot: {why}");
+                return;
+            }
+        };
+
+        let obj = self.obj();
+        glib::MainContext::default().spawn_local(clone!(
             #[weak]
             obj,
-            move |_, cairo, _, _| {
-                obj.imp().with_image("draw event", |image| {
-                    EditorWindow::do_draw(image, cairo, true);
+            async move {
+                let Ok(regions) = regions.await else {
+                    return;
+                };
+
+                obj.imp().with_image_mut("regions detected", |image| {
+                    image.operation_stack.set_regions(regions);
                 });
             }
         ));
-
-        self.setup_actions();
     }
 
-    fn dispose(&self) {
-        self.obj().dispose_children();
-        self.with_image_mut("dispose", |image| image.surface.finish());
+    pub(super) fn set_picked_region_use(&self, region_use: RegionUse, capture_bounds: Rectangle) {
+        self.picked_region_use
+            .set(Some((region_use, capture_bounds)));
     }
-}
 
-impl EditorWindow {
     fn on_get_child_position(&self, widget: &gtk4::Widget) -> Option<Allocation> {
         if widget.is::<gtk4::DrawingArea>() {
             return Some(Allocation::new(0, 0, widget.width(), widget.height()));
@@ -253,6 +391,9 @@ impl EditorWindow {
         let (width, height) = self.with_image("get-child-position", |image| {
             (image.surface.width(), image.surface.height())
         })?;
+        // Only the visible part of the image matters when it doesn't fit inside the window
+        let width = width.min(self.overlay.width());
+        let height = height.min(self.overlay.height());
 
         Some(Allocation::new(
             width / 2 - widget.width() / 2,
@@ -267,21 +408,49 @@ impl EditorWindow {
 #[gtk4::template_callbacks]
 impl EditorWindow {
     #[track_caller]
-    fn toolbar(&self) -> &toolbar::ToolbarWidget {
+    pub(super) fn toolbar(&self) -> &toolbar::ToolbarWidget {
         self.toolbar.get().unwrap()
     }
 
     #[template_callback]
-    async fn on_mouse_button_pressed(&self, _: i32, x: f64, y: f64, click: &gtk4::GestureClick) {
+    async fn on_mouse_button_pressed(
+        &self,
+        n_press: i32,
+        x: f64,
+        y: f64,
+        click: &gtk4::GestureClick,
+    ) {
         if click.current_button() == BUTTON_PRIMARY {
             if self.colour_requested.get() {
                 let colour = self.with_image("colour picker", |image| image.get_colour_at(x, y));
                 self.colour_requested.set(false);
                 self.colour_tx.send(colour.unwrap()).await.unwrap();
             } else {
-                self.with_image_mut("primary button pressed", |image| {
-                    image.operation_stack.start_operation_at(Point { x, y });
-                });
+                let edited_text = self
+                    .with_image_mut("primary button pressed", |image| {
+                        // The user may click before we got any motion events
+                        image.operation_stack.set_current_window(x, y);
+
+                        let point = Point { x, y };
+                        if !image.operation_stack.start_cursor_drag(point) {
+                            image.operation_stack.start_operation_at(point);
+                        }
+
+                        // Double-clicking text with the select tool edits it
+                        if n_press == 2 {
+                            image.operation_stack.selected_text()
+                        } else {
+                            None
+                        }
+                    })
+                    .flatten();
+
+                if let Some((idx, text)) = edited_text {
+                    let obj = self.obj();
+                    let dialog = TextDialog::for_editing(&obj, idx, &text);
+                    dialog.set_transient_for(Some(&*obj));
+                    dialog.show();
+                }
             }
         } else if click.current_button() == BUTTON_SECONDARY {
             self.obj().close();
@@ -297,12 +466,16 @@ impl EditorWindow {
     }
 
     #[template_callback]
-    fn on_mouse_button_released(&self, _: i32, x: f64, y: f64, _: &gtk4::GestureClick) {
+    fn on_mouse_button_released(&self, _: i32, _: f64, _: f64, _: &gtk4::GestureClick) {
         let should_queue_draw = self.with_image_mut("mouse button released event", |image| {
             // NOTE: image.operation_stack.finish_current_operation MUST be called in all
             //       branches of this if-chain, in order for tools to take part in the undo
             //       stack! For the Text tool, this happens in pop_text_dialog_and_get_text.
-            if image.operation_stack.current_tool() == Tool::Text {
+            //       Dragging the cursor around doesn't start an operation, so there's nothing to
+            //       finish in that case.
+            if image.operation_stack.finish_cursor_drag() {
+                true
+            } else if image.operation_stack.current_tool() == Tool::Text {
                 let dialog = TextDialog::new(&self.obj());
                 dialog.set_transient_for(Some(&*self.obj()));
                 dialog.show();
@@ -310,6 +483,10 @@ impl EditorWindow {
             } else if !image.operation_stack.current_tool().is_saving_tool() {
                 image.operation_stack.finish_current_operation();
                 true
+            } else if self.picked_region_use.get().is_some() {
+                image.operation_stack.finish_current_operation();
+                self.use_picked_region(image);
+                false
             } else {
                 image.operation_stack.finish_current_operation();
 
@@ -319,7 +496,6 @@ impl EditorWindow {
                         conn,
                         self.obj().upcast_ref(),
                         image,
-                        Some(Point { x, y }),
                     );
                 });
                 false
@@ -334,6 +510,11 @@ impl EditorWindow {
     #[template_callback]
     fn on_drag_update(&self, x: f64, y: f64, _: &gtk4::GestureDrag) {
         self.with_image_mut("drag update event", |image| {
+            if image.operation_stack.update_cursor_drag(x, y) {
+                self.drawing_area.queue_draw();
+                return;
+            }
+
             image
                 .operation_stack
                 .update_current_operation_end_coordinate(x, y);
@@ -347,24 +528,52 @@ impl EditorWindow {
     #[template_callback]
     fn on_drag_end(&self, x: f64, y: f64, _: &gtk4::GestureDrag) {
         self.with_image_mut("drag end event", |image| {
+            if image.operation_stack.update_cursor_drag(x, y) {
+                self.drawing_area.queue_draw();
+                return;
+            }
+
             image
                 .operation_stack
                 .update_current_operation_end_coordinate(x, y);
             if image.operation_stack.current_tool() == Tool::Crop {
                 self.toolbar().set_visible(true);
                 image.operation_stack.finish_current_operation();
+                // This can't go through `EditorWindow::set_current_tool`, as we're already
+                // borrowing the image, but the toolbar has to know about the new tool too
+                let properties = Tool::Pencil.properties();
                 image.operation_stack.set_current_tool(Tool::Pencil);
+                image.operation_stack.tool_properties = properties;
+                self.toolbar()
+                    .show_tool_properties(Tool::Pencil, properties);
             }
             self.drawing_area.queue_draw();
         });
     }
 
+    #[template_callback]
+    fn on_scroll(&self, _: f64, dy: f64, _: &gtk4::EventControllerScroll) -> Propagation {
+        let cycled = self
+            .with_image_mut("scroll event", |image| {
+                image.operation_stack.cycle_candidates(dy > 0.0)
+            })
+            .unwrap_or(false);
+
+        // Images bigger than the editor get scrolled when there's nothing to cycle through
+        if cycled {
+            self.drawing_area.queue_draw();
+            Propagation::Stop
+        } else {
+            Propagation::Proceed
+        }
+    }
+
     #[template_callback]
     fn on_key_pressed(
         &self,
         key: gdk::Key,
         _: u32,
-        _: gdk::ModifierType,
+        modifiers: gdk::ModifierType,
         _: &gtk4::EventControllerKey,
     ) -> Propagation {
         let handled = self
@@ -380,13 +589,17 @@ impl EditorWindow {
                         return false;
                     }
 
+                    if self.picked_region_use.get().is_some() {
+                        self.use_picked_region(image);
+                        return true;
+                    }
+
                     KCShot::the().with_conn(|conn| {
                         Self::do_save_surface(
                             &KCShot::the().model_notifier(),
                             conn,
                             self.obj().upcast_ref(),
                             image,
-                            None,
                         );
                     });
 
@@ -394,13 +607,36 @@ impl EditorWindow {
                 } else if key == gdk::Key::Shift_L || key == gdk::Key::Shift_R {
                     image.operation_stack.selection_mode = SelectionMode::WindowsWithoutDecorations;
                     return true;
+                } else if key == gdk::Key::Tab || key == gdk::Key::ISO_Left_Tab {
+                    // Shift+Tab arrives as ISO_Left_Tab
+                    let cycled = image.operation_stack.cycle_candidates(key == gdk::Key::Tab);
+                    self.drawing_area.queue_draw();
+                    return cycled;
+                } else if key == gdk::Key::Delete || key == gdk::Key::BackSpace {
+                    let deleted = image.operation_stack.delete_selected();
+                    self.drawing_area.queue_draw();
+                    return deleted;
+                } else if let Some(order) = z_order_for_key(key) {
+                    let reordered = image.operation_stack.reorder_selected(order);
+                    self.drawing_area.queue_draw();
+                    return reordered;
+                } else if let Some((dx, dy)) = arrow_key_direction(key) {
+                    // Holding Shift resizes the selection instead of moving it
+                    let nudged = image.operation_stack.nudge_crop(
+                        dx,
+                        dy,
+                        modifiers.contains(gdk::ModifierType::SHIFT_MASK),
+                    );
+                    self.drawing_area.queue_draw();
+                    return nudged;
                 }
 
                 false
             })
             .unwrap_or(false);
 
-        if handled {
+        // Other tools make no sense when only picking a region
+        if handled || self.picked_region_use.get().is_some() {
             return Propagation::Stop;
         }
 
@@ -432,6 +668,28 @@ impl EditorWindow {
     }
 }
 
+/// Returns where `key` moves the operation picked with the select tool to
+fn z_order_for_key(key: gdk::Key) -> Option<ZOrder> {
+    Some(match key {
+        gdk::Key::Page_Up => ZOrder::Raise,
+        gdk::Key::Page_Down => ZOrder::Lower,
+        gdk::Key::Home => ZOrder::Top,
+        gdk::Key::End => ZOrder::Bottom,
+        _ => None?,
+    })
+}
+
+/// Returns which way the selection moves by one pixel when `key` is pressed, if it's an arrow key
+fn arrow_key_direction(key: gdk::Key) -> Option<(f64, f64)> {
+    Some(match key {
+        gdk::Key::Left | gdk::Key::KP_Left => (-1.0, 0.0),
+        gdk::Key::Right | gdk::Key::KP_Right => (1.0, 0.0),
+        gdk::Key::Up | gdk::Key::KP_Up => (0.0, -1.0),
+        gdk::Key::Down | gdk::Key::KP_Down => (0.0, 1.0),
+        _ => None?,
+    })
+}
+
 // Actions
 impl EditorWindow {
     fn setup_actions(&self) {
@@ -462,6 +720,26 @@ impl EditorWindow {
             }
         ));
         obj.add_action(&redo_action);
+
+        // Only enabled once we know whether the cursor was captured separately from the screenshot
+        let toggle_cursor_action =
+            gio::SimpleAction::new_stateful("toggle-cursor", None, &true.to_variant());
+        toggle_cursor_action.set_enabled(false);
+        toggle_cursor_action.connect_activate(clone!(
+            #[weak]
+            obj,
+            move |action, _| {
+                let visible = !action.state().and_then(|state| state.get()).unwrap_or(true);
+                action.set_state(&visible.to_variant());
+
+                obj.imp()
+                    .with_image_mut("win.toggle-cursor activated", |image| {
+                        image.operation_stack.set_cursor_visible(visible);
+                        obj.imp().drawing_area.queue_draw();
+                    });
+            }
+        ));
+        obj.add_action(&toggle_cursor_action);
     }
 }
 
@@ -479,6 +757,12 @@ impl EditorWindow {
         cairo.set_operator(cairo::Operator::Over);
 
         image.operation_stack.execute(cairo, is_in_draw_event);
+
+        if is_in_draw_event {
+            image
+                .operation_stack
+                .draw_selection_aids(cairo, &image.surface);
+        }
     }
 
     pub(super) fn do_save_surface(
@@ -486,7 +770,6 @@ impl EditorWindow {
         conn: &mut SqliteConnection,
         window: &gtk4::Window,
         image: &Image,
-        point: Option<Point>,
     ) {
         let cairo = match Context::new(&image.surface) {
             Ok(cairo) => cairo,
@@ -498,16 +781,52 @@ impl EditorWindow {
 
         Self::do_draw(image, &cairo, false);
 
-        let rectangle = image
-            .operation_stack
-            .crop_region(point)
-            .unwrap_or(image.operation_stack.screen_dimensions);
+        let crop_region = image.operation_stack.crop_region();
+        if let Some(region) = crop_region
+            && image.shows_screen
+        {
+            Self::remember_region(region.normalised(), image.metadata.bounds);
+        }
+
+        let rectangle = crop_region.unwrap_or(image.operation_stack.screen_dimensions);
+
+        // Where the saved image is inside of the screenshot, the pixbuf only contains whole pixels
+        let (pixbuf, region) = match Self::capture_cropped_window(image) {
+            Some((surface, region)) => (
+                utils::pixbuf_for(
+                    &surface,
+                    Rectangle {
+                        x: 0.0,
+                        y: 0.0,
+                        ..region
+                    },
+                ),
+                region,
+            ),
+            None => {
+                let Rectangle { x, y, w, h } = rectangle.normalised();
+                let region = Rectangle {
+                    x: x.floor(),
+                    y: y.floor(),
+                    w: w.ceil(),
+                    h: h.ceil(),
+                };
+
+                (utils::pixbuf_for(&image.surface, region), region)
+            }
+        };
 
         window.close();
 
-        match utils::pixbuf_for(&image.surface, rectangle) {
+        match pixbuf {
             // Process all post capture actions
-            Some(mut pixbuf) => run_postcapture_actions(model_notifier, conn, &mut pixbuf),
+            Some(mut pixbuf) => {
+                let metadata = CaptureMetadata {
+                    window: image.operation_stack.cropped_window().cloned(),
+                    ..image.metadata.clone().cropped(region)
+                };
+                run_postcapture_actions(model_notifier, conn, &mut pixbuf, &metadata);
+            }
             None => {
                 error!(
                     "Failed to create a pixbuf from the surface: {:?} with crop region {:#?}",
@@ -518,6 +837,142 @@ impl EditorWindow {
         };
     }
 
+    /// Stores `region` of a screenshot of `bounds` as the last region, so that it can be captured
+    /// again without going through the editor
+    fn remember_region(region: Rectangle, bounds: Rectangle) {
+        let region = Rectangle {
+            x: region.x + bounds.x,
+            y: region.y + bounds.y,
+            ..region
+        };
+
+        if let Err(why) = Settings::open().try_set_last_region(region) {
+            tracing::warn!("Failed to update `last-region` setting value: {why}");
+        }
+    }
+
+    /// Closes the editor and starts recording (or capturing while scrolling) the region the user
+    /// picked, or the whole screenshot if they didn't pick one.
+    fn use_picked_region(&self, image: &Image) {
+        let Some((region_use, bounds)) = self.picked_region_use.get() else {
+            return;
+        };
+
+        let screen = image.operation_stack.screen_dimensions;
+        let region = image
+            .operation_stack
+            .crop_region()
+            .unwrap_or(screen)
+            .normalised();
+
+        // Windows may be partially off-screen, but we can only record what's on the screen
+        let left = region.x.max(0.0);
+        let top = region.y.max(0.0);
+        let right = (region.x + region.w).min(screen.w);
+        let bottom = (region.y + region.h).min(screen.h);
+
+        // Nothing can be captured from regions that don't cover at least a whole pixel, so we leave
+        // the editor open for the user to pick another one
+        if right - left < 1.0 || bottom - top < 1.0 {
+            tracing::warn!("Can't {region_use} an empty region of the screen");
+            return;
+        }
+
+        // The crop region is relative to the screenshot, while recordings need absolute coordinates
+        let region = Rectangle {
+            x: left + bounds.x,
+            y: top + bounds.y,
+            w: right - left,
+            h: bottom - top,
+        };
+
+        self.obj().close();
+
+        // Give the editor some time to disappear from the screen, so that it doesn't show up in the
+        // first frames that get captured
+        glib::timeout_add_local_once(Duration::from_millis(200), move || match region_use {
+            RegionUse::Recording => KCShot::the().start_recording(region),
+            RegionUse::ScrollingCapture => KCShot::the().start_scrolling_capture(region),
+        });
+    }
+
+    /// Captures the windows that can be captured on their own, so that they can be saved without
+    /// the windows overlapping them, see [`Self::capture_cropped_window`]
+    fn capture_windows(windows: &[kcshot_screenshot::Window]) -> HashMap<u32, cairo::ImageSurface> {
+        windows
+            .iter()
+            .filter_map(|window| window.id)
+            .filter_map(|id| match kcshot_screenshot::capture_window(id, true) {
+                Ok(surface) => Some((id, surface)),
+                Err(why) => {
+                    tracing::info!("Failed to capture window {id:#x} on its own: {why}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the capture of the window the user picked, taken along with the screenshot, so that
+    /// windows overlapping it don't show up in the screenshot, with the annotations drawn on top of
+    /// it. It is returned along with where it is inside of the screenshot.
+    ///
+    /// Returns `None` if the user didn't pick a whole window or if it couldn't be captured on its own,
+    /// in which case the screenshot should be cropped instead.
+    fn capture_cropped_window(image: &Image) -> Option<(cairo::ImageSurface, Rectangle)> {
+        let (window, selection_mode) = image.operation_stack.cropped_whole_window()?;
+        let id = window.id?;
+        let capture = image.window_captures.get(&id)?;
+
+        // Windows are captured along with their decorations, which get cut off if the user
+        // doesn't want them
+        let (width, height, window_rect) = match selection_mode {
+            SelectionMode::WindowsWithDecorations => {
+                (capture.width(), capture.height(), window.outer_rect)
+            }
+            SelectionMode::WindowsWithoutDecorations => (
+                window.content_rect.w as i32,
+                window.content_rect.h as i32,
+                window.content_rect,
+            ),
+        };
+
+        let surface = match cairo::ImageSurface::create(cairo::Format::ARgb32, width, height) {
+            Ok(surface) => surface,
+            Err(why) => {
+                error!("Failed to create a surface for window {id:#x}: {why}");
+                return None;
+            }
+        };
+
+        let cairo = match Context::new(&surface) {
+            Ok(cairo) => cairo,
+            Err(why) => {
+                error!("Got error constructing Cairo context for window {id:#x}: {why}");
+                return None;
+            }
+        };
+        log_if_err!(cairo.set_source_surface(
+            capture,
+            window.outer_rect.x - window_rect.x,
+            window.outer_rect.y - window_rect.y,
+        ));
+        log_if_err!(cairo.paint());
+
+        // The annotations were made on top of the whole screenshot
+        cairo.translate(-window_rect.x, -window_rect.y);
+        image.operation_stack.execute(&cairo, false);
+        drop(cairo);
+
+        let region = Rectangle {
+            x: window_rect.x,
+            y: window_rect.y,
+            w: surface.width() as f64,
+            h: surface.height() as f64,
+        };
+
+        Some((surface, region))
+    }
+
     pub(super) fn with_image<F, T>(&self, ctx: &str, func: F) -> Option<T>
     where
         F: FnOnce(&Image) -> T,
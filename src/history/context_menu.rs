@@ -39,9 +39,14 @@ pub fn context_menu(data: RowData, parent: &gtk4::Widget) -> gtk4::PopoverMenu {
         let menu = menu.clone();
         move |_, _| {
             menu.popdown();
-            if let Some(path) = &path {
-                clipboard()
-                    .set_texture(&gdk::Texture::from_file(&gio::File::for_path(path)).unwrap());
+            let Some(path) = &path else {
+                return;
+            };
+
+            // Recordings in formats GDK can't load (i.e. WebM) end up here
+            match gdk::Texture::from_file(&gio::File::for_path(path)) {
+                Ok(texture) => clipboard().set_texture(&texture),
+                Err(why) => tracing::warn!("Failed to load '{path}' as an image: {why}"),
             }
         }
     });
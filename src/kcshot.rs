@@ -2,11 +2,16 @@ use std::path::PathBuf;
 
 use diesel::SqliteConnection;
 use gtk4::{gio, glib, prelude::*, subclass::prelude::*};
-use kcshot_data::settings::Settings;
+use kcshot_data::{geometry::Rectangle, settings::Settings};
+use kcshot_screenshot::CaptureMetadata;
 
 use crate::{
-    appwindow,
-    history::{HistoryModel, ModelNotifier},
+    appwindow, db,
+    editor::EditorWindow,
+    history::{HistoryModel, ModelNotifier, RowData},
+    recording::Recording,
+    scrolling_capture::{self, ScrollingCapture},
+    systray,
 };
 
 glib::wrapper! {
@@ -73,6 +78,119 @@ impl KCShot {
             .as_ref()
             .map(tokio::runtime::Runtime::handle)
     }
+
+    /// Starts recording `region` (in the coordinate space of the virtual screen) in the background,
+    /// only one recording can be in progress at a time.
+    pub fn start_recording(&self, region: Rectangle) {
+        let mut recording = self.imp().recording.borrow_mut();
+        if recording.is_some() {
+            tracing::warn!("Tried to start a recording while another one is in progress");
+            return;
+        }
+
+        match Recording::start(region) {
+            Ok(started) => {
+                *recording = Some(started);
+                systray::set_recording(true);
+            }
+            Err(why) => tracing::error!("Failed to start recording: {why}"),
+        }
+    }
+
+    /// Stops the recording that is in progress, if any, and adds it to the history once the file
+    /// has been written
+    pub fn stop_recording(&self) {
+        let Some(recording) = self.imp().recording.take() else {
+            return;
+        };
+        systray::set_recording(false);
+
+        let app = self.clone();
+        glib::MainContext::default().spawn_local(async move {
+            let path = recording.path().to_owned();
+            let time = recording.time().to_owned();
+
+            if let Err(why) = recording.stop().await {
+                tracing::error!("Failed to save recording to '{path}': {why}");
+                return;
+            }
+
+            let res = app.with_conn(|conn| {
+                db::add_screenshot_to_history(conn, Some(path.clone()), time.clone(), None, None)
+            });
+            if let Err(why) = res {
+                tracing::error!("Failed to add recording to history: {why}");
+                return;
+            }
+
+            if let Err(why) = app
+                .model_notifier()
+                .send(RowData::new_from_components(Some(path), time, None))
+                .await
+            {
+                tracing::error!(
+                    "Failed to notify the history model that a new item was added: {why}"
+                );
+            }
+        });
+    }
+
+    /// Starts a scrolling capture of `region` (in the coordinate space of the virtual screen) in the
+    /// background, the stitched image is opened in the editor once it's over.
+    pub fn start_scrolling_capture(&self, region: Rectangle) {
+        if self.imp().scrolling_capture.borrow().is_some() {
+            tracing::warn!("Tried to start a scrolling capture while another one is in progress");
+            return;
+        }
+
+        let (capture, finished) = match ScrollingCapture::start(region) {
+            Ok(started) => started,
+            Err(why) => {
+                tracing::error!("Failed to start scrolling capture: {why}");
+                return;
+            }
+        };
+        self.imp().scrolling_capture.replace(Some(capture));
+        systray::set_scrolling_capture(true);
+
+        let app = self.clone();
+        glib::MainContext::default().spawn_local(async move {
+            let res = finished.await;
+
+            app.imp().scrolling_capture.take();
+            systray::set_scrolling_capture(false);
+
+            let frame = match res {
+                Ok(Ok(frame)) => frame,
+                Ok(Err(why)) => {
+                    tracing::error!("Failed to take scrolling capture: {why}");
+                    return;
+                }
+                Err(_) => {
+                    tracing::error!("The scrolling capture thread went away");
+                    return;
+                }
+            };
+
+            match scrolling_capture::into_surface(frame) {
+                Ok(image) => EditorWindow::show_with_image(
+                    app.upcast_ref(),
+                    image,
+                    CaptureMetadata::new(region),
+                    region.h as i32,
+                ),
+                Err(why) => tracing::error!("Failed to show scrolling capture: {why}"),
+            }
+        });
+    }
+
+    /// Stops the scrolling capture that is in progress, if any, the frames captured so far still end
+    /// up in the editor
+    pub fn stop_scrolling_capture(&self) {
+        if let Some(capture) = self.imp().scrolling_capture.borrow().as_ref() {
+            capture.stop();
+        }
+    }
 }
 
 mod underlying {
@@ -89,17 +207,27 @@ mod underlying {
         prelude::*,
         subclass::prelude::*,
     };
+    use kcshot_data::geometry::Rectangle;
+    use kcshot_screenshot::{CaptureMetadata, CaptureTarget, MonitorSelector};
 
-    use super::Settings;
+    use super::{Recording, ScrollingCapture, Settings};
     use crate::{
         appwindow, db,
         editor::EditorWindow,
         history::{HistoryModel, ModelNotifier},
+        postcapture::run_postcapture_actions,
         systray,
     };
 
     const NO_WINDOW_OPTION: &str = "no-window";
     const SCREENSHOT_OPTION: &str = "screenshot";
+    const MONITOR_OPTION: &str = "monitor";
+    const LIST_MONITORS_OPTION: &str = "list-monitors";
+    const DELAY_OPTION: &str = "delay";
+    const WINDOW_OPTION: &str = "window";
+    const ACTIVE_WINDOW_OPTION: &str = "active-window";
+    const LAST_REGION_OPTION: &str = "last-region";
+    const PRESET_OPTION: &str = "preset";
 
     pub struct KCShot {
         pub(super) show_main_window: Cell<bool>,
@@ -110,6 +238,8 @@ mod underlying {
         pub(super) systray_initialised: Cell<bool>,
         pub(super) window: OnceCell<appwindow::AppWindow>,
         pub(super) tokio_rt: Option<tokio::runtime::Runtime>,
+        pub(super) recording: RefCell<Option<Recording>>,
+        pub(super) scrolling_capture: RefCell<Option<ScrollingCapture>>,
     }
 
     impl KCShot {
@@ -141,6 +271,81 @@ mod underlying {
                 &format!("Take a screenshot (mutually exclusive with --{NO_WINDOW_OPTION})"),
                 None,
             );
+
+            app.add_main_option(
+                MONITOR_OPTION,
+                glib::Char::from(b'm'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::String,
+                &format!(
+                    "Take a screenshot of the given monitor, picked by name (e.g. DP-2) or by its index in --{LIST_MONITORS_OPTION} (mutually exclusive with --{NO_WINDOW_OPTION})"
+                ),
+                Some("MONITOR"),
+            );
+
+            app.add_main_option(
+                DELAY_OPTION,
+                glib::Char::from(b'd'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::Int,
+                &format!(
+                    "Take a screenshot after waiting for the given number of seconds (mutually exclusive with --{NO_WINDOW_OPTION})"
+                ),
+                Some("SECONDS"),
+            );
+
+            app.add_main_option(
+                WINDOW_OPTION,
+                glib::Char::from(b'w'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::String,
+                &format!(
+                    "Capture the window with the given X11 ID (e.g. 0x3a00007) and save it without opening the editor (mutually exclusive with --{NO_WINDOW_OPTION})"
+                ),
+                Some("WINDOW"),
+            );
+
+            app.add_main_option(
+                ACTIVE_WINDOW_OPTION,
+                glib::Char::from(b'a'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::None,
+                &format!(
+                    "Capture the window that has the input focus and save it without opening the editor (mutually exclusive with --{NO_WINDOW_OPTION})"
+                ),
+                None,
+            );
+
+            app.add_main_option(
+                LAST_REGION_OPTION,
+                glib::Char::from(b'r'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::None,
+                &format!(
+                    "Capture the region the last screenshot was cropped to and save it without opening the editor (mutually exclusive with --{NO_WINDOW_OPTION})"
+                ),
+                None,
+            );
+
+            app.add_main_option(
+                PRESET_OPTION,
+                glib::Char::from(b'p'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::String,
+                &format!(
+                    "Capture the region preset with the given name and save it without opening the editor (mutually exclusive with --{NO_WINDOW_OPTION})"
+                ),
+                Some("PRESET"),
+            );
+
+            app.add_main_option(
+                LIST_MONITORS_OPTION,
+                glib::Char::from(b'l'),
+                glib::OptionFlags::NONE,
+                glib::OptionArg::None,
+                "List the monitors that can be passed to --monitor",
+                None,
+            );
         }
 
         fn setup_actions(&self) {
@@ -153,12 +358,205 @@ mod underlying {
                     let editing_starts_with_cropping =
                         Settings::open().editing_starts_with_cropping();
 
-                    EditorWindow::show(app.upcast_ref(), editing_starts_with_cropping);
+                    EditorWindow::show(
+                        app.upcast_ref(),
+                        editing_starts_with_cropping,
+                        &CaptureTarget::from_settings(),
+                    );
                 })
                 .build();
 
-            self.obj()
-                .add_action_entries([show_main_window, new_editor_window]);
+            let screenshot_monitor = gio::ActionEntry::builder("screenshot-monitor")
+                .parameter_type(Some(&String::static_variant_type()))
+                .activate(|app: &super::KCShot, _, monitor| {
+                    let Some(monitor) = monitor.and_then(String::from_variant) else {
+                        tracing::error!("screenshot-monitor was activated without a monitor");
+                        return;
+                    };
+                    let Ok(selector) = monitor.parse::<MonitorSelector>();
+
+                    let editing_starts_with_cropping =
+                        Settings::open().editing_starts_with_cropping();
+
+                    EditorWindow::show(
+                        app.upcast_ref(),
+                        editing_starts_with_cropping,
+                        &CaptureTarget::Monitor(selector),
+                    );
+                })
+                .build();
+
+            // The parameter is the monitor to capture (or an empty string to let the settings decide)
+            // and the number of seconds to wait before capturing
+            let delayed_screenshot = gio::ActionEntry::builder("delayed-screenshot")
+                .parameter_type(Some(&<(String, u32)>::static_variant_type()))
+                .activate(|app: &super::KCShot, _, parameter| {
+                    let Some((monitor, delay)) = parameter.and_then(<(String, u32)>::from_variant)
+                    else {
+                        tracing::error!(
+                            "delayed-screenshot was activated without a monitor and a delay"
+                        );
+                        return;
+                    };
+
+                    let capture_target = if monitor.is_empty() {
+                        CaptureTarget::from_settings()
+                    } else {
+                        let Ok(selector) = monitor.parse::<MonitorSelector>();
+                        CaptureTarget::Monitor(selector)
+                    };
+
+                    let editing_starts_with_cropping =
+                        Settings::open().editing_starts_with_cropping();
+
+                    EditorWindow::show_after_delay(
+                        app.upcast_ref(),
+                        editing_starts_with_cropping,
+                        capture_target,
+                        delay,
+                    );
+                })
+                .build();
+
+            let capture_window = gio::ActionEntry::builder("capture-window")
+                .parameter_type(Some(&u32::static_variant_type()))
+                .activate(|app: &super::KCShot, _, window| {
+                    let Some(window) = window.and_then(u32::from_variant) else {
+                        tracing::error!("capture-window was activated without a window");
+                        return;
+                    };
+
+                    let surface = match kcshot_screenshot::capture_window(window, true) {
+                        Ok(surface) => surface,
+                        Err(why) => {
+                            tracing::error!("Failed to capture window {window:#x}: {why}");
+                            return;
+                        }
+                    };
+
+                    app.with_conn(|conn| {
+                        let Some(mut pixbuf) = gdk::pixbuf_get_from_surface(
+                            &surface,
+                            0,
+                            0,
+                            surface.width(),
+                            surface.height(),
+                        ) else {
+                            tracing::error!(
+                                "Failed to create a pixbuf from the capture of window {window:#x}"
+                            );
+                            return;
+                        };
+
+                        // Windows captured on their own aren't placed anywhere on the screen
+                        let metadata = CaptureMetadata::new(Rectangle {
+                            x: 0.0,
+                            y: 0.0,
+                            w: surface.width() as f64,
+                            h: surface.height() as f64,
+                        });
+                        run_postcapture_actions(
+                            &app.model_notifier(),
+                            conn,
+                            &mut pixbuf,
+                            &metadata,
+                        );
+                    });
+                })
+                .build();
+
+            let capture_active_window = gio::ActionEntry::builder("capture-active-window")
+                .activate(|app: &super::KCShot, _, _| {
+                    let with_decorations = Settings::open().active_window_includes_decorations();
+
+                    match kcshot_screenshot::capture_active_window(app.tokio_rt(), with_decorations)
+                    {
+                        Ok(screenshot) => {
+                            save_without_editor(app, &screenshot, "the active window")
+                        }
+                        Err(why) => tracing::error!("Failed to capture the active window: {why}"),
+                    }
+                })
+                .build();
+
+            let capture_last_region = gio::ActionEntry::builder("capture-last-region")
+                .activate(|app: &super::KCShot, _, _| {
+                    let region = Settings::open().last_region();
+                    if region.area() < 1.0 {
+                        tracing::error!(
+                            "Can't capture the last region, no screenshot was cropped yet"
+                        );
+                        return;
+                    }
+
+                    match kcshot_screenshot::capture_region(app.tokio_rt(), region) {
+                        Ok(screenshot) => save_without_editor(app, &screenshot, "the last region"),
+                        Err(why) => tracing::error!("Failed to capture the last region: {why}"),
+                    }
+                })
+                .build();
+
+            let capture_region_preset = gio::ActionEntry::builder("capture-region-preset")
+                .parameter_type(Some(&String::static_variant_type()))
+                .activate(|app: &super::KCShot, _, preset| {
+                    let Some(preset) = preset.and_then(String::from_variant) else {
+                        tracing::error!("capture-region-preset was activated without a preset");
+                        return;
+                    };
+
+                    let Some(&region) = Settings::open().region_presets().get(&preset) else {
+                        tracing::error!("There is no region preset named '{preset}'");
+                        return;
+                    };
+
+                    let what = format!("the '{preset}' region preset");
+                    match kcshot_screenshot::capture_region(app.tokio_rt(), region) {
+                        Ok(screenshot) => save_without_editor(app, &screenshot, &what),
+                        Err(why) => tracing::error!("Failed to capture {what}: {why}"),
+                    }
+                })
+                .build();
+
+            let record_region = gio::ActionEntry::builder("record-region")
+                .activate(|app: &super::KCShot, _, _| {
+                    EditorWindow::show_for_recording(
+                        app.upcast_ref(),
+                        &CaptureTarget::from_settings(),
+                    );
+                })
+                .build();
+
+            let stop_recording = gio::ActionEntry::builder("stop-recording")
+                .activate(|app: &super::KCShot, _, _| app.stop_recording())
+                .build();
+
+            let scrolling_capture = gio::ActionEntry::builder("scrolling-capture")
+                .activate(|app: &super::KCShot, _, _| {
+                    EditorWindow::show_for_scrolling_capture(
+                        app.upcast_ref(),
+                        &CaptureTarget::from_settings(),
+                    );
+                })
+                .build();
+
+            let stop_scrolling_capture = gio::ActionEntry::builder("stop-scrolling-capture")
+                .activate(|app: &super::KCShot, _, _| app.stop_scrolling_capture())
+                .build();
+
+            self.obj().add_action_entries([
+                show_main_window,
+                new_editor_window,
+                screenshot_monitor,
+                delayed_screenshot,
+                capture_window,
+                capture_active_window,
+                capture_last_region,
+                capture_region_preset,
+                record_region,
+                stop_recording,
+                scrolling_capture,
+                stop_scrolling_capture,
+            ]);
         }
     }
 
@@ -172,6 +570,8 @@ mod underlying {
                 model_notifier: Default::default(),
                 systray_initialised: Cell::new(false),
                 window: Default::default(),
+                recording: Default::default(),
+                scrolling_capture: Default::default(),
                 tokio_rt: kcshot_screenshot::will_make_use_of_desktop_portals().then(|| {
                     tokio::runtime::Builder::new_multi_thread()
                         .enable_all()
@@ -195,6 +595,11 @@ mod underlying {
                 .field("systray_initialised", &self.systray_initialised)
                 .field("window", &self.window)
                 .field("tokio_rt", &self.tokio_rt)
+                .field("is_recording", &self.recording.borrow().is_some())
+                .field(
+                    "is_scrolling_capture_running",
+                    &self.scrolling_capture.borrow().is_some(),
+                )
                 .finish()
         }
     }
@@ -265,7 +670,11 @@ mod underlying {
 
                 let editing_starts_with_cropping = Settings::open().editing_starts_with_cropping();
 
-                EditorWindow::show(self.obj().upcast_ref(), editing_starts_with_cropping);
+                EditorWindow::show(
+                    self.obj().upcast_ref(),
+                    editing_starts_with_cropping,
+                    &CaptureTarget::from_settings(),
+                );
             } else if show_main_window {
                 self.show_main_window.set(false);
 
@@ -279,22 +688,100 @@ mod underlying {
                 return ControlFlow::Break(glib::ExitCode::FAILURE);
             }
 
+            if options.contains(LIST_MONITORS_OPTION) {
+                return ControlFlow::Break(print_monitors());
+            }
+
+            let monitor = options.lookup::<String>(MONITOR_OPTION).ok().flatten();
+            let window = match options.lookup::<String>(WINDOW_OPTION).ok().flatten() {
+                Some(window) => match parse_window_id(&window) {
+                    Some(window) => Some(window),
+                    None => {
+                        let prog_name = glib::prgname().unwrap_or_else(|| "kcshot".into());
+
+                        eprintln!("{prog_name}: '{window}' is not a valid window ID");
+
+                        return ControlFlow::Break(glib::ExitCode::FAILURE);
+                    }
+                },
+                None => None,
+            };
+            let delay = options.lookup::<i32>(DELAY_OPTION).ok().flatten();
+            let preset = options.lookup::<String>(PRESET_OPTION).ok().flatten();
+
+            if delay.is_some_and(|delay| delay < 0) {
+                let prog_name = glib::prgname().unwrap_or_else(|| "kcshot".into());
+
+                eprintln!("{prog_name}: --{DELAY_OPTION} can't be negative");
+
+                return ControlFlow::Break(glib::ExitCode::FAILURE);
+            }
+
+            if let Some(monitor) = monitor.as_deref().filter(|monitor| !monitor.is_empty())
+                && let Err(exit_code) = check_monitor_exists(monitor)
+            {
+                return ControlFlow::Break(exit_code);
+            }
+
+            if let Some(preset) = preset.as_deref()
+                && let Err(exit_code) = check_preset_exists(preset)
+            {
+                return ControlFlow::Break(exit_code);
+            }
+
             match (
                 options.contains(NO_WINDOW_OPTION),
-                options.contains(SCREENSHOT_OPTION),
+                options.contains(SCREENSHOT_OPTION)
+                    || monitor.is_some()
+                    || delay.is_some()
+                    || window.is_some()
+                    || options.contains(ACTIVE_WINDOW_OPTION)
+                    || options.contains(LAST_REGION_OPTION)
+                    || preset.is_some(),
             ) {
                 (true, true) => {
                     let prog_name = glib::prgname().unwrap_or_else(|| "kcshot".into());
 
                     eprintln!(
-                        "{prog_name}: --{NO_WINDOW_OPTION} and --{SCREENSHOT_OPTION} mutually exclusive"
+                        "{prog_name}: --{NO_WINDOW_OPTION} and --{SCREENSHOT_OPTION}/--{MONITOR_OPTION}/--{DELAY_OPTION}/--{WINDOW_OPTION}/--{ACTIVE_WINDOW_OPTION}/--{LAST_REGION_OPTION}/--{PRESET_OPTION} mutually exclusive"
                     );
 
                     ControlFlow::Break(glib::ExitCode::FAILURE)
                 }
                 (true, false) => self.parent_handle_local_options(options),
                 (false, true) => {
-                    self.obj().activate_action("new-editor-window", None);
+                    if let Some(window) = window {
+                        self.obj()
+                            .activate_action("capture-window", Some(&window.to_variant()));
+                        return ControlFlow::Break(glib::ExitCode::SUCCESS);
+                    }
+
+                    if options.contains(ACTIVE_WINDOW_OPTION) {
+                        self.obj().activate_action("capture-active-window", None);
+                        return ControlFlow::Break(glib::ExitCode::SUCCESS);
+                    }
+
+                    if options.contains(LAST_REGION_OPTION) {
+                        self.obj().activate_action("capture-last-region", None);
+                        return ControlFlow::Break(glib::ExitCode::SUCCESS);
+                    }
+
+                    if let Some(preset) = preset {
+                        self.obj()
+                            .activate_action("capture-region-preset", Some(&preset.to_variant()));
+                        return ControlFlow::Break(glib::ExitCode::SUCCESS);
+                    }
+
+                    match (monitor, delay) {
+                        (monitor, Some(delay)) => self.obj().activate_action(
+                            "delayed-screenshot",
+                            Some(&(monitor.unwrap_or_default(), delay as u32).to_variant()),
+                        ),
+                        (Some(monitor), None) => self
+                            .obj()
+                            .activate_action("screenshot-monitor", Some(&monitor.to_variant())),
+                        (None, None) => self.obj().activate_action("new-editor-window", None),
+                    }
 
                     ControlFlow::Break(glib::ExitCode::SUCCESS)
                 }
@@ -309,6 +796,7 @@ mod underlying {
             self.parent_startup();
 
             self.setup_actions();
+            Recording::probe_encoders();
 
             // This hold has no matching release intentionally so that the application keeps running
             // in the background even when no top-level windows are spawned. (This is the case when
@@ -351,4 +839,91 @@ mod underlying {
     }
 
     impl GtkApplicationImpl for KCShot {}
+
+    fn print_monitors() -> glib::ExitCode {
+        let monitors = match kcshot_screenshot::monitors() {
+            Ok(monitors) => monitors,
+            Err(why) => {
+                eprintln!("Failed to retrieve monitors: {why}");
+                return glib::ExitCode::FAILURE;
+            }
+        };
+
+        for (idx, monitor) in monitors.iter().enumerate() {
+            let Rectangle { x, y, w, h } = monitor.rect;
+            let primary = if monitor.is_primary { " (primary)" } else { "" };
+
+            println!("{idx}: {} {w}x{h}+{x}+{y}{primary}", monitor.name);
+        }
+
+        glib::ExitCode::SUCCESS
+    }
+
+    /// Makes sure that `monitor`, as passed to --monitor, picks one of the connected monitors, so
+    /// that we can fail before trying to capture it
+    fn check_monitor_exists(monitor: &str) -> Result<(), glib::ExitCode> {
+        let prog_name = glib::prgname().unwrap_or_else(|| "kcshot".into());
+
+        let monitors = kcshot_screenshot::monitors().map_err(|why| {
+            eprintln!("{prog_name}: failed to retrieve monitors: {why}");
+            glib::ExitCode::FAILURE
+        })?;
+
+        let Ok(selector) = monitor.parse::<MonitorSelector>();
+        if selector.select(&monitors).is_none() {
+            eprintln!(
+                "{prog_name}: there is no monitor '{monitor}', see --{LIST_MONITORS_OPTION} for the connected ones"
+            );
+            return Err(glib::ExitCode::FAILURE);
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure that `preset`, as passed to --preset, is the name of one of the user's region
+    /// presets, so that we can fail before trying to capture it
+    fn check_preset_exists(preset: &str) -> Result<(), glib::ExitCode> {
+        if Settings::open().region_presets().contains_key(preset) {
+            return Ok(());
+        }
+
+        let prog_name = glib::prgname().unwrap_or_else(|| "kcshot".into());
+        eprintln!("{prog_name}: there is no region preset named '{preset}'");
+
+        Err(glib::ExitCode::FAILURE)
+    }
+
+    /// Runs the post-capture actions on a screenshot that was taken without showing the editor,
+    /// `what` describes what was captured for the logs
+    fn save_without_editor(
+        app: &super::KCShot,
+        screenshot: &kcshot_screenshot::Screenshot,
+        what: &str,
+    ) {
+        app.with_conn(|conn| {
+            let image = &screenshot.image;
+            let Some(mut pixbuf) =
+                gdk::pixbuf_get_from_surface(image, 0, 0, image.width(), image.height())
+            else {
+                tracing::error!("Failed to create a pixbuf from the capture of {what}");
+                return;
+            };
+
+            run_postcapture_actions(
+                &app.model_notifier(),
+                conn,
+                &mut pixbuf,
+                &screenshot.metadata,
+            );
+        });
+    }
+
+    /// Parses window IDs the way tools like xwininfo print them, i.e. in hexadecimal with a leading
+    /// `0x`, decimal IDs are accepted too
+    fn parse_window_id(window: &str) -> Option<u32> {
+        match window.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16).ok(),
+            None => window.parse().ok(),
+        }
+    }
 }
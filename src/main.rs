@@ -18,6 +18,8 @@ mod history;
 mod kcshot;
 mod logging;
 mod postcapture;
+mod recording;
+mod scrolling_capture;
 mod settings_window;
 mod systray;
 
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, fmt::Write as _};
+use std::{
+    collections::HashMap,
+    fmt::Write as _,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 use diesel::SqliteConnection;
 use gtk4::{
@@ -7,6 +11,7 @@ use gtk4::{
     glib,
 };
 use kcshot_data::settings::Settings;
+use kcshot_screenshot::CaptureMetadata;
 
 use crate::{
     db,
@@ -28,12 +33,14 @@ pub trait PostCaptureAction {
     /// Short description of the post capture action.
     fn description(&self) -> String;
 
-    /// Gets called when executing the post capture action.
+    /// Gets called when executing the post capture action, `metadata` describes the capture the
+    /// pixbuf was made from.
     fn handle(
         &self,
         model_notifier: &ModelNotifier,
         conn: &mut SqliteConnection,
         pixbuf: &mut Pixbuf,
+        metadata: &CaptureMetadata,
     );
 }
 
@@ -58,12 +65,9 @@ impl PostCaptureAction for SaveToDisk {
         model_notifier: &ModelNotifier,
         conn: &mut SqliteConnection,
         pixbuf: &mut Pixbuf,
+        metadata: &CaptureMetadata,
     ) {
-        let now = glib::DateTime::now_local()
-            .unwrap()
-            .format_iso8601()
-            .unwrap()
-            .to_string();
+        let time = format_time(metadata.time);
 
         let settings = Settings::open();
         let mut path = settings.saved_screenshots_path();
@@ -75,19 +79,31 @@ impl PostCaptureAction for SaveToDisk {
             tracing::error!("Failed to create directory='{path}': {why}");
         }
 
-        write!(path, "screenshot_{now}.png").expect("Writing to a string shouldn't fail");
+        match metadata.application() {
+            Some(application) => {
+                let application = application.replace('/', "_");
+                write!(path, "screenshot_{application}_{time}.png")
+            }
+            None => write!(path, "screenshot_{time}.png"),
+        }
+        .expect("Writing to a string shouldn't fail");
 
         if let Err(why) = pixbuf.savev(&path, "png", &[]) {
             tracing::error!("Failed to save screenshot to file: {why}");
         }
 
-        if let Err(why) = db::add_screenshot_to_history(conn, Some(path.clone()), now.clone(), None)
-        {
+        if let Err(why) = db::add_screenshot_to_history(
+            conn,
+            Some(path.clone()),
+            time.clone(),
+            None,
+            Some(metadata),
+        ) {
             tracing::error!("Failed to add screenshot to history: {why}");
             return;
         }
         if let Err(why) =
-            model_notifier.blocking_send(RowData::new_from_components(Some(path), now, None))
+            model_notifier.blocking_send(RowData::new_from_components(Some(path), time, None))
         {
             tracing::error!("Failed to notify the history model that a new item was added: {why}");
         }
@@ -115,6 +131,7 @@ impl PostCaptureAction for CopyToClipboard {
         _model_notifier: &ModelNotifier,
         _conn: &mut SqliteConnection,
         pixbuf: &mut Pixbuf,
+        _metadata: &CaptureMetadata,
     ) {
         let Some(display) = gdk::Display::default() else {
             tracing::error!("Failed to fetch gdk::Display, bailing...");
@@ -131,12 +148,25 @@ pub fn run_postcapture_actions(
     model_notifier: &ModelNotifier,
     conn: &mut SqliteConnection,
     pixbuf: &mut Pixbuf,
+    metadata: &CaptureMetadata,
 ) {
     for action in get_actions_from_settings() {
-        action.handle(model_notifier, conn, pixbuf);
+        action.handle(model_notifier, conn, pixbuf, metadata);
     }
 }
 
+/// Formats `time` as an ISO 8601 timestamp in the local timezone, like the timestamps used for the
+/// names of the saved files and in the history
+fn format_time(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+
+    glib::DateTime::from_unix_local(since_epoch.as_secs() as i64)
+        .and_then(|time| time.add_seconds(since_epoch.subsec_micros() as f64 / 1_000_000.0))
+        .and_then(|time| time.format_iso8601())
+        .map(|time| time.to_string())
+        .unwrap()
+}
+
 fn get_actions_from_settings() -> Vec<&'static dyn PostCaptureAction> {
     let action_names = Settings::open().post_capture_actions();
 
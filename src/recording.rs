@@ -0,0 +1,449 @@
+use std::{
+    fmt::Write as _,
+    fs::{self, File, OpenOptions},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    sync::{
+        Arc, OnceLock,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread::{self, Builder as ThreadBuilder},
+    time::{Duration, Instant},
+};
+
+use gtk4::glib;
+use kcshot_data::{geometry::Rectangle, settings::Settings};
+use kcshot_screenshot::{Frame, Recorder};
+use tokio::sync::oneshot;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to capture a frame: {0}")]
+    Capture(#[from] kcshot_screenshot::Error),
+    #[error("Encountered an I/O error: {0}")]
+    Io(#[from] io::Error),
+    #[error("Failed to encode GIF: {0}")]
+    Gif(#[from] gif::EncodingError),
+    #[error("Failed to encode APNG: {0}")]
+    Png(#[from] png::EncodingError),
+    #[error("The region to record is empty")]
+    EmptyRegion,
+    #[error("ffmpeg exited with {0}")]
+    Ffmpeg(ExitStatus),
+    #[error("The recording thread went away before finishing the recording")]
+    ThreadWentAway,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// How many captured frames may wait for the encoding thread before we start dropping new ones
+const MAX_QUEUED_FRAMES: usize = 8;
+
+/// Whether ffmpeg can encode WebM, see [`can_encode_webm`]
+static CAN_ENCODE_WEBM: OnceLock<bool> = OnceLock::new();
+
+/// The formats recordings can be saved in, as stored in the `recording-format` setting
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Gif,
+    Apng,
+    WebM,
+}
+
+impl Format {
+    fn from_settings() -> Self {
+        match Settings::open().recording_format().as_str() {
+            "gif" => Self::Gif,
+            "apng" => Self::Apng,
+            "webm" if can_encode_webm() => Self::WebM,
+            "webm" => {
+                tracing::info!(
+                    "ffmpeg with libvpx-vp9 support was not found, recording to a GIF instead"
+                );
+                Self::Gif
+            }
+            format => {
+                tracing::warn!("Unknown recording format '{format}', recording to a GIF instead");
+                Self::Gif
+            }
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            // APNGs are backwards compatible with PNGs, so viewers that don't support animations can
+            // still show the first frame
+            Self::Apng => "png",
+            Self::WebM => "webm",
+        }
+    }
+}
+
+/// A recording of a region of the screen that is running in the background.
+///
+/// Frames are captured on a "recording thread" and handed to an "encoding thread", so that slow
+/// encoders don't make us miss frames.
+pub struct Recording {
+    stop: Arc<AtomicBool>,
+    finished: oneshot::Receiver<Result<()>>,
+    path: String,
+    time: String,
+}
+
+impl Recording {
+    /// Starts recording `region` (in the coordinate space of the virtual screen) into a file inside
+    /// the screenshot folder, using the format and frame rate from the settings.
+    pub fn start(region: Rectangle) -> Result<Self> {
+        let settings = Settings::open();
+        let fps = settings.recording_fps().clamp(1, 60);
+        let format = Format::from_settings();
+
+        let time = glib::DateTime::now_local()
+            .unwrap()
+            .format_iso8601()
+            .unwrap()
+            .to_string();
+
+        let mut path = settings.saved_screenshots_path();
+        if !path.ends_with('/') {
+            path.push('/');
+        }
+        std::fs::create_dir_all(&path)?;
+        write!(path, "recording_{time}.{}", format.extension())
+            .expect("Writing to a string shouldn't fail");
+
+        let region = region.normalised();
+        if region.w < 1.0 || region.h < 1.0 {
+            return Err(Error::EmptyRegion);
+        }
+
+        let mut recorder = Recorder::new(region)?;
+        let encoder = FrameEncoder::new(format, &path, region.w as u32, region.h as u32, fps)?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (finished_tx, finished) = oneshot::channel();
+
+        ThreadBuilder::new()
+            .name("recording thread".into())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    let res = record(&mut recorder, encoder, fps, &stop);
+                    // If nobody is waiting for the recording to finish anymore, there's nobody to
+                    // tell about it either
+                    let _ = finished_tx.send(res);
+                }
+            })?;
+
+        Ok(Self {
+            stop,
+            finished,
+            path,
+            time,
+        })
+    }
+
+    /// The path of the file the recording is being saved to
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// When the recording was started, formatted as an ISO 8601 timestamp
+    pub fn time(&self) -> &str {
+        &self.time
+    }
+
+    /// Stops capturing new frames and waits for the encoder to finish writing the file
+    pub async fn stop(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        self.finished.await.map_err(|_| Error::ThreadWentAway)?
+    }
+
+    /// Checks in the background which formats recordings can be saved in, so that starting a
+    /// recording doesn't have to wait for ffmpeg
+    pub fn probe_encoders() {
+        let res = ThreadBuilder::new()
+            .name("encoder probing thread".into())
+            .spawn(can_encode_webm);
+
+        if let Err(why) = res {
+            tracing::warn!("Failed to spawn thread for probing encoders: {why}");
+        }
+    }
+}
+
+/// Captures a frame every `1/fps` seconds until `stop` is set
+fn record(
+    recorder: &mut Recorder,
+    encoder: FrameEncoder,
+    fps: u32,
+    stop: &AtomicBool,
+) -> Result<()> {
+    // Frames are big, so if the encoder can't keep up we'd rather drop some than run out of memory.
+    // Each frame is sent along with the number of the interval it was captured in.
+    let (frames_tx, frames_rx) = mpsc::sync_channel::<(Frame, u64)>(MAX_QUEUED_FRAMES);
+
+    let encoding_thread = ThreadBuilder::new()
+        .name("encoding thread".into())
+        .spawn(move || {
+            let mut encoder = encoder;
+            // We only know how long a frame is shown for once the next one arrives, as the frames
+            // in between may have been dropped
+            let mut previous: Option<(Frame, u64)> = None;
+            for (frame, idx) in frames_rx {
+                if let Some((previous, previous_idx)) = previous.replace((frame, idx)) {
+                    encoder.add_frame(previous, (idx - previous_idx) as u32)?;
+                }
+            }
+            if let Some((last, _)) = previous {
+                encoder.add_frame(last, 1)?;
+            }
+            encoder.finish()
+        })?;
+
+    let interval = Duration::from_secs(1) / fps;
+    let mut next_frame_at = Instant::now();
+    let mut capture_result = Ok(());
+    let mut frame_idx = 0;
+    let mut dropped_frames = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+        let frame = match recorder.capture_frame() {
+            Ok(frame) => frame,
+            Err(why) => {
+                capture_result = Err(why.into());
+                break;
+            }
+        };
+
+        match frames_tx.try_send((frame, frame_idx)) {
+            Ok(()) => {}
+            // The previous frame gets shown for longer instead
+            Err(mpsc::TrySendError::Full(_)) => dropped_frames += 1,
+            // The encoding thread only goes away early if it failed, in which case we'll get the
+            // error when joining it
+            Err(mpsc::TrySendError::Disconnected(_)) => break,
+        }
+
+        frame_idx += 1;
+        next_frame_at += interval;
+        thread::sleep(next_frame_at.saturating_duration_since(Instant::now()));
+    }
+
+    drop(frames_tx);
+    if dropped_frames > 0 {
+        tracing::warn!("The encoder couldn't keep up, dropped {dropped_frames} frames");
+    }
+
+    let encoding_result = encoding_thread.join().map_err(|_| Error::ThreadWentAway)?;
+
+    capture_result.and(encoding_result)
+}
+
+enum FrameEncoder {
+    Gif {
+        encoder: gif::Encoder<BufWriter<File>>,
+        fps: u32,
+    },
+    /// The number of frames has to be written at the start of APNG files, so we can only encode them
+    /// once the recording is over. Until then, they are stored in a temporary file.
+    Apng {
+        file: BufWriter<File>,
+        frames: BufWriter<File>,
+        /// For how many frame intervals each of the stored frames is shown
+        frame_intervals: Vec<u32>,
+        /// The size of the first frame, all the other ones must have the same size
+        frame_size: Option<(u32, u32)>,
+        fps: u32,
+    },
+    WebM {
+        ffmpeg: Child,
+        stdin: BufWriter<ChildStdin>,
+    },
+}
+
+impl FrameEncoder {
+    fn new(format: Format, path: &str, width: u32, height: u32, fps: u32) -> Result<Self> {
+        Ok(match format {
+            Format::Gif => {
+                let file = BufWriter::new(File::create(path)?);
+                let mut encoder = gif::Encoder::new(file, width as u16, height as u16, &[])?;
+                encoder.set_repeat(gif::Repeat::Infinite)?;
+
+                Self::Gif { encoder, fps }
+            }
+            Format::Apng => {
+                let frames_path = format!("{path}.frames");
+                let frames = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(&frames_path)?;
+                // We keep using the file through its descriptor, this way it goes away on its own
+                // once we're done with it, even if we never get to finish the recording
+                fs::remove_file(&frames_path)?;
+
+                Self::Apng {
+                    file: BufWriter::new(File::create(path)?),
+                    frames: BufWriter::new(frames),
+                    frame_intervals: vec![],
+                    frame_size: None,
+                    fps,
+                }
+            }
+            Format::WebM => {
+                let mut ffmpeg = Command::new("ffmpeg")
+                    .args(["-hide_banner", "-loglevel", "error", "-y"])
+                    // Frames are piped in exactly as they are stored by cairo
+                    .args(["-f", "rawvideo", "-pixel_format", "bgr0"])
+                    .args(["-video_size", &format!("{width}x{height}")])
+                    .args(["-framerate", &fps.to_string()])
+                    .args(["-i", "-"])
+                    // yuv420p can only represent even sizes
+                    .args(["-vf", "pad=ceil(iw/2)*2:ceil(ih/2)*2"])
+                    .args(["-c:v", "libvpx-vp9", "-pix_fmt", "yuv420p"])
+                    .arg(path)
+                    .stdin(Stdio::piped())
+                    .spawn()?;
+                let stdin = BufWriter::new(ffmpeg.stdin.take().expect("stdin is piped"));
+
+                Self::WebM { ffmpeg, stdin }
+            }
+        })
+    }
+
+    /// Adds `frame`, which is shown for `intervals` times as long as a frame usually is, this is
+    /// more than one if the frames after it had to be dropped
+    fn add_frame(&mut self, frame: Frame, intervals: u32) -> Result<()> {
+        match self {
+            Self::Gif { encoder, fps } => {
+                let mut rgba = to_rgb(&frame.data, true);
+                let mut gif_frame = gif::Frame::from_rgba_speed(
+                    frame.width as u16,
+                    frame.height as u16,
+                    &mut rgba,
+                    10,
+                );
+                // GIF delays are in hundredths of a second, which is too coarse to represent some
+                // frame rates exactly
+                gif_frame.delay = (100 * intervals / *fps).max(1) as u16;
+
+                encoder.write_frame(&gif_frame)?;
+            }
+            Self::Apng {
+                frames,
+                frame_intervals,
+                frame_size,
+                ..
+            } => {
+                let size = *frame_size.get_or_insert((frame.width, frame.height));
+                if size != (frame.width, frame.height) {
+                    tracing::warn!(
+                        "Skipping a {}x{} frame in a {}x{} recording",
+                        frame.width,
+                        frame.height,
+                        size.0,
+                        size.1
+                    );
+                    return Ok(());
+                }
+
+                frames.write_all(&to_rgb(&frame.data, false))?;
+                frame_intervals.push(intervals);
+            }
+            // ffmpeg expects frames at a constant rate, so frames that are shown for longer get
+            // repeated
+            Self::WebM { stdin, .. } => {
+                for _ in 0..intervals {
+                    stdin.write_all(&frame.data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            Self::Gif { encoder, .. } => {
+                encoder.into_inner()?.flush()?;
+            }
+            Self::Apng {
+                file,
+                frames,
+                frame_intervals,
+                frame_size,
+                fps,
+            } => {
+                let Some((width, height)) = frame_size else {
+                    return Ok(());
+                };
+
+                let mut frames = frames
+                    .into_inner()
+                    .map_err(io::IntoInnerError::into_error)?;
+                frames.seek(SeekFrom::Start(0))?;
+                let mut frames = BufReader::new(frames);
+
+                let mut encoder = png::Encoder::new(file, width, height);
+                encoder.set_color(png::ColorType::Rgb);
+                encoder.set_depth(png::BitDepth::Eight);
+                encoder.set_animated(frame_intervals.len() as u32, 0)?;
+
+                let mut writer = encoder.write_header()?;
+                let mut frame = vec![0; 3 * width as usize * height as usize];
+                for intervals in frame_intervals {
+                    frames.read_exact(&mut frame)?;
+                    writer.set_frame_delay(intervals as u16, fps as u16)?;
+                    writer.write_image_data(&frame)?;
+                }
+                writer.finish()?;
+            }
+            Self::WebM { mut ffmpeg, stdin } => {
+                // ffmpeg only finishes once its stdin is closed
+                drop(stdin.into_inner().map_err(io::IntoInnerError::into_error)?);
+
+                let status = ffmpeg.wait()?;
+                if !status.success() {
+                    return Err(Error::Ffmpeg(status));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Converts pixels stored in the layout of `cairo::Format::Rgb24` to RGB(A), with the alpha channel
+/// (which is always opaque) only being present if `with_alpha` is true
+fn to_rgb(data: &[u8], with_alpha: bool) -> Vec<u8> {
+    let channels = if with_alpha { 4 } else { 3 };
+    let mut rgb = Vec::with_capacity(data.len() / 4 * channels);
+
+    // Cairo stores pixels as native-endian u32s, so on little-endian machines this is BGRX
+    for pixel in data.chunks_exact(4) {
+        rgb.extend_from_slice(&[pixel[2], pixel[1], pixel[0]]);
+        if with_alpha {
+            rgb.push(0xff);
+        }
+    }
+
+    rgb
+}
+
+/// Checks whether ffmpeg is installed and can encode VP9, which is what we put inside WebM files.
+///
+/// This runs ffmpeg the first time it's called, see [`Recording::probe_encoders`].
+fn can_encode_webm() -> bool {
+    *CAN_ENCODE_WEBM.get_or_init(|| {
+        Command::new("ffmpeg")
+            .args(["-hide_banner", "-encoders"])
+            .stderr(Stdio::null())
+            .output()
+            .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains("libvpx-vp9"))
+    })
+}
@@ -0,0 +1,320 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, Builder as ThreadBuilder},
+    time::Duration,
+};
+
+use cairo::{Format as CairoImageFormat, ImageSurface};
+use kcshot_data::{geometry::Rectangle, settings::Settings};
+use kcshot_screenshot::{Frame, Recorder};
+use tokio::sync::oneshot;
+
+/// How long we wait after scrolling for the application to redraw itself
+const SCROLL_SETTLE_TIME: Duration = Duration::from_millis(300);
+/// How often frames are captured when the user does the scrolling
+const MANUAL_SCROLL_INTERVAL: Duration = Duration::from_millis(150);
+/// How many scrolls in a row have to leave the region unchanged before we assume the bottom of the
+/// page was reached
+const MAX_UNCHANGED_SCROLLS: u32 = 3;
+/// Cairo can't create image surfaces that are taller than this
+const MAX_HEIGHT: u32 = 32767;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Failed to capture a frame: {0}")]
+    Capture(#[from] kcshot_screenshot::Error),
+    #[error("Encountered an I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Encountered an error from cairo: {0}")]
+    Cairo(#[from] cairo::Error),
+    #[error("The region to capture is empty")]
+    EmptyRegion,
+    #[error("The scrolling capture thread went away before finishing the capture")]
+    ThreadWentAway,
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A capture of a region whose contents get scrolled, be it by us or by the user, that stitches all
+/// the frames it sees into a single tall image.
+pub struct ScrollingCapture {
+    stop: Arc<AtomicBool>,
+}
+
+impl ScrollingCapture {
+    /// Starts capturing `region` (in the coordinate space of the virtual screen) in the background.
+    ///
+    /// If the `scrolling-capture-injects-scrolls` setting is on, the region is scrolled down until
+    /// its contents stop changing, otherwise the capture goes on until [`Self::stop`] is called. The
+    /// returned receiver gets the stitched image once the capture is over.
+    pub fn start(region: Rectangle) -> Result<(Self, oneshot::Receiver<Result<Frame>>)> {
+        let normalised = region.normalised();
+        if normalised.w < 1.0 || normalised.h < 1.0 {
+            return Err(Error::EmptyRegion);
+        }
+
+        // We move the cursor into the region to scroll it, and if it were drawn onto the frames the
+        // rows it covers would differ between them, which makes lining them up harder
+        let mut recorder = Recorder::new(region)?.without_cursor();
+        let injects_scrolls = Settings::open().scrolling_capture_injects_scrolls();
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let (finished_tx, finished_rx) = oneshot::channel();
+
+        ThreadBuilder::new()
+            .name("scrolling capture thread".into())
+            .spawn({
+                let stop = stop.clone();
+                move || {
+                    let res = capture(&mut recorder, injects_scrolls, &stop);
+                    // If nobody is waiting for the capture to finish anymore, there's nobody to
+                    // tell about it either
+                    let _ = finished_tx.send(res);
+                }
+            })?;
+
+        Ok((Self { stop }, finished_rx))
+    }
+
+    /// Stops capturing new frames, the frames that were captured so far still get stitched together
+    pub fn stop(&self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+fn capture(recorder: &mut Recorder, injects_scrolls: bool, stop: &AtomicBool) -> Result<Frame> {
+    let mut stitcher = Stitcher::new(recorder.capture_frame()?);
+    let mut unchanged_scrolls = 0;
+
+    while !stop.load(Ordering::Relaxed) && stitcher.height < MAX_HEIGHT {
+        if injects_scrolls {
+            recorder.scroll_down()?;
+            thread::sleep(SCROLL_SETTLE_TIME);
+        } else {
+            thread::sleep(MANUAL_SCROLL_INTERVAL);
+        }
+
+        let moved = stitcher.push(recorder.capture_frame()?);
+
+        if moved {
+            unchanged_scrolls = 0;
+        } else if injects_scrolls {
+            unchanged_scrolls += 1;
+            if unchanged_scrolls == MAX_UNCHANGED_SCROLLS {
+                break;
+            }
+        }
+    }
+
+    Ok(stitcher.finish())
+}
+
+/// Turns a frame into an image surface that can be shown in the editor
+pub fn into_surface(frame: Frame) -> Result<ImageSurface> {
+    let stride = CairoImageFormat::Rgb24.stride_for_width(frame.width)?;
+
+    Ok(ImageSurface::create_for_data(
+        frame.data,
+        CairoImageFormat::Rgb24,
+        frame.width as i32,
+        frame.height as i32,
+        stride,
+    )?)
+}
+
+/// Glues frames of a region that is being scrolled down together, by finding how far the contents
+/// moved between consecutive frames and only appending the rows that came into view.
+struct Stitcher {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+    /// The hashes of the rows of the last frame that was stitched in
+    last_rows: Vec<u64>,
+}
+
+impl Stitcher {
+    fn new(frame: Frame) -> Self {
+        Self {
+            width: frame.width,
+            height: frame.height,
+            last_rows: row_hashes(&frame),
+            data: frame.data,
+        }
+    }
+
+    /// Appends the part of `frame` that wasn't visible in the previous one, returns false if the
+    /// contents didn't move at all or if the frame couldn't be lined up with the previous one
+    fn push(&mut self, frame: Frame) -> bool {
+        let rows = row_hashes(&frame);
+
+        let offset = match scroll_offset(&self.last_rows, &rows) {
+            Some(0) => return false,
+            Some(offset) => offset,
+            None => {
+                // The contents were scrolled by more than a frame or changed for some other reason,
+                // e.g. an animation. Appending the frame would most likely duplicate what we
+                // already have, so we skip it and keep lining up with the last frame we used.
+                tracing::info!("Skipping a frame of a scrolling capture that doesn't overlap");
+                return false;
+            }
+        };
+
+        let row_len = 4 * self.width as usize;
+        let new_rows = offset.min((MAX_HEIGHT - self.height) as usize);
+        let first_new_row = rows.len() - offset;
+
+        self.data.extend_from_slice(
+            &frame.data[first_new_row * row_len..(first_new_row + new_rows) * row_len],
+        );
+        self.height += new_rows as u32;
+        self.last_rows = rows;
+
+        true
+    }
+
+    fn finish(self) -> Frame {
+        Frame {
+            width: self.width,
+            height: self.height,
+            data: self.data,
+        }
+    }
+}
+
+fn row_hashes(frame: &Frame) -> Vec<u64> {
+    frame
+        .data
+        .chunks_exact(4 * frame.width as usize)
+        .map(|row| {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+/// Returns by how many rows the contents moved up from `previous` to `next`, i.e. the smallest
+/// offset for which (almost) all the rows of `next` line up with the ones of `previous`.
+///
+/// A few mismatched rows are tolerated, so that things like blinking carets or small sticky headers
+/// don't throw us off.
+fn scroll_offset(previous: &[u64], next: &[u64]) -> Option<usize> {
+    let height = previous.len().min(next.len());
+    if height == 0 {
+        return None;
+    }
+
+    // Small overlaps match by accident too easily, e.g. when both frames have blank rows at the edge
+    let min_overlap = (height / 8).max(1);
+
+    (0..=height - min_overlap).find(|&offset| {
+        let overlap = height - offset;
+        let mismatches = previous[offset..height]
+            .iter()
+            .zip(&next[..overlap])
+            .filter(|(previous, next)| previous != next)
+            .count();
+
+        mismatches <= overlap / 20
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use kcshot_screenshot::Frame;
+
+    use super::{Stitcher, row_hashes, scroll_offset};
+
+    /// Creates a frame whose rows are filled with the bytes in `rows`
+    fn frame(width: u32, rows: &[u8]) -> Frame {
+        Frame {
+            width,
+            height: rows.len() as u32,
+            data: rows
+                .iter()
+                .flat_map(|&row| vec![row; 4 * width as usize])
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn row_hashes_hash_every_row() {
+        let hashes = row_hashes(&frame(3, &[1, 2, 1]));
+
+        assert_eq!(hashes.len(), 3);
+        assert_eq!(hashes[0], hashes[2]);
+        assert_ne!(hashes[0], hashes[1]);
+    }
+
+    #[test]
+    fn scroll_offset_finds_exact_overlap() {
+        let previous = (0..16).collect::<Vec<u64>>();
+        let next = (4..20).collect::<Vec<u64>>();
+
+        assert_eq!(scroll_offset(&previous, &next), Some(4));
+    }
+
+    #[test]
+    fn scroll_offset_is_zero_for_unchanged_frames() {
+        let rows = (0..16).collect::<Vec<u64>>();
+
+        assert_eq!(scroll_offset(&rows, &rows), Some(0));
+    }
+
+    #[test]
+    fn scroll_offset_finds_no_overlap() {
+        let previous = (0..16).collect::<Vec<u64>>();
+        let next = (100..116).collect::<Vec<u64>>();
+
+        assert_eq!(scroll_offset(&previous, &next), None);
+    }
+
+    #[test]
+    fn scroll_offset_tolerates_a_few_mismatched_rows() {
+        let previous = (0..48).collect::<Vec<u64>>();
+
+        // With an overlap of 40 rows, up to 2 of them may differ
+        let mut next = (8..56).collect::<Vec<u64>>();
+        next[0] = 1000;
+        next[20] = 1001;
+        assert_eq!(scroll_offset(&previous, &next), Some(8));
+
+        next[30] = 1002;
+        assert_eq!(scroll_offset(&previous, &next), None);
+    }
+
+    #[test]
+    fn scroll_offset_handles_empty_frames() {
+        assert_eq!(scroll_offset(&[], &[]), None);
+        assert_eq!(scroll_offset(&[1, 2, 3], &[]), None);
+    }
+
+    #[test]
+    fn stitcher_appends_rows_that_came_into_view() {
+        let page = (0..12).collect::<Vec<u8>>();
+
+        let mut stitcher = Stitcher::new(frame(2, &page[0..8]));
+        assert!(stitcher.push(frame(2, &page[3..11])));
+        assert!(!stitcher.push(frame(2, &page[3..11])));
+
+        let stitched = stitcher.finish();
+        assert_eq!(stitched.height, 11);
+        assert_eq!(stitched.data, frame(2, &page[0..11]).data);
+    }
+
+    #[test]
+    fn stitcher_skips_frames_without_overlap() {
+        let mut stitcher = Stitcher::new(frame(2, &[1, 2, 3, 4]));
+        assert!(!stitcher.push(frame(2, &[5, 6, 7, 8])));
+        // Frames are still lined up with the last one that was used
+        assert!(stitcher.push(frame(2, &[3, 4, 9, 10])));
+
+        let stitched = stitcher.finish();
+        assert_eq!(stitched.height, 6);
+        assert_eq!(stitched.data, frame(2, &[1, 2, 3, 4, 9, 10]).data);
+    }
+}
@@ -17,10 +17,13 @@ mod underlying {
     use std::cell::OnceCell;
 
     use gtk4::{CompositeTemplate, glib, prelude::*, subclass::prelude::*};
-    use kcshot_data::settings::Settings;
+    use kcshot_data::{geometry::Rectangle, settings::Settings};
 
     use crate::ext::DisposeExt;
 
+    /// The values of the `recording-format` setting, in the order they're shown in the dropdown
+    const RECORDING_FORMATS: [&str; 3] = ["gif", "apng", "webm"];
+
     #[derive(Debug, Default, CompositeTemplate)]
     #[template(file = "src/settings_window.blp")]
     pub struct SettingsWindow {
@@ -31,7 +34,27 @@ mod underlying {
         #[template_child]
         capture_mouse_switch: TemplateChild<gtk4::Switch>,
         #[template_child]
+        capture_all_monitors_switch: TemplateChild<gtk4::Switch>,
+        #[template_child]
+        capture_delay_spinner: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        recording_fps_spinner: TemplateChild<gtk4::SpinButton>,
+        #[template_child]
+        recording_format_dropdown: TemplateChild<gtk4::DropDown>,
+        #[template_child]
+        scrolling_capture_injects_scrolls_switch: TemplateChild<gtk4::Switch>,
+        #[template_child]
+        active_window_includes_decorations_switch: TemplateChild<gtk4::Switch>,
+        #[template_child]
         editing_starts_by_cropping_switch: TemplateChild<gtk4::Switch>,
+        #[template_child]
+        last_region_label: TemplateChild<gtk4::Label>,
+        #[template_child]
+        preset_name_entry: TemplateChild<gtk4::Entry>,
+        #[template_child]
+        save_preset_button: TemplateChild<gtk4::Button>,
+        #[template_child]
+        region_presets_list: TemplateChild<gtk4::ListBox>,
 
         settings: OnceCell<Settings>,
     }
@@ -73,12 +96,63 @@ mod underlying {
             settings
                 .bind_capture_mouse_cursor(&self.capture_mouse_switch.get(), "active")
                 .build();
+            settings
+                .bind_capture_all_monitors(&self.capture_all_monitors_switch.get(), "active")
+                .build();
+            settings
+                .bind_capture_delay(&self.capture_delay_spinner.get(), "value")
+                .build();
+            settings
+                .bind_recording_fps(&self.recording_fps_spinner.get(), "value")
+                .build();
+
+            // The dropdown shows friendlier names than the ones stored in the settings, so it can't
+            // be bound directly
+            let format = settings.recording_format();
+            let selected = RECORDING_FORMATS
+                .iter()
+                .position(|&known| known == format)
+                .unwrap_or(0);
+            self.recording_format_dropdown.set_selected(selected as u32);
+            self.recording_format_dropdown
+                .connect_selected_notify(|dropdown| {
+                    if let Some(format) = RECORDING_FORMATS.get(dropdown.selected() as usize) {
+                        Settings::open().set_recording_format(format);
+                    }
+                });
+
+            settings
+                .bind_scrolling_capture_injects_scrolls(
+                    &self.scrolling_capture_injects_scrolls_switch.get(),
+                    "active",
+                )
+                .build();
+            settings
+                .bind_active_window_includes_decorations(
+                    &self.active_window_includes_decorations_switch.get(),
+                    "active",
+                )
+                .build();
             settings
                 .bind_editing_starts_with_cropping(
                     &self.editing_starts_by_cropping_switch.get(),
                     "active",
                 )
                 .build();
+
+            self.show_last_region(settings);
+            settings.connect_last_region_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |settings| this.show_last_region(settings)
+            ));
+
+            self.show_region_presets(settings);
+            settings.connect_region_presets_changed(glib::clone!(
+                #[weak(rename_to = this)]
+                self,
+                move |settings| this.show_region_presets(settings)
+            ));
         }
 
         fn dispose(&self) {
@@ -86,8 +160,78 @@ mod underlying {
         }
     }
 
+    impl SettingsWindow {
+        fn show_last_region(&self, settings: &Settings) {
+            let region = settings.last_region();
+            let has_region = region.area() >= 1.0;
+
+            self.last_region_label.set_label(&if has_region {
+                format_region(region)
+            } else {
+                "None yet".into()
+            });
+            self.save_preset_button.set_sensitive(has_region);
+        }
+
+        fn show_region_presets(&self, settings: &Settings) {
+            self.region_presets_list.remove_all();
+
+            for (name, region) in settings.region_presets() {
+                let row = gtk4::Box::new(gtk4::Orientation::Horizontal, 6);
+
+                let name_label = gtk4::Label::new(Some(&name));
+                name_label.set_halign(gtk4::Align::Start);
+                name_label.set_hexpand(true);
+                row.append(&name_label);
+
+                row.append(&gtk4::Label::new(Some(&format_region(region))));
+
+                let delete_button = gtk4::Button::from_icon_name("edit-delete-symbolic");
+                delete_button.set_tooltip_text(Some("Delete preset"));
+                delete_button.connect_clicked(move |_| {
+                    let settings = Settings::open();
+                    let mut presets = settings.region_presets();
+                    presets.remove(&name);
+                    if let Err(why) = settings.try_set_region_presets(presets) {
+                        tracing::error!("Failed to delete region preset '{name}': {why}");
+                    }
+                });
+                row.append(&delete_button);
+
+                self.region_presets_list.append(&row);
+            }
+        }
+    }
+
+    /// Formats `region` like X11 geometry strings, i.e. `WxH+X+Y`
+    fn format_region(Rectangle { x, y, w, h }: Rectangle) -> String {
+        format!("{w}x{h}+{x}+{y}")
+    }
+
     #[gtk4::template_callbacks]
     impl SettingsWindow {
+        #[template_callback]
+        fn on_save_preset(&self) {
+            let name = self.preset_name_entry.text();
+            let name = name.trim();
+            if name.is_empty() {
+                return;
+            }
+
+            let settings = self.settings.get_or_init(Settings::open);
+            let region = settings.last_region();
+            if region.area() < 1.0 {
+                return;
+            }
+
+            let mut presets = settings.region_presets();
+            presets.insert(name.to_owned(), region);
+            match settings.try_set_region_presets(presets) {
+                Ok(()) => self.preset_name_entry.set_text(""),
+                Err(why) => tracing::error!("Failed to save region preset '{name}': {why}"),
+            }
+        }
+
         #[template_callback]
         fn on_screenshot_directory_clicked(&self, _: gtk4::Button) {
             let folder_chooser = gtk4::FileChooserDialog::new(
@@ -20,6 +20,35 @@ pub fn init(app: &KCShot) {
     );
 }
 
+/// Returns whether a systray icon is currently being shown
+pub fn is_shown() -> bool {
+    sni::is_shown()
+}
+
+/// Shows how many seconds are left until a delayed screenshot gets taken on the systray icon, or
+/// goes back to the regular icon if `seconds_left` is `None`.
+///
+/// This does nothing if there is no systray icon.
+pub fn set_countdown(seconds_left: Option<u32>) {
+    sni::set_countdown(seconds_left);
+}
+
+/// Switches the systray menu between offering to start a recording and to stop the one that is
+/// in progress.
+///
+/// This does nothing if there is no systray icon.
+pub fn set_recording(is_recording: bool) {
+    sni::set_recording(is_recording);
+}
+
+/// Switches the systray menu between offering to start a scrolling capture and to stop the one that
+/// is in progress.
+///
+/// This does nothing if there is no systray icon.
+pub fn set_scrolling_capture(is_running: bool) {
+    sni::set_scrolling_capture(is_running);
+}
+
 #[derive(PartialEq, Eq)]
 enum Initialised {
     Yes,
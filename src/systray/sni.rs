@@ -1,11 +1,20 @@
-use std::{process::Command, thread::Builder as ThreadBuilder};
+use std::{
+    process::Command,
+    sync::{
+        OnceLock,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::Builder as ThreadBuilder,
+};
 
 use gtk4::{
+    cairo,
     gdk_pixbuf::Pixbuf,
     glib::{self},
     prelude::*,
 };
 use kcshot_data::settings::Settings;
+use kcshot_screenshot::CaptureTarget;
 use ksni::TrayMethods;
 use tokio::{
     runtime::Builder as RtBuilder,
@@ -15,6 +24,12 @@ use tokio::{
 use super::Initialised;
 use crate::{editor::EditorWindow, kcshot::KCShot};
 
+/// Used to tell the tray thread about changes to what it should show
+static UPDATE_TX: OnceLock<Sender<Update>> = OnceLock::new();
+
+/// Whether the tray service is running, i.e. whether a status notifier host accepted our icon
+static IS_SHOWN: AtomicBool = AtomicBool::new(false);
+
 /// Attempts to create a systray icon using the [KDE/freedesktop StatusNotifierItem spec][`kde_sni`].
 /// This is done by using the [ksni][`ksni`] crate.
 ///
@@ -37,7 +52,17 @@ pub(super) fn try_init(app: KCShot) -> Initialised {
     // and an async task running on the main thread to invoke GTK stuff from the SNI thread
     let (tx, mut rx) = mpsc::channel(16);
 
-    let tray_service = Tray { tx, icon };
+    let (update_tx, mut update_rx) = mpsc::channel(4);
+
+    let settings = Settings::open();
+    let tray_service = Tray {
+        tx,
+        icon,
+        countdown: None,
+        is_recording: false,
+        is_scrolling_capture_running: false,
+        region_presets: region_preset_names(&settings),
+    };
 
     // We make a new thread ourselves so we can give it a more descriptive name :^)
     let res = ThreadBuilder::new()
@@ -49,10 +74,31 @@ pub(super) fn try_init(app: KCShot) -> Initialised {
                 .unwrap();
 
             rt.block_on(async {
-                if let Err(why) = tray_service.spawn().await {
-                    tracing::warn!("Failed to run SNI tray service. This is not fatal: {why}");
+                let handle = match tray_service.spawn().await {
+                    Ok(handle) => {
+                        IS_SHOWN.store(true, Ordering::Relaxed);
+                        handle
+                    }
+                    Err(why) => {
+                        tracing::warn!("Failed to run SNI tray service. This is not fatal: {why}");
+                        return;
+                    }
+                };
+
+                while let Some(update) = update_rx.recv().await {
+                    handle
+                        .update(|tray: &mut Tray| match update {
+                            Update::Countdown(seconds_left) => tray.countdown = seconds_left,
+                            Update::Recording(is_recording) => tray.is_recording = is_recording,
+                            Update::ScrollingCapture(is_running) => {
+                                tray.is_scrolling_capture_running = is_running;
+                            }
+                            Update::RegionPresets(names) => tray.region_presets = names,
+                        })
+                        .await;
                 }
 
+                // The tray service stops running once the runtime goes away, so keep it alive
                 std::future::pending::<()>().await;
             });
         });
@@ -62,7 +108,17 @@ pub(super) fn try_init(app: KCShot) -> Initialised {
         return Initialised::No;
     }
 
+    // try_init only gets called once, so this can't fail
+    let _ = UPDATE_TX.set(update_tx);
+
+    settings.connect_region_presets_changed(|settings| {
+        send_update(Update::RegionPresets(region_preset_names(settings)));
+    });
+
     glib::MainContext::default().spawn_local(async move {
+        // The handler above only runs for as long as `settings` is alive
+        let _settings = &settings;
+
         while let Some(msg) = rx.recv().await {
             match msg {
                 Message::OpenMainWindow => app.main_window().present(),
@@ -78,8 +134,45 @@ pub(super) fn try_init(app: KCShot) -> Initialised {
                     let editing_starts_with_cropping =
                         Settings::open().editing_starts_with_cropping();
 
-                    EditorWindow::show(app.upcast_ref(), editing_starts_with_cropping);
+                    EditorWindow::show(
+                        app.upcast_ref(),
+                        editing_starts_with_cropping,
+                        &CaptureTarget::from_settings(),
+                    );
+                }
+                Message::CaptureActiveWindow => {
+                    app.activate_action("capture-active-window", None);
+                }
+                Message::CaptureLastRegion => {
+                    app.activate_action("capture-last-region", None);
+                }
+                Message::CaptureRegionPreset(preset) => {
+                    app.activate_action("capture-region-preset", Some(&preset.to_variant()));
+                }
+                Message::TakeDelayedScreenshot => {
+                    let settings = Settings::open();
+
+                    EditorWindow::show_after_delay(
+                        app.upcast_ref(),
+                        settings.editing_starts_with_cropping(),
+                        CaptureTarget::from_settings(),
+                        settings.capture_delay(),
+                    );
+                }
+                Message::StartRecording => {
+                    EditorWindow::show_for_recording(
+                        app.upcast_ref(),
+                        &CaptureTarget::from_settings(),
+                    );
+                }
+                Message::StopRecording => app.stop_recording(),
+                Message::StartScrollingCapture => {
+                    EditorWindow::show_for_scrolling_capture(
+                        app.upcast_ref(),
+                        &CaptureTarget::from_settings(),
+                    );
                 }
+                Message::StopScrollingCapture => app.stop_scrolling_capture(),
                 Message::Quit => app.quit(),
             }
         }
@@ -93,9 +186,56 @@ enum Message {
     OpenMainWindow,
     OpenScreenshotFolder,
     TakeScreenshot,
+    CaptureActiveWindow,
+    CaptureLastRegion,
+    CaptureRegionPreset(String),
+    TakeDelayedScreenshot,
+    StartRecording,
+    StopRecording,
+    StartScrollingCapture,
+    StopScrollingCapture,
     Quit,
 }
 
+/// Changes to the state of the tray that are sent from the main thread
+#[derive(Debug)]
+enum Update {
+    Countdown(Option<u32>),
+    Recording(bool),
+    ScrollingCapture(bool),
+    RegionPresets(Vec<String>),
+}
+
+pub(super) fn is_shown() -> bool {
+    IS_SHOWN.load(Ordering::Relaxed)
+}
+
+pub(super) fn set_countdown(seconds_left: Option<u32>) {
+    send_update(Update::Countdown(seconds_left));
+}
+
+pub(super) fn set_recording(is_recording: bool) {
+    send_update(Update::Recording(is_recording));
+}
+
+pub(super) fn set_scrolling_capture(is_running: bool) {
+    send_update(Update::ScrollingCapture(is_running));
+}
+
+fn region_preset_names(settings: &Settings) -> Vec<String> {
+    settings.region_presets().into_keys().collect()
+}
+
+fn send_update(update: Update) {
+    let Some(tx) = UPDATE_TX.get() else {
+        return;
+    };
+
+    if let Err(why) = tx.try_send(update) {
+        tracing::error!("Failed to send update to the tray thread: {why:?}");
+    }
+}
+
 fn load_icon() -> Result<ksni::Icon, glib::Error> {
     const ICON_BYTES: &[u8] = include_bytes!("../../resources/logo/tray.png");
 
@@ -118,10 +258,68 @@ fn load_icon() -> Result<ksni::Icon, glib::Error> {
     })
 }
 
+/// Draws `seconds_left` inside a circle, to be shown instead of our logo while a delayed screenshot
+/// is pending.
+fn countdown_icon(seconds_left: u32) -> Result<ksni::Icon, cairo::Error> {
+    const SIZE: i32 = 32;
+
+    let mut surface = cairo::ImageSurface::create(cairo::Format::ARgb32, SIZE, SIZE)?;
+    {
+        let cairo = cairo::Context::new(&surface)?;
+        let size = SIZE as f64;
+
+        cairo.arc(
+            size / 2.0,
+            size / 2.0,
+            size / 2.0,
+            0.0,
+            2.0 * std::f64::consts::PI,
+        );
+        cairo.set_source_rgb(0.0, 127.0 / 255.0, 190.0 / 255.0);
+        cairo.fill()?;
+
+        let text = seconds_left.to_string();
+        cairo.select_font_face(
+            "sans-serif",
+            cairo::FontSlant::Normal,
+            cairo::FontWeight::Bold,
+        );
+        cairo.set_font_size(if text.len() > 2 { 12.0 } else { 18.0 });
+        let extents = cairo.text_extents(&text)?;
+        cairo.move_to(
+            size / 2.0 - extents.width() / 2.0 - extents.x_bearing(),
+            size / 2.0 - extents.height() / 2.0 - extents.y_bearing(),
+        );
+        cairo.set_source_rgb(1.0, 1.0, 1.0);
+        cairo.show_text(&text)?;
+    }
+    surface.flush();
+
+    let data = surface
+        .data()
+        .map_err(|_| cairo::Error::SurfaceFinished)?
+        // cairo stores pixels as native-endian u32s, while SNI wants ARGB in network byte order
+        .chunks_exact(4)
+        .flat_map(|pixel| u32::from_ne_bytes(pixel.try_into().unwrap()).to_be_bytes())
+        .collect();
+
+    Ok(ksni::Icon {
+        width: SIZE,
+        height: SIZE,
+        data,
+    })
+}
+
 #[derive(Debug)]
 struct Tray {
     tx: Sender<Message>,
     icon: ksni::Icon,
+    /// How many seconds are left until a delayed screenshot is taken, if one is pending
+    countdown: Option<u32>,
+    is_recording: bool,
+    is_scrolling_capture_running: bool,
+    /// The names of the region presets saved in the settings
+    region_presets: Vec<String>,
 }
 
 impl ksni::Tray for Tray {
@@ -136,7 +334,17 @@ impl ksni::Tray for Tray {
     }
 
     fn icon_pixmap(&self) -> Vec<ksni::Icon> {
-        vec![self.icon.clone()]
+        let Some(seconds_left) = self.countdown else {
+            return vec![self.icon.clone()];
+        };
+
+        match countdown_icon(seconds_left) {
+            Ok(icon) => vec![icon],
+            Err(why) => {
+                tracing::error!("Failed to draw countdown icon: {why}");
+                vec![self.icon.clone()]
+            }
+        }
     }
 
     fn menu(&self) -> Vec<ksni::MenuItem<Self>> {
@@ -152,6 +360,109 @@ impl ksni::Tray for Tray {
                 ..Default::default()
             }
             .into(),
+            StandardItem {
+                label: "Capture active window".into(),
+                activate: Box::new(|tray: &mut Self| {
+                    if let Err(why) = tray.tx.try_send(Message::CaptureActiveWindow) {
+                        tracing::error!("Failed to send message: {why:?}");
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: "Capture last region".into(),
+                activate: Box::new(|tray: &mut Self| {
+                    if let Err(why) = tray.tx.try_send(Message::CaptureLastRegion) {
+                        tracing::error!("Failed to send message: {why:?}");
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
+            SubMenu {
+                label: "Capture region preset".into(),
+                enabled: !self.region_presets.is_empty(),
+                submenu: self
+                    .region_presets
+                    .iter()
+                    .map(|preset| {
+                        let preset = preset.clone();
+                        StandardItem {
+                            label: preset.clone(),
+                            activate: Box::new(move |tray: &mut Self| {
+                                let message = Message::CaptureRegionPreset(preset.clone());
+                                if let Err(why) = tray.tx.try_send(message) {
+                                    tracing::error!("Failed to send message: {why:?}");
+                                }
+                            }),
+                            ..Default::default()
+                        }
+                        .into()
+                    })
+                    .collect(),
+                ..Default::default()
+            }
+            .into(),
+            StandardItem {
+                label: "Take delayed screenshot".into(),
+                activate: Box::new(|tray: &mut Self| {
+                    if let Err(why) = tray.tx.try_send(Message::TakeDelayedScreenshot) {
+                        tracing::error!("Failed to send message: {why:?}");
+                    }
+                }),
+                ..Default::default()
+            }
+            .into(),
+            if self.is_recording {
+                StandardItem {
+                    label: "Stop recording".into(),
+                    icon_name: "media-playback-stop".into(),
+                    activate: Box::new(|tray: &mut Self| {
+                        if let Err(why) = tray.tx.try_send(Message::StopRecording) {
+                            tracing::error!("Failed to send message: {why:?}");
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            } else {
+                StandardItem {
+                    label: "Start recording".into(),
+                    icon_name: "media-record".into(),
+                    activate: Box::new(|tray: &mut Self| {
+                        if let Err(why) = tray.tx.try_send(Message::StartRecording) {
+                            tracing::error!("Failed to send message: {why:?}");
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            },
+            if self.is_scrolling_capture_running {
+                StandardItem {
+                    label: "Stop scrolling capture".into(),
+                    icon_name: "media-playback-stop".into(),
+                    activate: Box::new(|tray: &mut Self| {
+                        if let Err(why) = tray.tx.try_send(Message::StopScrollingCapture) {
+                            tracing::error!("Failed to send message: {why:?}");
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            } else {
+                StandardItem {
+                    label: "Scrolling capture".into(),
+                    activate: Box::new(|tray: &mut Self| {
+                        if let Err(why) = tray.tx.try_send(Message::StartScrollingCapture) {
+                            tracing::error!("Failed to send message: {why:?}");
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into()
+            },
             StandardItem {
                 label: "Open screenshot folder".into(),
                 activate: Box::new(|tray: &mut Self| {
@@ -180,7 +491,14 @@ impl ksni::Tray for Tray {
         ksni::ToolTip {
             icon_name: String::new(),
             icon_pixmap: vec![],
-            title: "kcshot".into(),
+            title: match self.countdown {
+                Some(seconds_left) => format!("kcshot - taking a screenshot in {seconds_left}s"),
+                None if self.is_recording => "kcshot - recording".into(),
+                None if self.is_scrolling_capture_running => {
+                    "kcshot - taking a scrolling capture".into()
+                }
+                None => "kcshot".into(),
+            },
             description: String::new(),
         }
     }